@@ -0,0 +1,17 @@
+/// With the ffi feature, writes the C header for `src/ffi.rs` to include/chip8.h
+fn main() {
+    #[cfg(feature = "ffi")]
+    {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+        let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        std::fs::create_dir_all(format!("{}/include", crate_dir)).expect("Failed to create include/");
+        let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", crate_dir)).expect("cbindgen.toml is invalid");
+        cbindgen::Builder::new()
+            .with_crate(&crate_dir)
+            .with_config(config)
+            .generate()
+            .expect("Failed to generate the C header")
+            .write_to_file(format!("{}/include/chip8.h", crate_dir));
+    }
+}
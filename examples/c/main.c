@@ -0,0 +1,57 @@
+/* Runs a ROM for a second of emulated time and prints the display, linking against the library built with the ffi feature.
+ * Build and run with `make run ROM=../../roms/IBM\ Logo.ch8` from this directory. */
+#include <stdio.h>
+#include <stdlib.h>
+#include "chip8.h"
+
+#define MAX_WIDTH 128
+#define MAX_HEIGHT 64
+
+static unsigned char *read_file(const char *path, size_t *len) {
+    FILE *file = fopen(path, "rb");
+    if (!file) {
+        return NULL;
+    }
+    unsigned char *data = malloc(4096);
+    *len = data ? fread(data, 1, 4096, file) : 0;
+    fclose(file);
+    return data;
+}
+
+int main(int argc, char **argv) {
+    if (argc != 2) {
+        fprintf(stderr, "usage: %s ROM\n", argv[0]);
+        return 2;
+    }
+    size_t len;
+    unsigned char *rom = read_file(argv[1], &len);
+    if (!rom) {
+        fprintf(stderr, "Failed to read %s\n", argv[1]);
+        return 1;
+    }
+    chip8_t *chip = chip8_new();
+    if (!chip || chip8_load_rom(chip, rom, len) != CHIP8_STATUS_OK) {
+        fprintf(stderr, "Failed to load %s\n", argv[1]);
+        return 1;
+    }
+    free(rom);
+    for (int frame = 0; frame < 60; frame++) {
+        Chip8Status status = chip8_run_frame(chip, 15);
+        if (status != CHIP8_STATUS_OK) {
+            fprintf(stderr, "Emulation stopped with status %d\n", status);
+            break;
+        }
+    }
+    static unsigned char pixels[MAX_WIDTH * MAX_HEIGHT];
+    if (chip8_framebuffer(chip, pixels, sizeof pixels) == CHIP8_STATUS_OK) {
+        size_t width = chip8_width(chip), height = chip8_height(chip);
+        for (size_t y = 0; y < height; y++) {
+            for (size_t x = 0; x < width; x++) {
+                putchar(pixels[y * width + x] ? '#' : ' ');
+            }
+            putchar('\n');
+        }
+    }
+    chip8_free(chip);
+    return 0;
+}
@@ -0,0 +1,63 @@
+"""Runs with pytest after `pip install .` from the repository root."""
+from pathlib import Path
+
+import pytest
+
+from chip8_rs_dmfg import Chip8
+
+ROMS = Path(__file__).resolve().parents[2] / "roms"
+
+
+def ibm_logo():
+    chip = Chip8()
+    chip.load_rom((ROMS / "IBM Logo.ch8").read_bytes())
+    for _ in range(60):
+        chip.run_frame(15)
+    return chip
+
+
+def test_draws_the_logo():
+    chip = ibm_logo()
+    rows = chip.framebuffer()
+    assert len(rows) == chip.height == 32
+    assert all(len(row) == chip.width == 64 for row in rows)
+    assert any(any(row) for row in rows)
+
+
+def test_framebuffer_bytes_match_rows():
+    chip = ibm_logo()
+    pixels = chip.framebuffer_bytes()
+    rows = chip.framebuffer()
+    assert [pixel != 0 for pixel in pixels] == [pixel for row in rows for pixel in row]
+
+
+def test_registers_and_timers():
+    chip = Chip8()
+    # V3 = 0x2A, delay timer = V3, I = 0x123
+    chip.load_rom(bytes([0x63, 0x2A, 0xF3, 0x15, 0xA1, 0x23]))
+    for _ in range(3):
+        chip.tick()
+    assert chip.registers[3] == 0x2A
+    assert chip.delay_timer == 0x2A
+    assert chip.i == 0x123
+    assert chip.pc == 0x206
+
+
+def test_save_state_round_trips():
+    chip = ibm_logo()
+    state = chip.save_state()
+    before = chip.framebuffer()
+    # reloading the same ROM clears the screen, a state only loads back into the ROM it was saved from
+    chip.load_rom((ROMS / "IBM Logo.ch8").read_bytes())
+    assert chip.framebuffer() != before
+    chip.load_state(state)
+    assert chip.framebuffer() == before
+    assert chip.save_state() == state
+
+
+def test_rejects_bad_input():
+    chip = Chip8()
+    with pytest.raises(ValueError):
+        chip.key(16, True)
+    with pytest.raises(ValueError):
+        chip.load_state(b"not a save state")
@@ -0,0 +1,21 @@
+//! Runs a frame of a ROM with only `core` and `alloc`, if this builds for a target without `std` so does the emulator core.
+#![no_std]
+
+use chip8_rs_dmfg::chip8::{Chip8, FrameResult, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use chip8_rs_dmfg::chip8_error::Chip8Error;
+
+/// What a board with a 64x32 LCD would call once per vsync, `lcd` gets one byte per pixel
+pub fn run_frame(chip: &mut Chip8, lcd: &mut [u8; DISPLAY_WIDTH * DISPLAY_HEIGHT]) -> Result<FrameResult, Chip8Error> {
+    let frame = chip.run_frame(15)?;
+    if frame.display_changed {
+        lcd.iter_mut().enumerate().for_each(|(i, pixel)| *pixel = chip.pixel_color(i) as u8);
+    }
+    Ok(frame)
+}
+
+/// A machine seeded from the board, there is no entropy source to fall back on without `std`
+pub fn boot(rom: &[u8], seed: u64) -> Result<Chip8, Chip8Error> {
+    let mut chip = Chip8::builder().build()?.with_seed(seed);
+    chip.load(rom)?;
+    Ok(chip)
+}
@@ -0,0 +1,231 @@
+use alloc::collections::{BTreeMap, BTreeSet};
+use core::ops::Range;
+use alloc::vec;
+use alloc::vec::Vec;
+use crate::chip8_instruction_set::{Address, Instruction};
+
+/// Straight line run of code, only entered at `start` and only left after its last instruction
+pub struct BasicBlock {
+    pub start: Address,
+    pub instructions: Vec<(Address, Instruction)>,
+    /// Addresses execution can continue at, empty after a return, exit or analysis boundary
+    pub successors: Vec<Address>
+}
+
+impl BasicBlock {
+    /// Address just past the last instruction
+    pub fn end(&self) -> Address {
+        self.instructions.last().map_or(self.start, |(address, instruction)| address.wrapping_add(instruction.size() as Address))
+    }
+}
+
+/// Code reachable from the entry point of a program, split into basic blocks, and the bytes nothing was found to execute
+pub struct ControlFlowGraph {
+    pub origin: Address,
+    pub blocks: BTreeMap<Address, BasicBlock>,
+    /// Addresses jumped to, called or skipped to, the ones a listing needs labels for
+    pub branch_targets: BTreeSet<Address>,
+    /// `BNNN` and `0NNN` instructions, which continue somewhere that can't be known without running the program
+    pub boundaries: BTreeSet<Address>,
+    /// Program bytes not covered by any reachable instruction
+    pub data: BTreeSet<Address>
+}
+
+impl ControlFlowGraph {
+    /// Every reachable instruction in address order
+    pub fn instructions(&self) -> impl Iterator<Item = &(Address, Instruction)> {
+        self.blocks.values().flat_map(|block| block.instructions.iter())
+    }
+
+    /// `data` merged into runs of consecutive addresses
+    pub fn data_ranges(&self) -> Vec<Range<Address>> {
+        let mut ranges: Vec<Range<Address>> = Vec::new();
+        for address in self.data.iter().copied() {
+            match ranges.last_mut() {
+                Some(range) if range.end == address => range.end += 1,
+                _ => ranges.push(address..address + 1)
+            }
+        }
+        ranges
+    }
+}
+
+/// How execution leaves an instruction
+enum Flow {
+    Continue,
+    Jump(Address),
+    Call(Address),
+    Skip,
+    Stop
+}
+
+fn flow(instruction: &Instruction) -> Flow {
+    match instruction {
+        Instruction::JumpToAddress(address) => Flow::Jump(*address),
+        Instruction::ExecSubroutine(address) => Flow::Call(*address),
+        Instruction::SkipFollowingIfRegEq(..) | Instruction::SkipFollowingIfRegNeq(..) | Instruction::SkipFollowingIfRegEqReg(..) |
+        Instruction::SkipIfNE(..) | Instruction::SkipIfKeyPressed(_) | Instruction::SkipIfKeyNotPressed(_) => Flow::Skip,
+        Instruction::ReturnFromSubroutine | Instruction::Exit | Instruction::JumpWithOffset(_) | Instruction::ExecSubroutineML(_) => Flow::Stop,
+        _ => Flow::Continue
+    }
+}
+
+/// Decodes the instruction at `address`, `None` outside the program or for unknown opcodes
+fn decode_at(program: &[u8], origin: Address, address: Address) -> Option<Instruction> {
+    let offset = address.checked_sub(origin)? as usize;
+    match program.get(offset..)? {
+        [upper, lower, next_upper, next_lower, ..] => Instruction::decode_long((*upper, *lower).into(), Some((*next_upper, *next_lower).into())),
+        [upper, lower, ..] => Instruction::decode((*upper, *lower).into()),
+        _ => None
+    }
+    .filter(|instruction| offset + instruction.size() <= program.len())
+}
+
+/// Follows jumps, calls and skips from `origin`, where `program` is loaded, to find the reachable code.
+/// Unlike `disassemble` this doesn't assume code is aligned to even addresses, and anything it can't reach is
+/// reported as data. Computed jumps and machine code calls end the analysis along their path.
+pub fn analyze(program: &[u8], origin: Address) -> ControlFlowGraph {
+    let mut instructions = BTreeMap::new();
+    let mut leaders = BTreeSet::from([origin]);
+    let mut branch_targets = BTreeSet::new();
+    let mut boundaries = BTreeSet::new();
+    let mut pending = vec![origin];
+    while let Some(mut address) = pending.pop() {
+        while !instructions.contains_key(&address) {
+            let instruction = match decode_at(program, origin, address) {
+                Some(instruction) => instruction,
+                None => break
+            };
+            let next = address.wrapping_add(instruction.size() as Address);
+            let flow = flow(&instruction);
+            if matches!(instruction, Instruction::JumpWithOffset(_) | Instruction::ExecSubroutineML(_)) {
+                boundaries.insert(address);
+            }
+            instructions.insert(address, instruction);
+            match flow {
+                Flow::Continue => {
+                    address = next;
+                    continue;
+                }
+                Flow::Jump(target) | Flow::Call(target) => {
+                    branch_targets.insert(target);
+                    leaders.insert(target);
+                    pending.push(target);
+                    if let Flow::Call(_) = flow {
+                        leaders.insert(next);
+                        pending.push(next);
+                    }
+                }
+                Flow::Skip => {
+                    // the skipped instruction can be the four byte F000 NNNN
+                    let skipped_size = decode_at(program, origin, next).map_or(2, |skipped| skipped.size());
+                    let target = next.wrapping_add(skipped_size as Address);
+                    branch_targets.insert(target);
+                    leaders.insert(next);
+                    leaders.insert(target);
+                    pending.push(next);
+                    pending.push(target);
+                }
+                Flow::Stop => {}
+            }
+            break;
+        }
+    }
+
+    let mut blocks = BTreeMap::new();
+    for start in leaders.iter().copied().filter(|leader| instructions.contains_key(leader)) {
+        let mut block = BasicBlock {
+            start,
+            instructions: Vec::new(),
+            successors: Vec::new()
+        };
+        let mut address = start;
+        while let Some(instruction) = instructions.get(&address) {
+            let next = address.wrapping_add(instruction.size() as Address);
+            block.instructions.push((address, *instruction));
+            block.successors = match flow(instruction) {
+                Flow::Continue => vec![next],
+                Flow::Jump(target) => vec![target],
+                Flow::Call(target) => vec![target, next],
+                Flow::Skip => {
+                    let skipped_size = instructions.get(&next).map_or(2, |skipped| skipped.size());
+                    vec![next, next.wrapping_add(skipped_size as Address)]
+                }
+                Flow::Stop => vec![]
+            };
+            if !matches!(flow(instruction), Flow::Continue) || leaders.contains(&next) {
+                break;
+            }
+            address = next;
+        }
+        // falling off the end of the program or into an unknown opcode leads nowhere known
+        block.successors.retain(|successor| instructions.contains_key(successor));
+        blocks.insert(start, block);
+    }
+
+    let mut code = BTreeSet::new();
+    for (address, instruction) in instructions.iter() {
+        code.extend((0..instruction.size() as Address).map(|i| address.wrapping_add(i)));
+    }
+    let data = (0..program.len())
+        .map(|offset| origin.wrapping_add(offset as Address))
+        .filter(|address| !code.contains(address))
+        .collect();
+    ControlFlowGraph {
+        origin,
+        blocks,
+        branch_targets,
+        boundaries,
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_sprite_table_jumped_over_is_data() {
+        let program = [
+            0x12, 0x06, // JP 0x206
+            0xFF, 0x81, 0x81, 0xFF, // sprite
+            0xA2, 0x02, // LD I, 0x202
+            0xD0, 0x14, // DRW V0, V0, 4
+            0x12, 0x0A // JP 0x20A
+        ];
+        let graph = analyze(&program, 0x200);
+        assert_eq!(graph.data_ranges(), vec![0x202..0x206]);
+        let addresses: Vec<Address> = graph.instructions().map(|(address, _)| *address).collect();
+        assert_eq!(addresses, [0x200, 0x206, 0x208, 0x20A]);
+        assert_eq!(graph.branch_targets, BTreeSet::from([0x206, 0x20A]));
+        assert_eq!(graph.blocks.keys().copied().collect::<Vec<Address>>(), [0x200, 0x206, 0x20A]);
+        assert_eq!(graph.blocks[&0x206].successors, [0x20A]);
+    }
+
+    #[test]
+    fn code_at_odd_addresses_is_followed() {
+        // JP 0x203; a padding byte; LD V0, 5; JP 0x205
+        let graph = analyze(&[0x12, 0x03, 0x00, 0x60, 0x05, 0x12, 0x05], 0x200);
+        let addresses: Vec<Address> = graph.instructions().map(|(address, _)| *address).collect();
+        assert_eq!(addresses, [0x200, 0x203, 0x205]);
+        assert_eq!(graph.data, BTreeSet::from([0x202]));
+    }
+
+    #[test]
+    fn skips_and_calls_split_blocks_and_computed_jumps_end_them() {
+        let program = [
+            0x30, 0x01, // SE V0, 0x01
+            0x22, 0x08, // CALL 0x208
+            0xB3, 0x00, // JP V0, 0x300
+            0x00, 0x00, // never reached
+            0x00, 0xEE // RET
+        ];
+        let graph = analyze(&program, 0x200);
+        assert_eq!(graph.blocks[&0x200].successors, [0x202, 0x204]);
+        assert_eq!(graph.blocks[&0x202].successors, [0x208, 0x204]);
+        assert!(graph.blocks[&0x204].successors.is_empty());
+        assert!(graph.blocks[&0x208].successors.is_empty());
+        assert_eq!(graph.boundaries, BTreeSet::from([0x204]));
+        assert_eq!(graph.data_ranges(), vec![0x206..0x208]);
+    }
+}
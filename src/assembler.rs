@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use crate::chip8_instruction_set::{Address, Instruction, Operand, ParseInstructionError};
+
+/// Programs are assembled as if loaded at this address unless they start with `.org`, labels resolve relative to it
+pub const ORIGIN: Address = 0x200;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssembleError {
+    UnknownMnemonic(usize, String),
+    InvalidOperands(usize, String),
+    ValueOutOfRange(usize, String),
+    UnknownLabel(usize, String),
+    /// A `SYS` target that would assemble as another instruction, e.g. 0x0E0
+    SysAddressTaken(usize, String),
+    DuplicateLabel(usize, String),
+    /// `.org` pointing before code that's already been assembled
+    OriginBackwards(usize, String),
+    /// Octo syntax this assembler doesn't handle, e.g. macros
+    Unsupported(usize, String),
+    /// An Octo `else`, `end`, `again` or `while` outside the block it belongs to, or a block left open
+    UnbalancedBlock(usize, String),
+    /// Octo programs start running at `: main`
+    MissingMain,
+    ProgramTooLarge(usize)
+}
+
+impl Display for AssembleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AssembleError::UnknownMnemonic(line, s) => f.write_str(&format!("Line {}: unknown instruction '{}'", line, s)),
+            AssembleError::InvalidOperands(line, s) => f.write_str(&format!("Line {}: invalid operands for '{}'", line, s)),
+            AssembleError::ValueOutOfRange(line, s) => f.write_str(&format!("Line {}: value '{}' is out of range", line, s)),
+            AssembleError::UnknownLabel(line, s) => f.write_str(&format!("Line {}: unknown label '{}'", line, s)),
+            AssembleError::SysAddressTaken(line, s) => f.write_str(&format!("Line {}: SYS {} would assemble as another instruction", line, s)),
+            AssembleError::DuplicateLabel(line, s) => f.write_str(&format!("Line {}: label '{}' is defined more than once", line, s)),
+            AssembleError::OriginBackwards(line, s) => f.write_str(&format!("Line {}: origin {} is before the code above it", line, s)),
+            AssembleError::Unsupported(line, s) => f.write_str(&format!("Line {}: '{}' isn't supported", line, s)),
+            AssembleError::UnbalancedBlock(line, s) => f.write_str(&format!("Line {}: unmatched '{}'", line, s)),
+            AssembleError::MissingMain => f.write_str("The program has no 'main' label to start at"),
+            AssembleError::ProgramTooLarge(size) => f.write_str(&format!("Assembled program is {} bytes, which doesn't fit in 64 KiB of memory", size))
+        }
+    }
+}
+
+impl Error for AssembleError {
+
+}
+
+struct Statement<'a> {
+    line: usize,
+    mnemonic: String,
+    operands: Vec<&'a str>
+}
+
+impl Statement<'_> {
+    /// `.byte` is one byte per operand, `.word` two, `LD I, LONG` four and every other instruction is two bytes
+    fn size(&self) -> usize {
+        match self.mnemonic.as_str() {
+            ".BYTE" => self.operands.len(),
+            ".WORD" => self.operands.len() * 2,
+            _ if self.operands.iter().any(|operand| operand.eq_ignore_ascii_case("LONG")) => 4,
+            _ => 2
+        }
+    }
+}
+
+/// Assembles a listing using the mnemonics printed by the disassembler into a ROM loaded at `ORIGIN`.
+///
+/// One instruction per line, `;` starts a comment, `name:` defines a label and numbers are decimal or 0x-prefixed hex.
+/// `.byte` and `.word` emit raw data, e.g. `.byte 0xF0 0x90` or `.word 0x1234, loop`.
+/// `.org 0x300` continues at 0x300, padding with zeros. Before any code it moves the load address instead, the ROM then
+/// has to be run with the same `--load-address`.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssembleError> {
+    let mut labels = HashMap::new();
+    let mut statements = Vec::new();
+    let mut start = ORIGIN as usize;
+    let mut address = start;
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let mut code = line.split(';').next().unwrap_or("").trim();
+        while let Some((label, rest)) = code.split_once(':') {
+            let label = label.trim();
+            if label.is_empty() || !label.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                break;
+            }
+            if labels.insert(label.to_string(), address).is_some() {
+                return Err(AssembleError::DuplicateLabel(line_number, label.to_string()));
+            }
+            code = rest.trim();
+        }
+        if code.is_empty() {
+            continue;
+        }
+        let (mnemonic, rest) = code.split_once(char::is_whitespace).unwrap_or((code, ""));
+        let statement = Statement {
+            line: line_number,
+            mnemonic: mnemonic.to_uppercase(),
+            operands: rest.split(|c: char| c == ',' || c.is_whitespace()).filter(|s| !s.is_empty()).collect()
+        };
+        if statement.mnemonic == ".ORG" {
+            let origin = origin(&statement)?;
+            if statements.is_empty() {
+                start = origin;
+                // labels above the first line of code point at it
+                labels.values_mut().for_each(|label| *label = origin);
+            }else if origin < address {
+                return Err(AssembleError::OriginBackwards(line_number, statement.operands[0].to_string()));
+            }
+            address = origin;
+        }else{
+            address += statement.size();
+        }
+        statements.push(statement);
+    }
+    // XO-CHIP can address all 64 KiB
+    if address > 0x10000 {
+        return Err(AssembleError::ProgramTooLarge(address - start));
+    }
+
+    let mut program = Vec::with_capacity(address - start);
+    for statement in &statements {
+        let line = statement.line;
+        let operand = |token: &str| parse_operand(token, &labels);
+        match statement.mnemonic.as_str() {
+            ".ORG" => program.resize(origin(statement)? - start, 0),
+            ".BYTE" => {
+                for token in &statement.operands {
+                    program.push(number(operand(token), 0xFF, token, line)? as u8);
+                }
+            }
+            ".WORD" => {
+                for token in &statement.operands {
+                    program.extend_from_slice(&number(operand(token), 0xFFFF, token, line)?.to_be_bytes());
+                }
+            }
+            mnemonic => {
+                let instruction = Instruction::from_tokens(mnemonic, &statement.operands, operand)
+                    .map_err(|e| assemble_error(e, line, &format!("{} {}", statement.mnemonic, statement.operands.join(", "))))?;
+                program.extend(instruction.to_bytes());
+            }
+        }
+    }
+    Ok(program)
+}
+
+/// `.org` only takes a number, labels after it aren't placed yet
+fn origin(statement: &Statement) -> Result<usize, AssembleError> {
+    match statement.operands.as_slice() {
+        [token] => number(token.parse::<Operand>(), 0xFFFF, token, statement.line).map(|origin| origin as usize),
+        _ => Err(AssembleError::InvalidOperands(statement.line, format!(".ORG {}", statement.operands.join(", "))))
+    }
+}
+
+/// Labels stand in for numbers anywhere an operand isn't one of the fixed names
+fn parse_operand(token: &str, labels: &HashMap<String, usize>) -> Result<Operand, ParseInstructionError> {
+    match token.parse::<Operand>() {
+        Err(ParseInstructionError::UnknownOperand(_)) => labels.get(token)
+            .map(|address| Operand::Number(*address as u16))
+            .ok_or_else(|| ParseInstructionError::UnknownOperand(token.to_string())),
+        operand => operand
+    }
+}
+
+fn number(operand: Result<Operand, ParseInstructionError>, max: u16, token: &str, line: usize) -> Result<u16, AssembleError> {
+    match operand {
+        Ok(Operand::Number(value)) if value <= max => Ok(value),
+        Ok(Operand::Number(_)) | Err(ParseInstructionError::InvalidNumber(_)) => Err(AssembleError::ValueOutOfRange(line, token.to_string())),
+        Err(ParseInstructionError::UnknownOperand(_)) => Err(AssembleError::UnknownLabel(line, token.to_string())),
+        _ => Err(AssembleError::InvalidOperands(line, token.to_string()))
+    }
+}
+
+/// `statement` is the source text shown for invalid operands
+pub(crate) fn assemble_error(error: ParseInstructionError, line: usize, statement: &str) -> AssembleError {
+    match error {
+        ParseInstructionError::UnknownMnemonic(mnemonic) => AssembleError::UnknownMnemonic(line, mnemonic),
+        ParseInstructionError::UnknownOperand(token) => AssembleError::UnknownLabel(line, token),
+        ParseInstructionError::RegisterOutOfRange(token) | ParseInstructionError::InvalidNumber(token) => AssembleError::ValueOutOfRange(line, token),
+        ParseInstructionError::ValueOutOfRange { value, .. } => AssembleError::ValueOutOfRange(line, format!("0x{:X}", value)),
+        ParseInstructionError::SysAddressTaken(address) => AssembleError::SysAddressTaken(line, format!("0x{:03X}", address)),
+        ParseInstructionError::WrongOperandCount { .. } | ParseInstructionError::InvalidOperands(_) => {
+            AssembleError::InvalidOperands(line, statement.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::{Chip8, HaltReason, TickStatus};
+    use crate::chip8_instruction_set::RawInstruction;
+    use crate::disassembler::disassemble;
+
+    const DRAW_AND_LOOP: &str = "
+        ; draws the 0 glyph at (10, 5) and stops
+        LD V0, 10
+        LD V1, 0x05
+        LD V2, 0
+        LD F, V2      ; I points at the glyph
+        DRW V0, V1, 5
+    loop: JP loop
+    ";
+
+    #[test]
+    fn disassembling_the_output_gives_back_the_listing() {
+        let listing = [
+            "CLS", "CALL 0x20A", "LD V3, 0x2A", "ADD V3, V4", "SHL V3, V4", "LD I, 0x300", "DRW V0, V1, 15",
+            "LD V5, K", "LD B, V5", "LD [I], V5", "SKNP VE", "SCD 3", "HIGH", "PLANE 2", "LD I, LONG 0x1234", "RET"
+        ];
+        let program = assemble(&listing.join("\n")).unwrap();
+        let disassembled: Vec<String> = disassemble(&program, ORIGIN).iter()
+            .map(|word| word.instruction.unwrap().to_string())
+            .collect();
+        assert_eq!(disassembled, listing);
+    }
+
+    #[test]
+    fn every_decodable_opcode_assembles_from_its_mnemonic() {
+        for class in 0..16u16 {
+            let opcodes: Vec<u16> = (class << 12..=class << 12 | 0xFFF)
+                .filter(|opcode| Instruction::decode(RawInstruction(*opcode)).is_some())
+                .collect();
+            let listing = opcodes.iter()
+                .map(|opcode| Instruction::decode(RawInstruction(*opcode)).unwrap().to_string())
+                .collect::<Vec<String>>()
+                .join("\n");
+            let expected: Vec<u8> = opcodes.iter().flat_map(|opcode| opcode.to_be_bytes()).collect();
+            assert!(assemble(&listing).unwrap() == expected, "class {:X}", class);
+        }
+    }
+
+    #[test]
+    fn sys_targets_taken_by_other_instructions_are_rejected_with_their_line() {
+        assert_eq!(assemble("CLS\nSYS 0x0E0"), Err(AssembleError::SysAddressTaken(2, "0x0E0".to_string())));
+        assert_eq!(assemble("SYS 0"), Err(AssembleError::SysAddressTaken(1, "0x000".to_string())));
+        assert_eq!(assemble("SYS 0x0E1"), Ok(vec![0x00, 0xE1]));
+    }
+
+    #[test]
+    fn an_assembled_program_runs() {
+        let program = assemble(DRAW_AND_LOOP).unwrap();
+        assert_eq!(program, [0x60, 0x0A, 0x61, 0x05, 0x62, 0x00, 0xF2, 0x29, 0xD0, 0x15, 0x12, 0x0A]);
+        let mut chip = Chip8::default();
+        chip.load(&program).unwrap();
+        while chip.tick().unwrap() != TickStatus::Halted {}
+        assert_eq!(chip.halted(), Some(&HaltReason::SelfJump(0x20A)));
+        assert_eq!(chip.registers()[..3], [10, 5, 0]);
+        let width = chip.get_screen_size().0;
+        let rows: Vec<String> = (5..10)
+            .map(|y| (10..14).map(|x| if chip.get_display()[y * width + x] {'#'} else {'.'}).collect())
+            .collect();
+        assert_eq!(rows, ["####", "#..#", "#..#", "#..#", "####"]);
+    }
+
+    #[test]
+    fn labels_calls_and_data_run_as_written() {
+        let source = "
+                CALL draw
+                ADD V3, 1
+            end: JP end
+            draw:
+                LD I, sprite
+                LD V0, 8
+                LD V1, 2
+                DRW V0, V1, 3
+                LD V3, 0x40
+                RET
+            .org 0x300
+            sprite: .byte 0xF0 0x90
+                .byte 0xF0
+        ";
+        let program = assemble(source).unwrap();
+        assert_eq!(program.len(), 0x103);
+        let mut chip = Chip8::default();
+        chip.load(&program).unwrap();
+        while chip.tick().unwrap() != TickStatus::Halted {}
+        assert_eq!(chip.halted(), Some(&HaltReason::SelfJump(0x204)));
+        assert_eq!(chip.registers()[..4], [8, 2, 0, 0x41]);
+        assert_eq!(chip.registers()[0xF], 0);
+        assert_eq!(chip.i(), 0x300);
+        let width = chip.get_screen_size().0;
+        let rows: Vec<String> = (1..6)
+            .map(|y| (7..13).map(|x| if chip.get_display()[y * width + x] {'#'} else {'.'}).collect())
+            .collect();
+        assert_eq!(rows, ["......", ".####.", ".#..#.", ".####.", "......"]);
+    }
+
+    #[test]
+    fn undefined_labels_are_reported_with_their_line() {
+        assert_eq!(assemble("CLS\nJP nowhere"), Err(AssembleError::UnknownLabel(2, "nowhere".to_string())));
+        assert_eq!(assemble("start: CALL start\n\nLD I, sprites"), Err(AssembleError::UnknownLabel(3, "sprites".to_string())));
+        assert_eq!(assemble(".word 0x1234, table"), Err(AssembleError::UnknownLabel(1, "table".to_string())));
+    }
+
+    #[test]
+    fn out_of_range_addresses_are_reported_with_their_line() {
+        assert_eq!(assemble("CLS\nJP 0x1000"), Err(AssembleError::ValueOutOfRange(2, "0x1000".to_string())));
+        assert_eq!(assemble("CALL 4096"), Err(AssembleError::ValueOutOfRange(1, "0x1000".to_string())));
+        // labels past 12 bits can only be reached with LD I, LONG
+        let far = "LD I, far\n.org 0x1000\nfar: .byte 1";
+        assert_eq!(assemble(far), Err(AssembleError::ValueOutOfRange(1, "0x1000".to_string())));
+        assert!(assemble(&far.replace("I, far", "I, LONG far")).is_ok());
+        assert_eq!(assemble(".byte 0x100"), Err(AssembleError::ValueOutOfRange(1, "0x100".to_string())));
+        assert_eq!(assemble("CLS\n.org 0xFFFF\n.word 0"), Err(AssembleError::ProgramTooLarge(0xFE01)));
+    }
+}
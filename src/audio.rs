@@ -0,0 +1,189 @@
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use rodio::{OutputStream, Sink, Source};
+
+pub const SAMPLE_RATE: u32 = 44100;
+const BEEP_FREQUENCY: f32 = 440.0;
+const VOLUME: f32 = 0.2;
+/// Samples to fade the beep in or out over, switching the wave on and off at full volume clicks
+const FADE_SAMPLES: f32 = SAMPLE_RATE as f32 * 0.005;
+
+/// The CHIP-8 buzzer, a square wave that plays while the sound timer is running
+pub struct Beeper {
+    // dropping the stream closes the audio device
+    _stream: OutputStream,
+    _sink: Sink,
+    active: Arc<AtomicBool>,
+    gate: BeepGate
+}
+
+impl Beeper {
+    /// Every beep lasts at least `min_frames`
+    pub fn new(min_frames: u32) -> Result<Self, Box<dyn Error>> {
+        let (stream, handle) = OutputStream::try_default()?;
+        let sink = Sink::try_new(&handle)?;
+        let active = Arc::new(AtomicBool::new(false));
+        sink.append(SquareWave::new(active.clone()));
+        Ok(Beeper {
+            _stream: stream,
+            _sink: sink,
+            active,
+            gate: BeepGate::new(min_frames)
+        })
+    }
+
+    /// Starts or stops the beep, called once per frame with the sound timer from before it counted down that frame
+    pub fn update(&mut self, sound_timer: u8, muted: bool) {
+        let open = if muted {
+            self.gate.close();
+            false
+        }else{
+            self.gate.frame(sound_timer)
+        };
+        self.active.store(open, Ordering::Relaxed);
+    }
+}
+
+/// Decides from the sound timer whether the buzzer sounds each frame. Once a beep starts it is held for at
+/// least `min_frames`, and a timer rewritten before running out keeps it going, so ROMs that write small
+/// values every frame get a steady tone.
+pub struct BeepGate {
+    min_frames: u32,
+    // frames the current beep has sounded for, 0 while silent
+    open_frames: u32
+}
+
+impl BeepGate {
+    pub fn new(min_frames: u32) -> Self {
+        BeepGate {
+            min_frames,
+            open_frames: 0
+        }
+    }
+
+    /// Whether the buzzer sounds this frame
+    pub fn frame(&mut self, sound_timer: u8) -> bool {
+        let open = sound_timer > 0 || (self.open_frames > 0 && self.open_frames < self.min_frames);
+        self.open_frames = if open {self.open_frames.saturating_add(1)} else {0};
+        open
+    }
+
+    /// Silences the buzzer straight away, cutting short the minimum beep
+    pub fn close(&mut self) {
+        self.open_frames = 0;
+    }
+}
+
+/// Endless square wave that fades to silence while `active` is unset rather than stopping,
+/// so the stream keeps running and the beep can start again without a gap
+pub struct SquareWave {
+    active: Arc<AtomicBool>,
+    // position within the current period, 0 to 1
+    phase: f32,
+    level: f32
+}
+
+impl SquareWave {
+    /// Mono samples at `SAMPLE_RATE`
+    pub fn new(active: Arc<AtomicBool>) -> Self {
+        SquareWave {
+            active,
+            phase: 0.0,
+            level: 0.0
+        }
+    }
+}
+
+impl Iterator for SquareWave {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let target = if self.active.load(Ordering::Relaxed) {1.0} else {0.0};
+        let step = 1.0 / FADE_SAMPLES;
+        self.level = if self.level < target {(self.level + step).min(target)} else {(self.level - step).max(target)};
+        self.phase = (self.phase + BEEP_FREQUENCY / SAMPLE_RATE as f32).fract();
+        let wave = if self.phase < 0.5 {1.0} else {-1.0};
+        Some(wave * self.level * VOLUME)
+    }
+}
+
+impl Source for SquareWave {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_wave_fades_in_and_out_instead_of_clicking() {
+        let active = Arc::new(AtomicBool::new(false));
+        let mut wave = SquareWave::new(active.clone());
+        assert!(wave.by_ref().take(100).all(|sample| sample == 0.0));
+
+        active.store(true, Ordering::Relaxed);
+        let fade_in: Vec<f32> = wave.by_ref().take(FADE_SAMPLES as usize + 1).map(f32::abs).collect();
+        assert!(fade_in.windows(2).all(|pair| pair[1] >= pair[0] && pair[1] - pair[0] <= VOLUME / FADE_SAMPLES + f32::EPSILON));
+        assert!((fade_in.last().unwrap() - VOLUME).abs() < 1e-6);
+
+        active.store(false, Ordering::Relaxed);
+        let fade_out: Vec<f32> = wave.by_ref().take(FADE_SAMPLES as usize + 1).map(f32::abs).collect();
+        assert!(fade_out.windows(2).all(|pair| pair[1] <= pair[0]));
+        assert_eq!(*fade_out.last().unwrap(), 0.0);
+    }
+
+    /// Runs a sound timer through `gate` a frame at a time, with the ROM writing `writes[n]` to it on frame n
+    fn gate_over(gate: &mut BeepGate, writes: &[Option<u8>]) -> Vec<bool> {
+        let mut timer = 0u8;
+        writes.iter().map(|write| {
+            if let Some(value) = write {
+                timer = *value;
+            }
+            let open = gate.frame(timer);
+            timer = timer.saturating_sub(1);
+            open
+        }).collect()
+    }
+
+    #[test]
+    fn short_timers_beep_for_at_least_the_minimum() {
+        let one = [Some(1), None, None, None, None];
+        assert_eq!(gate_over(&mut BeepGate::new(1), &one), [true, false, false, false, false]);
+        assert_eq!(gate_over(&mut BeepGate::new(3), &one), [true, true, true, false, false]);
+        let two = [Some(2), None, None, None, None];
+        assert_eq!(gate_over(&mut BeepGate::new(1), &two), [true, true, false, false, false]);
+        assert_eq!(gate_over(&mut BeepGate::new(3), &two), [true, true, true, false, false]);
+    }
+
+    #[test]
+    fn rewriting_the_timer_before_it_runs_out_beeps_without_gaps() {
+        let writes: Vec<Option<u8>> = (0..12).map(|frame| if frame % 3 == 0 && frame < 9 {Some(4)} else {None}).collect();
+        let mut expected = vec![true; 10];
+        expected.extend([false, false]);
+        assert_eq!(gate_over(&mut BeepGate::new(1), &writes), expected);
+    }
+
+    #[test]
+    fn closing_cuts_the_minimum_beep_short() {
+        let mut gate = BeepGate::new(5);
+        assert!(gate.frame(1));
+        gate.close();
+        assert!(!gate.frame(0));
+    }
+}
@@ -0,0 +1,132 @@
+use std::borrow::Cow;
+use std::error::Error;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use gif::{Encoder, Frame, Repeat};
+use raqote::Color;
+use chip8_rs_dmfg::chip8::{Chip8, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/// GIF frames are the low resolution display scaled up this many times, high resolution frames half as much
+const GIF_SCALE: usize = 4;
+/// Shortest frame delay in hundredths of a second that browsers play back as written
+const MIN_GIF_DELAY: u16 = 2;
+
+/// `{rom name without extension}-YYYYMMDD-HHMMSS.{extension}` in the current directory, using UTC
+pub fn capture_path(rom_path: &str, extension: &str) -> String {
+    let name = Path::new(rom_path).file_stem().map_or(rom_path.to_string(), |stem| stem.to_string_lossy().to_string());
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, time) = (seconds / 86400, seconds % 86400);
+    // days since the epoch to a civil date, from Howard Hinnant's date algorithms
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 {mp + 3} else {mp - 9};
+    let year = year_of_era + era * 400 + if month <= 2 {1} else {0};
+    format!("{}-{:04}{:02}{:02}-{:02}{:02}{:02}.{}", name, year, month, day, time / 3600, time / 60 % 60, time % 60, extension)
+}
+
+/// Palette indices of the display scaled up with nearest neighbour to `width` x `height`
+fn scaled_pixels(chip: &Chip8, width: usize, height: usize) -> Vec<u8> {
+    let (display_width, display_height) = chip.get_screen_size();
+    (0..width * height)
+        .map(|i| {
+            let (x, y) = (i % width * display_width / width, i / width * display_height / height);
+            chip.pixel_color(y * display_width + x) as u8
+        })
+        .collect()
+}
+
+/// Saves the display as a PNG the size of the window, `palette` is indexed by `Chip8::pixel_color`
+pub fn write_png(chip: &Chip8, path: &str, palette: &[Color; 4], scale: usize) -> Result<(), Box<dyn Error>> {
+    let (width, height) = (DISPLAY_WIDTH * scale, DISPLAY_HEIGHT * scale);
+    let pixels = scaled_pixels(chip, width, height).iter()
+        .flat_map(|index| {
+            let color = palette[*index as usize];
+            [color.r(), color.g(), color.b()]
+        })
+        .collect::<Vec<u8>>();
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&pixels)?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// Animated GIF written one 60 Hz frame at a time. Unchanged frames are merged into the previous one,
+/// and as GIF delays count hundredths of a second, frame lengths are rounded so the total stays in sync.
+pub struct GifRecorder {
+    encoder: Encoder<BufWriter<File>>,
+    path: String,
+    pending: Option<Vec<u8>>,
+    // 60 Hz frames recorded so far and hundredths of a second written out for them
+    frames: u64,
+    written: u64
+}
+
+impl GifRecorder {
+    pub fn create(path: &str, palette: &[Color; 4]) -> Result<Self, Box<dyn Error>> {
+        let global_palette = palette.iter().flat_map(|color| [color.r(), color.g(), color.b()]).collect::<Vec<u8>>();
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = Encoder::new(file, (DISPLAY_WIDTH * GIF_SCALE) as u16, (DISPLAY_HEIGHT * GIF_SCALE) as u16, &global_palette)?;
+        encoder.set_repeat(Repeat::Infinite)?;
+        Ok(GifRecorder {
+            encoder,
+            path: path.to_string(),
+            pending: None,
+            frames: 0,
+            written: 0
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Seconds of gameplay recorded
+    pub fn seconds(&self) -> u64 {
+        self.frames / 60
+    }
+
+    /// Adds the current display as the next 60 Hz frame
+    pub fn push(&mut self, chip: &Chip8) -> Result<(), Box<dyn Error>> {
+        let pixels = scaled_pixels(chip, DISPLAY_WIDTH * GIF_SCALE, DISPLAY_HEIGHT * GIF_SCALE);
+        if self.pending.as_ref() != Some(&pixels) {
+            self.write_pending()?;
+            self.pending = Some(pixels);
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    fn write_pending(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(pixels) = self.pending.take() {
+            let end = self.frames * 100 / 60;
+            let delay = end.saturating_sub(self.written).max(MIN_GIF_DELAY as u64);
+            self.written += delay;
+            let frame = Frame {
+                width: (DISPLAY_WIDTH * GIF_SCALE) as u16,
+                height: (DISPLAY_HEIGHT * GIF_SCALE) as u16,
+                buffer: Cow::Owned(pixels),
+                delay: delay.min(u16::MAX as u64) as u16,
+                ..Frame::default()
+            };
+            self.encoder.write_frame(&frame)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the last frame and closes the file
+    pub fn finish(mut self) -> Result<(), Box<dyn Error>> {
+        self.write_pending()?;
+        self.encoder.into_inner()?.into_inner()?;
+        Ok(())
+    }
+}
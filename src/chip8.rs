@@ -1,11 +1,35 @@
-use std::collections::HashMap;
-use std::ops::{Add, BitAnd, BitOr, BitXor, Shl, Shr};
-use lazy_static::lazy_static;
-use log::{error, info, warn};
-use minifb::Key;
-use rand::{Rng, thread_rng};
-use raqote::{Color, DrawOptions, DrawTarget, SolidSource, Source};
+use core::ops::{BitAnd, BitOr, BitXor, Shl, Shr};
+use alloc::collections::BTreeSet;
+use core::fmt::{Display, Formatter};
+use core::ops::Range;
+use core::str::FromStr;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use log::{info, warn};
+use crate::chip8_error::Chip8Error;
 use crate::chip8_instruction_set::{Address, Instruction, RawInstruction};
+use crate::disassembler::{self, DisassembledWord};
+use crate::quirks::{IndexIncrement, Quirks, SpriteEdge};
+use crate::rng::XorShiftRng;
+use crate::save_state::{hash_program, SaveState, SaveStateError};
+
+pub const DISPLAY_WIDTH: usize = 64;
+pub const DISPLAY_HEIGHT: usize = 32;
+pub const HIRES_DISPLAY_WIDTH: usize = 128;
+pub const HIRES_DISPLAY_HEIGHT: usize = 64;
+pub const FONT_ADDRESS: Address = 0x00;
+pub const BIG_SPRITES_ADDRESS: Address = 0x50;
+/// First address past both fonts, programs only load below it with `with_font_overwrite`
+pub const FONTS_END: Address = BIG_SPRITES_ADDRESS + (BIG_SPRITES.len() * BIG_SPRITES[0].len()) as Address;
+/// Where programs are loaded and start unless `with_origin` says otherwise
+pub const DEFAULT_ORIGIN: Address = 0x200;
+/// Room for the fonts and at least one byte of program
+pub const MIN_MEMORY: usize = 0x201;
+/// All that XO-CHIP's 16 bit addresses can reach
+pub const MAX_MEMORY: usize = 0x10000;
 
 const SPRITES: [[u8; 5]; 16] = [
     [0xf0, 0x90, 0x90, 0x90, 0xf0], //0
@@ -26,249 +50,1362 @@ const SPRITES: [[u8; 5]; 16] = [
     [0xf0, 0x80, 0xf0, 0x80, 0x80], //f
 ];
 
+const BIG_SPRITES: [[u8; 10]; 16] = [
+    [0xff, 0xff, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xff, 0xff], //0
+    [0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xff, 0xff], //1
+    [0xff, 0xff, 0x03, 0x03, 0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff], //2
+    [0xff, 0xff, 0x03, 0x03, 0xff, 0xff, 0x03, 0x03, 0xff, 0xff], //3
+    [0xc3, 0xc3, 0xc3, 0xc3, 0xff, 0xff, 0x03, 0x03, 0x03, 0x03], //4
+    [0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0x03, 0x03, 0xff, 0xff], //5
+    [0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff], //6
+    [0xff, 0xff, 0x03, 0x03, 0x06, 0x0c, 0x18, 0x18, 0x18, 0x18], //7
+    [0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff], //8
+    [0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff, 0x03, 0x03, 0xff, 0xff], //9
+    [0x7e, 0xff, 0xc3, 0xc3, 0xc3, 0xff, 0xff, 0xc3, 0xc3, 0xc3], //a
+    [0xfc, 0xfc, 0xc3, 0xc3, 0xfc, 0xfc, 0xc3, 0xc3, 0xfc, 0xfc], //b
+    [0x3c, 0xff, 0xc3, 0xc0, 0xc0, 0xc0, 0xc0, 0xc3, 0xff, 0x3c], //c
+    [0xfc, 0xfe, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xfe, 0xfc], //d
+    [0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff], //e
+    [0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0xc0, 0xc0, 0xc0, 0xc0], //f
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    Chip8,
+    SuperChip,
+    XoChip
+}
+
+impl FromStr for Variant {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "chip8" | "chip-8" => Ok(Variant::Chip8),
+            "schip" | "superchip" | "super-chip" => Ok(Variant::SuperChip),
+            "xochip" | "xo-chip" => Ok(Variant::XoChip),
+            _ => Err(format!("Unknown variant '{}', expected chip8, schip or xochip", s))
+        }
+    }
+}
+
+impl Display for Variant {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Variant::Chip8 => f.write_str("chip8"),
+            Variant::SuperChip => f.write_str("schip"),
+            Variant::XoChip => f.write_str("xochip")
+        }
+    }
+}
+
+/// What to do with an instruction the machine can't run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpcodePolicy {
+    /// Skip over it silently
+    Ignore,
+    /// Skip over it and log a warning
+    Warn,
+    /// Stop the machine with PC left on the instruction
+    Halt
+}
+
+impl FromStr for OpcodePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ignore" => Ok(OpcodePolicy::Ignore),
+            "warn" => Ok(OpcodePolicy::Warn),
+            "halt" => Ok(OpcodePolicy::Halt),
+            _ => Err(format!("Unknown policy '{}', expected ignore, warn or halt", s))
+        }
+    }
+}
+
+impl Display for OpcodePolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            OpcodePolicy::Ignore => f.write_str("ignore"),
+            OpcodePolicy::Warn => f.write_str("warn"),
+            OpcodePolicy::Halt => f.write_str("halt")
+        }
+    }
+}
+
+/// What to do when an instruction reads or writes memory past the end
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryPolicy {
+    /// Continue at address 0, like the address lines of a real machine would
+    Wrap,
+    /// Stop with `Chip8Error::MemoryOutOfBounds`
+    Error
+}
+
+impl FromStr for MemoryPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "wrap" => Ok(MemoryPolicy::Wrap),
+            "error" => Ok(MemoryPolicy::Error),
+            _ => Err(format!("Unknown policy '{}', expected wrap or error", s))
+        }
+    }
+}
+
+impl Display for MemoryPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            MemoryPolicy::Wrap => f.write_str("wrap"),
+            MemoryPolicy::Error => f.write_str("error")
+        }
+    }
+}
+
+/// What happens to instructions writing to the interpreter area below 0x200, where the fonts live
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteProtection {
+    /// Writes go through like anywhere else
+    Off,
+    /// Protected bytes are left unchanged and a warning is logged
+    Ignore,
+    /// Stop with `Chip8Error::ProtectedWrite`
+    Error
+}
+
+impl FromStr for WriteProtection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "off" => Ok(WriteProtection::Off),
+            "ignore" => Ok(WriteProtection::Ignore),
+            "error" => Ok(WriteProtection::Error),
+            _ => Err(format!("Unknown write protection '{}', expected off, ignore or error", s))
+        }
+    }
+}
+
+impl Display for WriteProtection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            WriteProtection::Off => f.write_str("off"),
+            WriteProtection::Ignore => f.write_str("ignore"),
+            WriteProtection::Error => f.write_str("error")
+        }
+    }
+}
+
+/// Progress of an FX0A wait, which only completes on a key pressed and released after it started
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyWait {
+    Idle,
+    Waiting,
+    Pressed(u8),
+    Released(u8)
+}
+
+/// Why the machine stopped with `TickStatus::Halted`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HaltReason {
+    /// An instruction was rejected by its `OpcodePolicy`
+    Rejected(Chip8Error),
+    /// A jump or call to its own address, the usual way for a CHIP-8 program to end since nothing can run after it
+    SelfJump(Address),
+    /// The program counter left memory, or points at its last byte so only half an instruction could be fetched
+    PcOutOfBounds(Address),
+    /// The machine language call handler asked to stop at a 0NNN call to this address
+    MlCall(Address)
+}
+
+impl Display for HaltReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            HaltReason::Rejected(error) => write!(f, "{}", error),
+            HaltReason::SelfJump(address) => write!(f, "Program jumps or calls to itself at 0x{:04x}", address),
+            HaltReason::PcOutOfBounds(address) => write!(f, "Program counter ran past the end of memory at 0x{:04x}", address),
+            HaltReason::MlCall(address) => write!(f, "Machine language call to 0x{:03x} halted the machine", address)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TickStatus {
+    Executed,
+    /// The instruction at this address was not executed, ticking again steps over the breakpoint
+    BreakpointHit(Address),
+    /// A hook returned `HookAction::Pause` for the instruction at this address. From the pre-instruction hook the
+    /// instruction has not run yet and ticking again runs it without asking the hook a second time.
+    HookPaused(Address),
+    /// The program ran the SUPER-CHIP exit instruction, further ticks do nothing
+    Exited,
+    /// A sprite was already drawn this frame and the display wait quirk is on, the draw runs after the next `decrement_time`
+    WaitingForFrame,
+    /// The program ended in a jump to itself or an instruction was rejected by its `OpcodePolicy`, see `Chip8::halted`.
+    /// Further ticks do nothing until the machine is reloaded
+    Halted,
+    /// The instruction ran and changed a watched register or wrote to watched memory
+    WatchHit(WatchHit)
+}
+
+/// The first watch an instruction triggered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchHit {
+    Register { register: u8, old: u8, new: u8 },
+    Memory { address: Address, old: u8, new: u8 }
+}
+
+impl Display for WatchHit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            WatchHit::Register { register, old, new } => write!(f, "V{:X} changed from 0x{:02x} to 0x{:02x}", register, old, new),
+            WatchHit::Memory { address, old, new } => write!(f, "0x{:04x} written, 0x{:02x} to 0x{:02x}", address, old, new)
+        }
+    }
+}
+
+/// Everything a single `Chip8::step` did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepOutcome {
+    pub status: TickStatus,
+    /// The instruction that ran, `None` when the step stopped before executing anything or skipped an unknown opcode
+    pub instruction: Option<Instruction>,
+    pub pc_before: Address,
+    pub pc_after: Address,
+    /// The display contents or resolution changed and should be redrawn
+    pub display_changed: bool,
+    /// FX0A found no key pressed and will run again on the next step
+    pub waiting_for_key: bool,
+    /// The sound timer is non-zero
+    pub sound_playing: bool
+}
+
+/// Everything a `Chip8::run_frame` did
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrameResult {
+    /// Instructions that ran, fewer than asked for when the frame ended early
+    pub instructions: u32,
+    /// How much of the budget the instructions used up, the same as `instructions` for `run_frame`.
+    /// With `run_frame_costed` the last instruction can take it past the budget.
+    pub cycles: u32,
+    /// The last instruction that ran and its address, e.g. the one that triggered a watch
+    pub last_instruction: Option<(Address, Instruction)>,
+    /// The display contents or resolution changed and should be redrawn
+    pub display_changed: bool,
+    /// The sound timer was non-zero before it counted down, so the buzzer sounds for this frame
+    pub sound_active: bool,
+    /// FX0A ended the frame early, it waits for a key
+    pub waiting_for_key: bool,
+    /// Anything else that ended the frame early: the display wait quirk, a breakpoint or watch, or the machine
+    /// exiting or halting
+    pub stopped_by: Option<TickStatus>
+}
+
+/// Where execution continues after an instruction
+#[derive(Default, PartialEq, Eq)]
+enum ControlFlow {
+    /// The instruction after this one
+    #[default]
+    Next,
+    /// The instruction after the next one
+    Skip,
+    Jump(Address),
+    /// This instruction again, on the next step
+    Wait
+}
+
+/// Side effects of `execute` that the frontend cares about, and where to continue
+#[derive(Default)]
+struct ExecuteEffects {
+    display_changed: bool,
+    flow: ControlFlow
+}
+
+/// What a hook wants the machine to do next
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookAction {
+    Continue,
+    /// Stop the current step with `TickStatus::HookPaused`
+    Pause
+}
+
+/// What the machine language call handler did with a 0NNN call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MlCallResult {
+    /// Handled, carry on with the next instruction
+    Continue,
+    /// Stop the machine with `HaltReason::MlCall`, PC left on the call
+    Halt,
+    /// Not a call the handler knows, the sys `OpcodePolicy` deals with it as if there were no handler
+    Unhandled
+}
+
+/// The part of the machine a machine language call handler can see. Memory is read only, registers can be written.
+pub struct Chip8StateView<'a> {
+    registers: &'a mut [u8; 16],
+    memory: &'a [u8],
+    address_register: Address,
+    pc: Address
+}
+
+impl Chip8StateView<'_> {
+    pub fn registers(&self) -> &[u8; 16] {
+        self.registers
+    }
+
+    pub fn register(&self, register: u8) -> u8 {
+        self.registers[register as usize & 0xf]
+    }
+
+    pub fn set_register(&mut self, register: u8, value: u8) {
+        self.registers[register as usize & 0xf] = value;
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        self.memory
+    }
+
+    pub fn i(&self) -> Address {
+        self.address_register
+    }
+
+    /// Address of the 0NNN instruction being handled
+    pub fn pc(&self) -> Address {
+        self.pc
+    }
+}
+
+type RandomSource = Box<dyn FnMut() -> u8 + Send>;
+type MlCallHandler = Box<dyn FnMut(&mut Chip8StateView, Address) -> MlCallResult + Send>;
+type InstructionHook = Box<dyn FnMut(Address, &Instruction, &Chip8) -> HookAction + Send>;
+type DrawHook = Box<dyn FnMut(Address, &Chip8) -> HookAction + Send>;
+type UnknownInstructionHook = Box<dyn FnMut(Address, RawInstruction, &Chip8) -> HookAction + Send>;
+type TraceFn = Box<dyn FnMut(&TraceEvent) + Send>;
+
+/// Callbacks for tools built on the emulator, each one is only a `None` check while unset
+#[derive(Default)]
+struct Hooks {
+    pre_instruction: Option<InstructionHook>,
+    draw: Option<DrawHook>,
+    unknown_instruction: Option<UnknownInstructionHook>
+}
+
+/// Passed to the trace hook right before an instruction executes
+pub struct TraceEvent<'a> {
+    pub pc: Address,
+    pub opcode: RawInstruction,
+    pub instruction: &'a Instruction,
+    pub registers: &'a [u8; 16]
+}
+
+impl Display for TraceEvent<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "0x{:04X}  {:04X}  {:<16}", self.pc, self.opcode.0, self.instruction.to_string())?;
+        let read = self.instruction.registers_read();
+        if !read.is_empty() {
+            let values = read.iter()
+                .map(|reg| format!("V{:X}=0x{:02X}", reg, self.registers[*reg as usize]))
+                .collect::<Vec<String>>();
+            write!(f, " ({})", values.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks the memory and stack sizes a `Chip8` is created with. Defaults to the classic 4 KB of memory and a 16 level stack.
+#[derive(Debug, Clone)]
+pub struct Chip8Builder {
+    memory: usize,
+    stack_depth: usize
+}
+
+impl Default for Chip8Builder {
+    fn default() -> Self {
+        Chip8Builder {
+            memory: 0x1000,
+            stack_depth: 16
+        }
+    }
+}
+
+impl Chip8Builder {
+    /// Bytes of memory, from `MIN_MEMORY` to `MAX_MEMORY`
+    pub fn memory(mut self, memory: usize) -> Self {
+        self.memory = memory;
+        self
+    }
+
+    /// Subroutine calls that can be nested, at least 1
+    pub fn stack_depth(mut self, stack_depth: usize) -> Self {
+        self.stack_depth = stack_depth;
+        self
+    }
+
+    pub fn build(self) -> Result<Chip8, Chip8Error> {
+        if !(MIN_MEMORY..=MAX_MEMORY).contains(&self.memory) {
+            return Err(Chip8Error::InvalidMemorySize { size: self.memory });
+        }
+        if self.stack_depth == 0 {
+            return Err(Chip8Error::InvalidStackDepth);
+        }
+        Ok(Chip8::new(self.memory, self.stack_depth))
+    }
+}
+
 pub struct Chip8 {
-    display: DrawTarget,
-    display_color: Color,
-    display_scale: u32,
+    display: Vec<bool>,
+    second_plane: Vec<bool>,
+    planes: u8,
+    audio_pattern: [u8; 16],
+    hires: bool,
+    // bit N set when row N changed since the frontend last drew it
+    dirty_rows: u64,
+    variant: Variant,
+    quirks: Quirks,
+    drew_this_frame: bool,
+    exited: bool,
+    halted: Option<HaltReason>,
+    on_unknown: OpcodePolicy,
+    on_sys: OpcodePolicy,
+    on_out_of_bounds: MemoryPolicy,
+    write_protection: WriteProtection,
+    flags: [u8; 16],
     memory: Vec<u8>,
     stack_memory: Vec<Address>,
+    stack_size: usize,
+    origin: Address,
+    font_overwrite: bool,
     instruction_pointer: Address,
     registers: [u8; 16],
     keys: [bool; 16],
+    key_wait: KeyWait,
     address_register: Address,
     delay_timer: u8,
     sound_timer: u8,
-    keymap: HashMap<Key, u8>
+    program: Vec<u8>,
+    program_hash: u64,
+    tick_count: u64,
+    rng: XorShiftRng,
+    random_source: Option<RandomSource>,
+    breakpoints: BTreeSet<Address>,
+    // bit N set watches VN
+    register_watches: u16,
+    memory_watches: Vec<Range<Address>>,
+    watch_hit: Option<WatchHit>,
+    stepping_over_breakpoint: Option<Address>,
+    hooks: Hooks,
+    ml_call_handler: Option<MlCallHandler>,
+    // the pre-instruction hook already saw the instruction here and paused on it
+    hook_paused_at: Option<Address>,
+    trace_fn: Option<TraceFn>
+}
+
+impl Default for Chip8 {
+    fn default() -> Self {
+        Chip8::new(0x1000, 16)
+    }
 }
 
 impl Chip8 {
-    pub fn new(memory: usize, stack_memory: usize, display_scale: u32, display_color: Color, keymap: HashMap<Key, u8>) -> Self {
+    pub fn new(memory: usize, stack_memory: usize) -> Self {
         Chip8{
-            display: DrawTarget::new(64 * display_scale as i32, 32 * display_scale as i32),
-            display_color,
-            display_scale,
+            display: vec![false; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+            second_plane: vec![false; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+            planes: 1,
+            audio_pattern: [0; 16],
+            hires: false,
+            dirty_rows: u64::MAX,
+            variant: Variant::Chip8,
+            quirks: Quirks::default(),
+            drew_this_frame: false,
+            exited: false,
+            halted: None,
+            on_unknown: OpcodePolicy::Warn,
+            on_sys: OpcodePolicy::Warn,
+            on_out_of_bounds: MemoryPolicy::Error,
+            write_protection: WriteProtection::Off,
+            flags: [0; 16],
             memory: vec![0; memory],
-            stack_memory: vec![0; stack_memory],
+            stack_memory: Vec::with_capacity(stack_memory),
+            stack_size: stack_memory,
+            origin: DEFAULT_ORIGIN,
+            font_overwrite: false,
             registers: [0; 16],
             address_register: 0,
             keys: [false; 16],
-            instruction_pointer: 0x200 as Address,
+            key_wait: KeyWait::Idle,
+            instruction_pointer: DEFAULT_ORIGIN,
             delay_timer: 0,
             sound_timer: 0,
-            keymap
+            program: Vec::new(),
+            program_hash: hash_program(&[]),
+            tick_count: 0,
+            rng: XorShiftRng::default(),
+            random_source: None,
+            breakpoints: BTreeSet::new(),
+            register_watches: 0,
+            memory_watches: Vec::new(),
+            watch_hit: None,
+            stepping_over_breakpoint: None,
+            hooks: Hooks::default(),
+            ml_call_handler: None,
+            hook_paused_at: None,
+            trace_fn: None
         }
     }
 
+    /// Like `new` but with the sizes checked
+    pub fn builder() -> Chip8Builder {
+        Chip8Builder::default()
+    }
+
+    /// Makes `RandWithMask` produce the same sequence on every run
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.set_rng_seed(seed);
+        self
+    }
+
+    pub fn set_rng_seed(&mut self, seed: u64) {
+        self.rng = XorShiftRng::new(seed);
+    }
+
+    /// Takes the bytes for `RandWithMask` from `source` instead of the built in generator, for boards with a hardware RNG.
+    /// Save states and seeds only cover the built in generator.
+    pub fn set_random_source<F: FnMut() -> u8 + Send + 'static>(&mut self, source: F) {
+        self.random_source = Some(Box::new(source));
+    }
+
+    pub fn clear_random_source(&mut self) {
+        self.random_source = None;
+    }
+
+    /// Also switches to the quirks preset of `variant`, call `with_quirks` afterwards to override it
+    pub fn with_variant(mut self, variant: Variant) -> Self {
+        self.variant = variant;
+        self.quirks = Quirks::for_variant(variant);
+        self
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    pub fn with_quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// How opcodes that don't decode, or belong to another variant, are handled
+    pub fn with_unknown_policy(mut self, policy: OpcodePolicy) -> Self {
+        self.on_unknown = policy;
+        self
+    }
+
+    /// How 0NNN machine code calls are handled, some ROMs use them as a no-op
+    pub fn with_sys_policy(mut self, policy: OpcodePolicy) -> Self {
+        self.on_sys = policy;
+        self
+    }
+
+    /// How instructions reading or writing data past the end of memory are handled, instruction fetches always fail
+    pub fn with_memory_policy(mut self, policy: MemoryPolicy) -> Self {
+        self.on_out_of_bounds = policy;
+        self
+    }
+
+    /// Guards the interpreter area below 0x200, or below the origin when a program is loaded lower, against writes by
+    /// instructions. Loading a program is still allowed.
+    pub fn with_write_protection(mut self, protection: WriteProtection) -> Self {
+        self.write_protection = protection;
+        self
+    }
+
+    /// Loads programs at `origin` and starts them there, e.g. 0x600 for ETI-660 programs. Takes effect on the next `load`,
+    /// which fails for origins past the end of memory or below `FONTS_END` unless `with_font_overwrite` allows it.
+    pub fn with_origin(mut self, origin: Address) -> Self {
+        self.origin = origin;
+        self
+    }
+
+    /// Lets `load` put programs over the fonts, for origins below `FONTS_END`
+    pub fn with_font_overwrite(mut self, allowed: bool) -> Self {
+        self.font_overwrite = allowed;
+        self
+    }
+
+    pub fn origin(&self) -> Address {
+        self.origin
+    }
+
+    /// Why the machine halted, if it did
+    pub fn halted(&self) -> Option<&HaltReason> {
+        self.halted.as_ref()
+    }
+
+    /// The display is 64x32, or 128x64 while a SUPER-CHIP program has high resolution mode enabled
     pub fn get_screen_size(&self) -> (usize, usize) {
-        (self.display.width() as usize, self.display.height() as usize)
+        if self.hires {
+            (HIRES_DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT)
+        }else{
+            (DISPLAY_WIDTH, DISPLAY_HEIGHT)
+        }
+    }
+
+    fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        let (width, height) = self.get_screen_size();
+        self.display = vec![false; width * height];
+        self.second_plane = vec![false; width * height];
+        self.dirty_rows = u64::MAX;
+    }
+
+    /// Rows of the display that changed since the last call, bit N for row N. Frontends can redraw just those rows.
+    pub fn take_dirty_rows(&mut self) -> u64 {
+        core::mem::take(&mut self.dirty_rows)
+    }
+
+    /// Pixels of the display in row-major order, `true` for a lit pixel. With XO-CHIP this is the first plane.
+    pub fn get_display(&self) -> &[bool] {
+        &self.display
+    }
+
+    /// Pixels of the second XO-CHIP plane, always blank for the other variants
+    pub fn get_second_plane(&self) -> &[bool] {
+        &self.second_plane
+    }
+
+    /// Palette index of a pixel: 0 when unlit, 1 for the first plane, 2 for the second plane and 3 for both
+    pub fn pixel_color(&self, index: usize) -> usize {
+        self.display[index] as usize | (self.second_plane[index] as usize).shl(1)
+    }
+
+    /// The 16 byte XO-CHIP audio pattern last loaded with F002
+    pub fn audio_pattern(&self) -> &[u8; 16] {
+        &self.audio_pattern
+    }
+
+    /// Stable hash of the display contents, useful for comparing against known good output
+    pub fn display_hash(&self) -> u64 {
+        let (width, height) = self.get_screen_size();
+        let mut bytes = vec![width as u8, height as u8];
+        bytes.extend(self.display.iter().map(|lit| *lit as u8));
+        // only XO-CHIP uses the second plane, leaving it out keeps the other variants' hashes unchanged
+        if self.variant == Variant::XoChip {
+            bytes.extend(self.second_plane.iter().map(|lit| *lit as u8));
+        }
+        hash_program(&bytes)
+    }
+
+    fn selected_planes_mut(&mut self) -> Vec<&mut Vec<bool>> {
+        let planes = self.planes;
+        [&mut self.display, &mut self.second_plane].into_iter()
+            .enumerate()
+            .filter(|(i, _)| planes.shr(*i as u8).bitand(1) == 1)
+            .map(|(_, plane)| plane)
+            .collect()
+    }
+
+    fn get_instruction(&self, address: u16) -> Result<RawInstruction, Chip8Error> {
+        let range = self.memory_range(address as usize, 2)?;
+        Ok((self.memory[range.start], self.memory[range.start + 1]).into())
+    }
+
+    fn memory_range(&self, start: usize, len: usize) -> Result<Range<usize>, Chip8Error> {
+        if start + len > self.memory.len() {
+            Err(Chip8Error::MemoryOutOfBounds { addr: start.max(self.memory.len()) })
+        }else{
+            Ok(start..start + len)
+        }
+    }
+
+    pub fn pc(&self) -> Address {
+        self.instruction_pointer
+    }
+
+    pub fn i(&self) -> Address {
+        self.address_register
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.registers
+    }
+
+    pub fn stack(&self) -> &[Address] {
+        &self.stack_memory
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    /// Overwrites VX, for debuggers. Registers past F are ignored.
+    pub fn set_register(&mut self, register: u8, value: u8) {
+        if let Some(slot) = self.registers.get_mut(register as usize) {
+            *slot = value;
+        }
     }
 
-    pub fn get_screen_buffer(&self) -> &[u32] {
-        self.display.get_data()
+    pub fn peek_next_instruction(&self) -> Option<Instruction> {
+        let next = self.get_instruction(self.instruction_pointer.wrapping_add(2)).ok();
+        self.get_instruction(self.instruction_pointer).ok().and_then(|instruction| Instruction::decode_long(instruction, next))
     }
 
-    fn get_instruction_mut(&mut self, address: u16) -> (&mut u8, &mut u8){
-        let (lower, upper) = self.memory.split_at_mut(address as usize + 1);
-        (lower.last_mut().unwrap(), upper.first_mut().unwrap())
+    /// Size of the instruction a skip jumps over, two words if it is XO-CHIP's `F000 NNNN`
+    fn skipped_size(&self, address: Address) -> Address {
+        let long = self.variant == Variant::XoChip && self.get_instruction(address) == Ok(RawInstruction(0xF000));
+        if long {4} else {2}
     }
 
-    fn get_instruction(&self, address: u16) -> RawInstruction {
-        (self.memory[address as usize], self.memory[address as usize + 1])
+    /// Copies `program` to memory at the origin and resets the machine to run it.
+    /// An empty program or one that doesn't fit is rejected, leaving the machine as it was.
+    pub fn load(&mut self, program: &[u8]) -> Result<(), Chip8Error> {
+        if program.is_empty() {
+            return Err(Chip8Error::EmptyProgram);
+        }
+        if self.origin < FONTS_END && !self.font_overwrite {
+            return Err(Chip8Error::OriginOverlapsFonts { origin: self.origin });
+        }
+        let available = self.memory.len().saturating_sub(self.origin as usize);
+        if program.len() > available {
+            return Err(Chip8Error::ProgramTooLarge { size: program.len(), available });
+        }
+        self.load_unchecked(program);
+        Ok(())
     }
 
-    pub fn load(&mut self, program: &[u8]) {
+    fn load_unchecked(&mut self, program: &[u8]) {
         self.memory = vec![0; self.memory.len()];
-        for (i, b) in program.iter().enumerate() {
-            program.iter().enumerate().for_each(|(i, v)|self.memory[0x200 + i] = *v)
+        // the fonts go in first so a program loaded over them on purpose wins rather than the other way around
+        SPRITES.iter().flatten().enumerate().for_each(|(i, b)|self.memory[FONT_ADDRESS as usize + i] = *b);
+        BIG_SPRITES.iter().flatten().enumerate().for_each(|(i, b)|self.memory[BIG_SPRITES_ADDRESS as usize + i] = *b);
+        let origin = self.origin as usize;
+        // only empty when reset before anything was loaded
+        if let Some(target) = self.memory.get_mut(origin..origin + program.len()) {
+            target.copy_from_slice(program);
         }
         self.address_register = 0;
-        self.instruction_pointer = 0x200;
+        self.registers = [0; 16];
+        self.keys = [false; 16];
+        self.key_wait = KeyWait::Idle;
+        self.stack_memory.clear();
+        self.instruction_pointer = self.origin;
         self.delay_timer = 0;
         self.sound_timer = 0;
-        self.display.clear(SolidSource::from(Color::new(255, 0, 0, 0)));
-        SPRITES.iter().flatten().enumerate().for_each(|(i, b)|self.memory[i] = *b);
+        self.set_hires(false);
+        self.planes = 1;
+        self.audio_pattern = [0; 16];
+        self.exited = false;
+        self.halted = None;
+        self.drew_this_frame = false;
+        self.stepping_over_breakpoint = None;
+        self.hook_paused_at = None;
+        self.watch_hit = None;
+        self.program_hash = hash_program(program);
+        self.program = program.to_vec();
+        self.tick_count = 0;
     }
 
-    pub fn tick(&mut self) {
-    let to_execute = self.get_instruction(self.instruction_pointer);
-    match Instruction::decode(to_execute) {
-            None => error!("Unknown instruction encountered {:02x}{:02x}", to_execute.0, to_execute.1),
-            Some(instruction) => self.execute(&instruction)
-        }
-        self.instruction_pointer += 2;
+    /// Restarts the loaded program from a clean machine, undoing any changes it made to memory
+    pub fn reset(&mut self) {
+        let program = core::mem::take(&mut self.program);
+        self.load_unchecked(&program);
     }
 
-    pub fn decrement_time(&mut self){
-        self.delay_timer = self.delay_timer.checked_sub(1).unwrap_or(0)
+    pub fn add_breakpoint(&mut self, address: Address) {
+        self.breakpoints.insert(address);
     }
 
-    pub fn set_pressed(&mut self, key: &Key, pressed: bool){
-        if let Some(v) = self.keymap.get(key) {
-            self.keys[*v as usize] = pressed;
-        }
+    pub fn remove_breakpoint(&mut self, address: Address) {
+        self.breakpoints.remove(&address);
     }
 
-    fn execute(&mut self, instruction: &Instruction) {
-        match instruction {
-            Instruction::ExecSubroutineML(_) => warn!("Not implemented {:?}", instruction),
-            Instruction::ClearScreen => {
-                self.display.clear(SolidSource::from(Color::new(255, 0, 0, 0)));
-            },
-            Instruction::ReturnFromSubroutine => {
-                self.instruction_pointer = self.stack_memory.pop().expect("Popped from empty stack");
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    /// Stops with `TickStatus::WatchHit` after an instruction changes the value of register `register`
+    pub fn add_register_watch(&mut self, register: u8) {
+        self.register_watches |= 1u16.shl(register.bitand(0xf));
+    }
+
+    /// Stops with `TickStatus::WatchHit` after an instruction writes to memory in `range`, even if the value stays the same
+    pub fn add_memory_watch(&mut self, range: Range<Address>) {
+        self.memory_watches.push(range);
+    }
+
+    pub fn clear_watches(&mut self) {
+        self.register_watches = 0;
+        self.memory_watches.clear();
+    }
+
+    /// Calls `trace_fn` with every instruction before it is executed
+    pub fn set_trace_fn<F: FnMut(&TraceEvent) + Send + 'static>(&mut self, trace_fn: F) {
+        self.trace_fn = Some(Box::new(trace_fn));
+    }
+
+    pub fn clear_trace_fn(&mut self) {
+        self.trace_fn = None;
+    }
+
+    /// Calls `hook` with the PC, the instruction and the machine right before each instruction executes
+    pub fn set_pre_instruction_hook<F: FnMut(Address, &Instruction, &Chip8) -> HookAction + Send + 'static>(&mut self, hook: F) {
+        self.hooks.pre_instruction = Some(Box::new(hook));
+    }
+
+    /// Calls `hook` with the PC of every instruction that changed the display, after it ran
+    pub fn set_draw_hook<F: FnMut(Address, &Chip8) -> HookAction + Send + 'static>(&mut self, hook: F) {
+        self.hooks.draw = Some(Box::new(hook));
+    }
+
+    /// Calls `hook` with the PC and opcode of every instruction that doesn't decode, before `on_unknown` decides what happens
+    pub fn set_unknown_instruction_hook<F: FnMut(Address, RawInstruction, &Chip8) -> HookAction + Send + 'static>(&mut self, hook: F) {
+        self.hooks.unknown_instruction = Some(Box::new(hook));
+    }
+
+    pub fn clear_hooks(&mut self) {
+        self.hooks = Hooks::default();
+    }
+
+    /// Runs `handler` for every 0NNN call with the called address, in place of the sys `OpcodePolicy` for the calls it handles.
+    /// Test ROMs can use it for syscalls, this one logs the registers on `SYS 0xFF0` and stops on `SYS 0xFFF`:
+    ///
+    /// ```
+    /// use chip8_rs_dmfg::chip8::{Chip8, MlCallResult};
+    ///
+    /// let mut chip = Chip8::default();
+    /// chip.set_ml_call_handler(|state, address| match address {
+    ///     0xff0 => {
+    ///         println!("{:02x?}", state.registers());
+    ///         MlCallResult::Continue
+    ///     }
+    ///     0xfff => MlCallResult::Halt,
+    ///     _ => MlCallResult::Unhandled
+    /// });
+    /// ```
+    pub fn set_ml_call_handler<F: FnMut(&mut Chip8StateView, Address) -> MlCallResult + Send + 'static>(&mut self, handler: F) {
+        self.ml_call_handler = Some(Box::new(handler));
+    }
+
+    pub fn clear_ml_call_handler(&mut self) {
+        self.ml_call_handler = None;
+    }
+
+    /// Runs one instruction, see `step` for details on what it did
+    pub fn tick(&mut self) -> Result<TickStatus, Chip8Error> {
+        self.step().map(|outcome| outcome.status)
+    }
+
+    /// Runs one instruction unless the machine exited or halted, a breakpoint is hit or the display wait quirk holds it back
+    pub fn step(&mut self) -> Result<StepOutcome, Chip8Error> {
+        if self.exited {
+            return Ok(self.idle_outcome(TickStatus::Exited));
+        }
+        if self.halted.is_some() {
+            return Ok(self.idle_outcome(TickStatus::Halted));
+        }
+        let pc = self.instruction_pointer;
+        if self.breakpoints.contains(&pc) && self.stepping_over_breakpoint.take() != Some(pc) {
+            self.stepping_over_breakpoint = Some(pc);
+            return Ok(self.idle_outcome(TickStatus::BreakpointHit(pc)));
+        }
+        let to_execute = match self.get_instruction(pc) {
+            Ok(opcode) => opcode,
+            // halting rather than failing keeps whatever the program drew on screen
+            Err(_) => {
+                self.halted = Some(HaltReason::PcOutOfBounds(pc));
+                return Ok(self.idle_outcome(TickStatus::Halted));
             }
-            Instruction::JumpToAddress(addr) => {
-                self.instruction_pointer = *addr;
-                self.instruction_pointer -= 2;
+        };
+        let next = self.get_instruction(self.instruction_pointer.wrapping_add(2)).ok();
+        let decoded = match Instruction::decode_long(to_execute, next) {
+            Some(instruction) if instruction.is_schip() && self.variant == Variant::Chip8 => None,
+            Some(instruction) if instruction.is_xochip() && self.variant != Variant::XoChip => None,
+            decoded => decoded
+        };
+        let (instruction, effects) = match decoded {
+            None => return Ok(self.unknown_instruction(to_execute, pc)),
+            // with a handler the call runs first, see `execute`
+            Some(Instruction::ExecSubroutineML(_)) if self.on_sys == OpcodePolicy::Halt && self.ml_call_handler.is_none() => {
+                self.halted = Some(HaltReason::Rejected(Chip8Error::UnknownInstruction { opcode: to_execute, pc }));
+                return Ok(self.idle_outcome(TickStatus::Halted));
             }
-            Instruction::ExecSubroutine(addr) => {
-                self.stack_memory.push(self.instruction_pointer);
-                self.instruction_pointer = *addr;
-                self.instruction_pointer -= 2;
+            // only a literal self jump or self call, BNNN lands on itself just as often but V0 can change and send it elsewhere.
+            // A call to itself would otherwise only stop once the stack overflows.
+            Some(Instruction::JumpToAddress(address) | Instruction::ExecSubroutine(address)) if address == pc => {
+                self.halted = Some(HaltReason::SelfJump(pc));
+                return Ok(self.idle_outcome(TickStatus::Halted));
             }
-            Instruction::SkipFollowingIfRegEq(reg0, value) => {
-                if self.registers[*reg0 as usize] == *value {
-                    self.instruction_pointer += 2
-                }
+            Some(Instruction::DrawSprite(..)) if self.quirks.display_wait && self.drew_this_frame => {
+                return Ok(self.idle_outcome(TickStatus::WaitingForFrame));
             }
-            Instruction::SkipFollowingIfRegNeq(reg0, value) => {
-                if self.registers[*reg0 as usize] != *value {
-                    self.instruction_pointer += 2
+            Some(instruction) => {
+                // the hook is taken out while it runs so it can look at the machine
+                if let Some(mut hook) = self.hooks.pre_instruction.take() {
+                    let action = if self.hook_paused_at.take() == Some(pc) {HookAction::Continue} else {hook(pc, &instruction, self)};
+                    self.hooks.pre_instruction = Some(hook);
+                    if action == HookAction::Pause {
+                        self.hook_paused_at = Some(pc);
+                        return Ok(self.idle_outcome(TickStatus::HookPaused(pc)));
+                    }
                 }
-            }
-            Instruction::SkipFollowingIfRegEqReg(reg0, reg1) => {
-                if self.registers[*reg0 as usize] == self.registers[*reg1 as usize] {
-                    self.instruction_pointer += 2
+                if let Some(trace_fn) = self.trace_fn.as_mut() {
+                    trace_fn(&TraceEvent { pc, opcode: to_execute, instruction: &instruction, registers: &self.registers });
                 }
+                // registers are compared before and after rather than checked on every write, and only while watched
+                let registers_before = (self.register_watches != 0).then_some(self.registers);
+                let effects = self.execute(&instruction)?;
+                if let Some(before) = registers_before {
+                    self.check_register_watches(&before);
+                }
+                (instruction, effects)
             }
-            Instruction::StoreToReg(reg0, value) => {
-                self.registers[*reg0 as usize] = *value
-            }
-            Instruction::AddToReg(reg0, value) => {
-                let (new_value, _) = self.registers[*reg0 as usize].overflowing_add(*value);
-                self.registers[*reg0 as usize] = new_value
-            }
-            Instruction::MoveValue(reg0, reg1) => {
-                self.registers[*reg0 as usize] = self.registers[*reg1 as usize]
+        };
+        self.tick_count += 1;
+        let next = pc.wrapping_add(instruction.size() as Address);
+        self.instruction_pointer = match effects.flow {
+            ControlFlow::Next => next,
+            ControlFlow::Skip => next.wrapping_add(self.skipped_size(next)),
+            ControlFlow::Jump(address) => address,
+            ControlFlow::Wait => pc
+        };
+        let draw_action = match self.hooks.draw.take() {
+            Some(mut hook) if effects.display_changed => {
+                let action = hook(pc, self);
+                self.hooks.draw = Some(hook);
+                action
             }
-            Instruction::OrRegister(reg0, reg1) => {
-                self.registers[*reg0 as usize] = self.registers[*reg0 as usize].bitor(self.registers[*reg1 as usize])
+            hook => {
+                self.hooks.draw = hook;
+                HookAction::Continue
             }
-            Instruction::AndRegister(reg0, reg1) => {
+        };
+        let status = match self.watch_hit.take() {
+            _ if self.exited => TickStatus::Exited,
+            _ if self.halted.is_some() => TickStatus::Halted,
+            Some(hit) => TickStatus::WatchHit(hit),
+            None if draw_action == HookAction::Pause => TickStatus::HookPaused(pc),
+            None => TickStatus::Executed
+        };
+        Ok(StepOutcome {
+            status,
+            instruction: Some(instruction),
+            pc_before: pc,
+            pc_after: self.instruction_pointer,
+            display_changed: effects.display_changed,
+            waiting_for_key: effects.flow == ControlFlow::Wait,
+            sound_playing: self.sound_timer > 0
+        })
+    }
+
+    fn check_register_watches(&mut self, before: &[u8; 16]) {
+        let changed = (0..16u8).find(|reg| {
+            self.register_watches.shr(reg).bitand(1) == 1 && before[*reg as usize] != self.registers[*reg as usize]
+        });
+        if let (None, Some(register)) = (self.watch_hit, changed) {
+            self.watch_hit = Some(WatchHit::Register { register, old: before[register as usize], new: self.registers[register as usize] });
+        }
+    }
+
+    /// Addresses of the `len` bytes from `start`, wrapping around or failing past the end of memory as `on_out_of_bounds` says
+    fn data_addresses(&self, start: usize, len: usize) -> Result<Vec<usize>, Chip8Error> {
+        match self.on_out_of_bounds {
+            MemoryPolicy::Wrap => Ok((start..start + len).map(|address| address % self.memory.len()).collect()),
+            MemoryPolicy::Error => self.memory_range(start, len).map(|range| range.collect())
+        }
+    }
+
+    /// Reads `len` bytes of data from `address`, every memory read an instruction does goes through here
+    fn read_memory(&self, address: usize, len: usize) -> Result<Vec<u8>, Chip8Error> {
+        Ok(self.data_addresses(address, len)?.into_iter().map(|read| self.memory[read]).collect())
+    }
+
+    /// Copies `bytes` to memory at `address`, every memory write an instruction does goes through here for the watches
+    fn write_memory(&mut self, address: usize, bytes: &[u8]) -> Result<(), Chip8Error> {
+        let mut writes = self.data_addresses(address, bytes.len())?.into_iter().zip(bytes.iter().copied()).collect::<Vec<(usize, u8)>>();
+        let protected_end = self.origin.min(DEFAULT_ORIGIN) as usize;
+        if let Some((protected, _)) = writes.iter().find(|(written, _)| *written < protected_end) {
+            let pc = self.instruction_pointer;
+            match self.write_protection {
+                WriteProtection::Off => {}
+                WriteProtection::Ignore => {
+                    warn!("Ignoring write to protected memory at 0x{:04x} by the instruction at 0x{:04x}", protected, pc);
+                    writes.retain(|(written, _)| *written >= protected_end);
+                }
+                WriteProtection::Error => return Err(Chip8Error::ProtectedWrite { addr: *protected, pc })
+            }
+        }
+        if self.watch_hit.is_none() && !self.memory_watches.is_empty() {
+            let watched = writes.iter().find(|(written, _)| {
+                self.memory_watches.iter().any(|watch| watch.contains(&(*written as Address)))
+            });
+            if let Some((written, new)) = watched {
+                self.watch_hit = Some(WatchHit::Memory { address: *written as Address, old: self.memory[*written], new: *new });
+            }
+        }
+        writes.into_iter().for_each(|(written, byte)| self.memory[written] = byte);
+        Ok(())
+    }
+
+    fn increment_i_after_load_store(&mut self, last_register: u8) {
+        let increment = match self.quirks.load_store_increment {
+            IndexIncrement::XPlusOne => last_register as Address + 1,
+            IndexIncrement::X => last_register as Address,
+            IndexIncrement::None => 0
+        };
+        self.address_register = self.address_register.wrapping_add(increment).bitand(self.address_mask());
+    }
+
+    /// I is 12 bits wide, except on XO-CHIP which can address 64 KiB
+    fn address_mask(&self) -> Address {
+        if self.variant == Variant::XoChip {0xFFFF} else {0x0FFF}
+    }
+
+    /// Applies `on_unknown` to an opcode that can't run, skipping over it unless the policy halts
+    fn unknown_instruction(&mut self, opcode: RawInstruction, pc: Address) -> StepOutcome {
+        let action = match self.hooks.unknown_instruction.take() {
+            Some(mut hook) => {
+                let action = hook(pc, opcode, self);
+                self.hooks.unknown_instruction = Some(hook);
+                action
+            }
+            None => HookAction::Continue
+        };
+        let error = Chip8Error::UnknownInstruction { opcode, pc };
+        match self.on_unknown {
+            OpcodePolicy::Halt => {
+                self.halted = Some(HaltReason::Rejected(error));
+                return self.idle_outcome(TickStatus::Halted);
+            }
+            OpcodePolicy::Warn => warn!("Skipping {}", error),
+            OpcodePolicy::Ignore => {}
+        }
+        self.tick_count += 1;
+        self.instruction_pointer = self.instruction_pointer.wrapping_add(2);
+        let status = if action == HookAction::Pause {TickStatus::HookPaused(pc)} else {TickStatus::Executed};
+        StepOutcome {
+            pc_before: pc,
+            ..self.idle_outcome(status)
+        }
+    }
+
+    fn idle_outcome(&self, status: TickStatus) -> StepOutcome {
+        StepOutcome {
+            status,
+            instruction: None,
+            pc_before: self.instruction_pointer,
+            pc_after: self.instruction_pointer,
+            display_changed: false,
+            waiting_for_key: false,
+            sound_playing: self.sound_timer > 0
+        }
+    }
+
+    /// Number of instructions executed since the program was loaded
+    pub fn tick_count(&self) -> u64 {
+        self.tick_count
+    }
+
+    /// Hash of the loaded program, see `save_state::hash_program`
+    pub fn program_hash(&self) -> u64 {
+        self.program_hash
+    }
+
+    /// Runs up to `instructions` instructions and then counts the timers down, one 60 Hz frame for frontends that
+    /// don't interleave anything else with the instructions. The frame ends early for the display wait quirk and
+    /// for FX0A waiting on a key. Breakpoints, watches, exits and halts end it too but leave the timers alone,
+    /// as the machine isn't expected to keep running.
+    pub fn run_frame(&mut self, instructions: u32) -> Result<FrameResult, Chip8Error> {
+        self.run_frame_costed(instructions, |_| 1)
+    }
+
+    /// `run_frame` with each instruction using up `cost` of `budget` instead of 1, e.g. `Instruction::cycle_cost` to
+    /// run at the COSMAC VIP's pace. The frame ends once the instructions cost `budget` or more.
+    pub fn run_frame_costed(&mut self, budget: u32, mut cost: impl FnMut(&Instruction) -> u32) -> Result<FrameResult, Chip8Error> {
+        let mut result = FrameResult::default();
+        while result.cycles < budget {
+            let outcome = self.step()?;
+            result.display_changed |= outcome.display_changed;
+            if let Some(instruction) = outcome.instruction {
+                result.instructions += 1;
+                result.cycles = result.cycles.saturating_add(cost(&instruction));
+                result.last_instruction = Some((outcome.pc_before, instruction));
+            }
+            match outcome.status {
+                TickStatus::Executed if outcome.waiting_for_key => {
+                    result.waiting_for_key = true;
+                    break;
+                }
+                TickStatus::Executed => {}
+                TickStatus::WaitingForFrame => {
+                    result.stopped_by = Some(TickStatus::WaitingForFrame);
+                    break;
+                }
+                status => {
+                    result.stopped_by = Some(status);
+                    return Ok(result);
+                }
+            }
+        }
+        result.sound_active = self.is_sound_active();
+        self.decrement_time();
+        Ok(result)
+    }
+
+    /// Called once per 60 Hz frame, also ends the frame for the display wait quirk
+    pub fn decrement_time(&mut self){
+        self.drew_this_frame = false;
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+    }
+
+    /// The buzzer sounds for as long as the sound timer is above zero
+    pub fn is_sound_active(&self) -> bool {
+        self.sound_timer > 0
+    }
+
+    /// Which of the 16 keys are currently held down
+    pub fn keys(&self) -> &[bool; 16] {
+        &self.keys
+    }
+
+    /// Lets go of every key without completing a pending FX0A, for when releases can't be seen,
+    /// like while the window is in the background
+    pub fn release_all_keys(&mut self) {
+        self.keys = [false; 16];
+        if let KeyWait::Pressed(_) = self.key_wait {
+            self.key_wait = KeyWait::Waiting;
+        }
+    }
+
+    /// Presses or releases hex key `key`, 0 to F. Mapping physical keys or buttons onto these is up to the frontend,
+    /// a key past F is a bug in that mapping, debug builds panic on it and release builds ignore it.
+    pub fn set_key_state(&mut self, key: u8, pressed: bool){
+        debug_assert!(key <= 0xF, "key 0x{:X} is past the hex keypad", key);
+        if let Some(state) = self.keys.get_mut(key as usize) {
+            let was_pressed = core::mem::replace(state, pressed);
+            self.key_wait = match self.key_wait {
+                KeyWait::Waiting if pressed && !was_pressed => KeyWait::Pressed(key),
+                KeyWait::Pressed(waited) if waited == key && !pressed => KeyWait::Released(key),
+                key_wait => key_wait
+            };
+        }
+    }
+
+    fn execute(&mut self, instruction: &Instruction) -> Result<ExecuteEffects, Chip8Error> {
+        let mut effects = ExecuteEffects::default();
+        match instruction {
+            Instruction::ExecSubroutineML(address) => {
+                let result = match self.ml_call_handler.as_mut() {
+                    Some(handler) => {
+                        let mut state = Chip8StateView {
+                            registers: &mut self.registers,
+                            memory: &self.memory,
+                            address_register: self.address_register,
+                            pc: self.instruction_pointer
+                        };
+                        handler(&mut state, *address)
+                    }
+                    None => MlCallResult::Unhandled
+                };
+                match result {
+                    MlCallResult::Continue => {}
+                    MlCallResult::Halt => {
+                        self.halted = Some(HaltReason::MlCall(*address));
+                        effects.flow = ControlFlow::Wait;
+                    }
+                    MlCallResult::Unhandled => match self.on_sys {
+                        OpcodePolicy::Ignore => {}
+                        OpcodePolicy::Warn => warn!("Skipping machine code call {}", instruction),
+                        OpcodePolicy::Halt => {
+                            let error = Chip8Error::UnknownInstruction { opcode: instruction.encode(), pc: self.instruction_pointer };
+                            self.halted = Some(HaltReason::Rejected(error));
+                            effects.flow = ControlFlow::Wait;
+                        }
+                    }
+                }
+            },
+            Instruction::ClearScreen => {
+                self.selected_planes_mut().into_iter().for_each(|plane| plane.fill(false));
+                self.dirty_rows = u64::MAX;
+                effects.display_changed = true;
+            },
+            Instruction::ReturnFromSubroutine => {
+                // the stack holds the address of the call, execution continues after it
+                let call = self.stack_memory.pop().ok_or(Chip8Error::StackUnderflow)?;
+                effects.flow = ControlFlow::Jump(call.wrapping_add(2));
+            }
+            Instruction::JumpToAddress(addr) => {
+                effects.flow = ControlFlow::Jump(*addr);
+            }
+            Instruction::ExecSubroutine(addr) => {
+                if self.stack_memory.len() >= self.stack_size {
+                    return Err(Chip8Error::StackOverflow);
+                }
+                self.stack_memory.push(self.instruction_pointer);
+                effects.flow = ControlFlow::Jump(*addr);
+            }
+            Instruction::SkipFollowingIfRegEq(reg0, value) => {
+                if self.registers[*reg0 as usize] == *value {
+                    effects.flow = ControlFlow::Skip;
+                }
+            }
+            Instruction::SkipFollowingIfRegNeq(reg0, value) => {
+                if self.registers[*reg0 as usize] != *value {
+                    effects.flow = ControlFlow::Skip;
+                }
+            }
+            Instruction::SkipFollowingIfRegEqReg(reg0, reg1) => {
+                if self.registers[*reg0 as usize] == self.registers[*reg1 as usize] {
+                    effects.flow = ControlFlow::Skip;
+                }
+            }
+            Instruction::StoreToReg(reg0, value) => {
+                self.registers[*reg0 as usize] = *value
+            }
+            Instruction::AddToReg(reg0, value) => {
+                let (new_value, _) = self.registers[*reg0 as usize].overflowing_add(*value);
+                self.registers[*reg0 as usize] = new_value
+            }
+            Instruction::MoveValue(reg0, reg1) => {
+                self.registers[*reg0 as usize] = self.registers[*reg1 as usize]
+            }
+            Instruction::OrRegister(reg0, reg1) => {
+                self.registers[*reg0 as usize] = self.registers[*reg0 as usize].bitor(self.registers[*reg1 as usize])
+            }
+            Instruction::AndRegister(reg0, reg1) => {
                 self.registers[*reg0 as usize] = self.registers[*reg0 as usize].bitand(self.registers[*reg1 as usize])
             }
             Instruction::XorRegister(reg0, reg1) => {
                 self.registers[*reg0 as usize] = self.registers[*reg0 as usize].bitxor(self.registers[*reg1 as usize])
             }
+            // the 8XY4-8XYE handlers read both operands before writing anything and write VF last,
+            // so VF holds only the flag when it is also VX and VX == VY sees the unmodified value
             Instruction::AddWithCarry(reg0, reg1) => {
                 let (new_value, overflow) = self.registers[*reg0 as usize].overflowing_add(self.registers[*reg1 as usize]);
                 self.registers[*reg0 as usize] = new_value;
-                if overflow {
-                    self.registers[0x0F] = 1
-                }else{
-                    self.registers[0x0F] = 0
-                }
+                self.registers[0xF] = overflow as u8;
             }
             Instruction::SubWithCarry(reg0, reg1) => {
                 let (new_value, overflow) = self.registers[*reg0 as usize].overflowing_sub(self.registers[*reg1 as usize]);
                 self.registers[*reg0 as usize] = new_value;
-                if overflow {
-                    self.registers[0x0F] = 0
-                }else{
-                    self.registers[0x0F] = 1
-                }
+                self.registers[0xF] = !overflow as u8;
             }
             Instruction::ShiftRight(reg0, reg1) => {
-                let lsb = self.registers[*reg1 as usize].bitand(0b1);
-                self.registers[*reg0 as usize] = self.registers[*reg1 as usize].shr(1);
-                self.registers[0xF] = lsb;
+                let source = if self.quirks.shift_in_place {reg0} else {reg1};
+                let value = self.registers[*source as usize];
+                self.registers[*reg0 as usize] = value.shr(1);
+                self.registers[0xF] = value.bitand(0b1);
             }
             Instruction::SubWithCarry2(reg0, reg1) => {
                 let (new_value, overflow) = self.registers[*reg1 as usize].overflowing_sub(self.registers[*reg0 as usize]);
                 self.registers[*reg0 as usize] = new_value;
-                if overflow {
-                    self.registers[0xF] = 0
-                }else{
-                    self.registers[0xF] = 1
-                }
+                self.registers[0xF] = !overflow as u8;
             }
             Instruction::ShiftLeft(reg0, reg1) => {
-                let msb = self.registers[*reg1 as usize].shr(7);
-                self.registers[*reg0 as usize] = self.registers[*reg1 as usize].shl(1);
-                self.registers[0xF] = msb
+                let source = if self.quirks.shift_in_place {reg0} else {reg1};
+                let value = self.registers[*source as usize];
+                self.registers[*reg0 as usize] = value.shl(1);
+                self.registers[0xF] = value.shr(7);
             }
             Instruction::SkipIfNE(reg0, reg1) => {
                 if self.registers[*reg0 as usize] != self.registers[*reg1 as usize] {
-                    self.instruction_pointer += 2
+                    effects.flow = ControlFlow::Skip;
                 }
             }
             Instruction::StoreAddressToI(addr) => {
                 self.address_register = *addr;
             }
             Instruction::JumpWithOffset(addr) => {
-                self.instruction_pointer = *addr + self.registers[0] as u16;
-                self.instruction_pointer -= 2;
+                // the X of BXNN is the top nibble of the address
+                let offset_register = if self.quirks.jump_with_vx {addr.shr(8u16).bitand(0xf) as usize} else {0};
+                effects.flow = ControlFlow::Jump(addr.wrapping_add(self.registers[offset_register] as u16));
             }
             Instruction::RandWithMask(reg0, mask) => {
-                self.registers[*reg0 as usize] = thread_rng().gen::<u8>().bitand(mask)
+                let random = match self.random_source.as_mut() {
+                    Some(source) => source(),
+                    None => self.rng.next_u8()
+                };
+                self.registers[*reg0 as usize] = random.bitand(mask)
             }
             Instruction::DrawSprite(reg0, reg1, len) => {
-                let x = self.registers[*reg0 as usize];
-                let y = self.registers[*reg1 as usize];
+                let (width, height) = self.get_screen_size();
+                // the start position always wraps around, the part of the sprite past the edge wraps or is clipped per quirk
+                let x = self.registers[*reg0 as usize] as usize % width;
+                let y = self.registers[*reg1 as usize] as usize % height;
+                let wrap = self.quirks.sprite_edges == SpriteEdge::Wrap;
                 let sprite_address = self.address_register;
+                self.drew_this_frame = true;
+                effects.display_changed = true;
                 info!("Drawing sprite at address {:x} to {}, {}", sprite_address, x, y);
-                let sprite_data = (sprite_address..(sprite_address + *len as u16))
-                    .map(|address| self.memory[address as usize]).collect::<Vec<u8>>();
-                for (row_num, row) in sprite_data.iter().enumerate() {
-                    let mut row_bits: u8 = (*row);
-                    let mut column_off = 0;
-                    while column_off < 8 {
-                        if row_bits.shr(7) == 1u8 {
-                            self.display.fill_rect(
-                                ((x + column_off) as u32 * self.display_scale) as f32,
-                                ((y.overflowing_add(row_num as u8).0) as u32 * self.display_scale) as f32,
-                                self.display_scale as f32,
-                                self.display_scale as f32,
-                                &Source::Solid(SolidSource::from(self.display_color)),
-                                &DrawOptions::default()
-                            );
-                        }else{
-                            self.display.fill_rect(
-                                ((x + column_off) as u32 * self.display_scale) as f32,
-                                ((y.overflowing_add(row_num as u8).0) as u32 * self.display_scale) as f32,
-                                self.display_scale as f32,
-                                self.display_scale as f32,
-                                &Source::Solid(SolidSource::from_unpremultiplied_argb(255, 0, 0, 0)),
-                                &DrawOptions::default()
-                            );
+                // DXY0 draws a 16x16 sprite on SUPER-CHIP and XO-CHIP, two bytes per row
+                let (sprite_width, rows) = if *len == 0 && self.variant != Variant::Chip8 {(16, 16)} else {(8, *len as usize)};
+                let sprite_len = rows * sprite_width / 8;
+                // with both XO-CHIP planes selected the sprite for the second plane follows the one for the first
+                let plane_count = self.planes.count_ones() as usize;
+                let sprite_data = self.read_memory(sprite_address as usize, sprite_len * plane_count)?;
+                (y..y + rows).filter(|row| wrap || *row < height).for_each(|row| self.dirty_rows |= 1u64.shl(row % height));
+                let mut collision = false;
+                for (plane, plane_data) in self.selected_planes_mut().into_iter().zip(sprite_data.chunks(sprite_len.max(1))) {
+                    for (row_num, row) in plane_data.chunks(sprite_width / 8).enumerate() {
+                        let row_bits = row.iter().fold(0u16, |bits, byte| bits.shl(8) | *byte as u16);
+                        let row_y = y + row_num;
+                        if row_y >= height && !wrap {
+                            break;
+                        }
+                        for column_off in 0..sprite_width {
+                            let column_x = x + column_off;
+                            if column_x >= width && !wrap {
+                                break;
+                            }
+                            if row_bits.shr(sprite_width - 1 - column_off).bitand(1) == 1 {
+                                // pixels are XORed on, VF reports whether any lit pixel was turned off
+                                let pixel = &mut plane[row_y % height * width + column_x % width];
+                                collision |= *pixel;
+                                *pixel = !*pixel;
+                            }
                         }
-                        column_off += 1;
-                        row_bits = row_bits.shl(1)
                     }
                 }
-
+                self.registers[0xF] = collision as u8;
             }
+            // like the COSMAC VIP only the low nibble of VX selects the key
             Instruction::SkipIfKeyPressed(reg0) => {
-                if self.keys[self.registers[*reg0 as usize] as usize] {
-                    self.instruction_pointer += 2;
+                if self.keys[self.registers[*reg0 as usize].bitand(0x0f) as usize] {
+                    effects.flow = ControlFlow::Skip;
                 }
             }
             Instruction::SkipIfKeyNotPressed(reg0) => {
-                if !self.keys[self.registers[*reg0 as usize] as usize] {
-                    self.instruction_pointer += 2;
+                if !self.keys[self.registers[*reg0 as usize].bitand(0x0f) as usize] {
+                    effects.flow = ControlFlow::Skip;
                 }
             }
             Instruction::ReadDelayTimer(reg0) => {
                 self.registers[*reg0 as usize] = self.delay_timer
             }
             Instruction::WaitForKey(reg0) => {
-                let pressed = self.keys.iter().enumerate().filter(|(key, x)|**x).map(|(key, _)| key).collect::<Vec<usize>>();
-                if let Some(key) = pressed.first() {
-                    self.registers[*reg0 as usize] = *key as u8
+                // like the COSMAC VIP, keys already held when the wait starts don't count and the key is taken on release
+                if let KeyWait::Released(key) = self.key_wait {
+                    self.registers[*reg0 as usize] = key;
+                    self.key_wait = KeyWait::Idle;
                 }else{
-                    self.instruction_pointer -= 2;
+                    if self.key_wait == KeyWait::Idle {
+                        self.key_wait = KeyWait::Waiting;
+                    }
+                    effects.flow = ControlFlow::Wait;
                 }
-
             }
             Instruction::WriteDelayTimer(reg0) => {
                 self.delay_timer = self.registers[*reg0 as usize]
@@ -277,38 +1414,1354 @@ impl Chip8 {
                 self.sound_timer = self.registers[*reg0 as usize]
             }
             Instruction::IncrementIWithReg(reg0) => {
-                self.address_register += self.registers[*reg0 as usize] as u16
+                let sum = self.address_register as u32 + self.registers[*reg0 as usize] as u32;
+                if self.quirks.i_overflow_sets_vf {
+                    self.registers[0xF] = (sum > 0xFFF) as u8;
+                }
+                self.address_register = (sum as Address).bitand(self.address_mask());
             }
             Instruction::GetSpriteDataAddress(reg0) => {
-                let sprite_num = self.registers[*reg0 as usize];
-                let address = sprite_num as u16 * 5;
+                // only the low nibble selects a glyph, anything else would point past the font
+                let value = self.registers[*reg0 as usize];
+                if value > 0x0f {
+                    warn!("FX29 at 0x{:04x} asked for the glyph of 0x{:02x}, using the low nibble", self.instruction_pointer, value);
+                }
+                let sprite_num = value.bitand(0x0f);
+                let address = FONT_ADDRESS + sprite_num as u16 * 5;
                 info!("Address for sprite {} is {:x}", sprite_num, address);
                 self.address_register = address;
             }
             Instruction::StoreBCD(reg0) => {
+                // hundreds at I, tens at I+1, ones at I+2
                 let v = self.registers[*reg0 as usize];
-                let digits = (0..=2).map(
-                    |idx|{(v as i32/10_i32.pow(idx) % 10) as u8}
-                ).collect::<Vec<u8>>();
-
-                digits.iter().rev().enumerate().for_each(|(i, d)|self.memory[self.address_register as usize + i] = *d)
+                let digits = [v / 100, v / 10 % 10, v % 10];
+                self.write_memory(self.address_register as usize, &digits)?;
             }
             Instruction::StoreRegisters(reg0) => {
-                self.registers[0..=*reg0 as usize].iter().enumerate().for_each(|(i, value)|self.memory[self.address_register as usize + i] = *value);
-                self.address_register += *reg0 as u16 + 1;
+                let registers = self.registers;
+                self.write_memory(self.address_register as usize, &registers[0..=*reg0 as usize])?;
+                self.increment_i_after_load_store(*reg0);
             }
             Instruction::FillRegisters(reg0) => {
-                self.registers[0..=*reg0 as usize].iter_mut().enumerate().for_each(|(i, value)|*value = self.memory[self.address_register as usize + i]);
-                self.address_register += *reg0 as u16 + 1;
+                let values = self.read_memory(self.address_register as usize, *reg0 as usize + 1)?;
+                self.registers[0..=*reg0 as usize].copy_from_slice(&values);
+                self.increment_i_after_load_store(*reg0);
+            }
+            Instruction::ScrollDown(rows) => {
+                let (width, height) = self.get_screen_size();
+                let shift = (*rows as usize).min(height) * width;
+                for plane in self.selected_planes_mut() {
+                    plane.copy_within(0..width * height - shift, shift);
+                    plane[0..shift].fill(false);
+                }
+                self.dirty_rows = u64::MAX;
+                effects.display_changed = true;
+            }
+            Instruction::ScrollUp(rows) => {
+                let (width, height) = self.get_screen_size();
+                let shift = (*rows as usize).min(height) * width;
+                for plane in self.selected_planes_mut() {
+                    plane.copy_within(shift..width * height, 0);
+                    plane[width * height - shift..].fill(false);
+                }
+                self.dirty_rows = u64::MAX;
+                effects.display_changed = true;
+            }
+            Instruction::ScrollRight => {
+                let (width, _) = self.get_screen_size();
+                for row in self.selected_planes_mut().into_iter().flat_map(|plane| plane.chunks_mut(width)) {
+                    row.copy_within(0..width - 4, 4);
+                    row[0..4].fill(false);
+                }
+                self.dirty_rows = u64::MAX;
+                effects.display_changed = true;
+            }
+            Instruction::ScrollLeft => {
+                let (width, _) = self.get_screen_size();
+                for row in self.selected_planes_mut().into_iter().flat_map(|plane| plane.chunks_mut(width)) {
+                    row.copy_within(4..width, 0);
+                    row[width - 4..width].fill(false);
+                }
+                self.dirty_rows = u64::MAX;
+                effects.display_changed = true;
+            }
+            Instruction::Exit => {
+                self.exited = true;
+            }
+            Instruction::LowResolution => {
+                self.set_hires(false);
+                effects.display_changed = true;
+            }
+            Instruction::HighResolution => {
+                self.set_hires(true);
+                effects.display_changed = true;
+            }
+            Instruction::GetBigSpriteDataAddress(reg0) => {
+                let sprite_num = self.registers[*reg0 as usize].bitand(0x0f);
+                self.address_register = BIG_SPRITES_ADDRESS + sprite_num as u16 * 10;
+            }
+            Instruction::StoreFlags(reg0) => {
+                self.flags[0..=*reg0 as usize].copy_from_slice(&self.registers[0..=*reg0 as usize]);
+            }
+            Instruction::LoadFlags(reg0) => {
+                self.registers[0..=*reg0 as usize].copy_from_slice(&self.flags[0..=*reg0 as usize]);
+            }
+            Instruction::SaveRange(reg0, reg1) => {
+                let values = Chip8::register_range(*reg0, *reg1).into_iter()
+                    .map(|reg| self.registers[reg])
+                    .collect::<Vec<u8>>();
+                self.write_memory(self.address_register as usize, &values)?;
+            }
+            Instruction::LoadRange(reg0, reg1) => {
+                let registers = Chip8::register_range(*reg0, *reg1);
+                let values = self.read_memory(self.address_register as usize, registers.len())?;
+                values.into_iter().zip(registers).for_each(|(value, reg)| self.registers[reg] = value);
+            }
+            Instruction::LoadILong(addr) => {
+                self.address_register = *addr;
+            }
+            Instruction::SelectPlanes(planes) => {
+                self.planes = planes.bitand(0b11);
+            }
+            Instruction::LoadAudioPattern => {
+                let pattern = self.read_memory(self.address_register as usize, self.audio_pattern.len())?;
+                self.audio_pattern.copy_from_slice(&pattern);
+            }
+
+        }
+        Ok(effects)
+    }
+
+    /// Registers VX to VY for 5XY2/5XY3, in descending order when X is greater than Y
+    fn register_range(from: u8, to: u8) -> Vec<usize> {
+        if from <= to {
+            (from as usize..=to as usize).collect()
+        }else{
+            (to as usize..=from as usize).rev().collect()
+        }
+    }
+
+    pub fn save_state(&self) -> SaveState {
+        SaveState {
+            program_hash: self.program_hash,
+            memory: self.memory.clone(),
+            stack_memory: self.stack_memory.clone(),
+            instruction_pointer: self.instruction_pointer,
+            address_register: self.address_register,
+            registers: self.registers,
+            keys: self.keys,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            display: self.display.clone(),
+            second_plane: self.second_plane.clone(),
+            planes: self.planes,
+            audio_pattern: self.audio_pattern,
+            hires: self.hires,
+            exited: self.exited,
+            flags: self.flags,
+            rng_state: self.rng.state(),
+            tick_count: self.tick_count,
+            quirks: self.quirks
+        }
+    }
+
+    pub fn load_state(&mut self, state: &SaveState) -> Result<(), SaveStateError> {
+        if state.memory.len() != self.memory.len() {
+            return Err(SaveStateError::MemorySizeMismatch { expected: self.memory.len(), found: state.memory.len() });
+        }
+        let expected_display = if state.hires {HIRES_DISPLAY_WIDTH * HIRES_DISPLAY_HEIGHT} else {DISPLAY_WIDTH * DISPLAY_HEIGHT};
+        if state.display.len() != expected_display {
+            return Err(SaveStateError::DisplaySizeMismatch { expected: expected_display, found: state.display.len() });
+        }
+        if state.second_plane.len() != expected_display {
+            return Err(SaveStateError::DisplaySizeMismatch { expected: expected_display, found: state.second_plane.len() });
+        }
+        if state.stack_memory.len() > self.stack_size {
+            return Err(SaveStateError::StackTooDeep { max: self.stack_size, found: state.stack_memory.len() });
+        }
+        if state.program_hash != self.program_hash {
+            return Err(SaveStateError::RomMismatch);
+        }
+        self.memory.copy_from_slice(&state.memory);
+        self.stack_memory = state.stack_memory.clone();
+        self.instruction_pointer = state.instruction_pointer;
+        self.address_register = state.address_register;
+        self.registers = state.registers;
+        self.keys = state.keys;
+        // the PC is still on a pending FX0A, which starts its wait over
+        self.key_wait = KeyWait::Idle;
+        self.delay_timer = state.delay_timer;
+        self.sound_timer = state.sound_timer;
+        self.display = state.display.clone();
+        self.second_plane = state.second_plane.clone();
+        self.planes = state.planes;
+        self.audio_pattern = state.audio_pattern;
+        self.hires = state.hires;
+        self.dirty_rows = u64::MAX;
+        self.exited = state.exited;
+        self.halted = None;
+        self.flags = state.flags;
+        self.rng = XorShiftRng::from_state(state.rng_state);
+        self.tick_count = state.tick_count;
+        self.quirks = state.quirks;
+        Ok(())
+    }
+
+    /// The loaded ROM as it is in memory now, one line per word when printed, e.g. `0x200: 00E0  CLS`
+    pub fn disassemble(&self) -> Vec<DisassembledWord> {
+        let origin = (self.origin as usize).min(self.memory.len());
+        let end = (origin + self.program.len()).min(self.memory.len());
+        disassembler::disassemble(&self.memory[origin..end], self.origin)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Draws random digits at random positions and counts frames in V3, calling a subroutine to set the delay timer
+    const RANDOM_DIGITS: [u8; 20] = [
+        0xC0, 0x3F, // RND V0, 0x3F
+        0xC1, 0x1F, // RND V1, 0x1F
+        0xC2, 0x0F, // RND V2, 0x0F
+        0xF2, 0x29, // LD F, V2
+        0xD0, 0x15, // DRW V0, V1, 5
+        0x73, 0x01, // ADD V3, 0x01
+        0x22, 0x10, // CALL 0x210
+        0x12, 0x00, // JP 0x200
+        0xF3, 0x15, // LD DT, V3
+        0x00, 0xEE  // RET
+    ];
+
+    fn machine(program: &[u8]) -> Chip8 {
+        let mut chip = Chip8::default().with_seed(1);
+        chip.load(program).unwrap();
+        chip
+    }
+
+    /// Registers and display after each of `frames` frames
+    fn run_frames(chip: &mut Chip8, frames: usize) -> Vec<([u8; 16], u64)> {
+        (0..frames).map(|_| {
+            chip.run_frame(10).unwrap();
+            (*chip.registers(), chip.display_hash())
+        }).collect()
+    }
+
+    #[test]
+    fn save_state_resumes_identically() {
+        let mut chip = machine(&RANDOM_DIGITS);
+        run_frames(&mut chip, 20);
+        let saved = chip.save_state().to_bytes();
+        let first_run = run_frames(&mut chip, 30);
+
+        chip.load_state(&SaveState::from_bytes(&saved).unwrap()).unwrap();
+        assert_eq!(chip.save_state().to_bytes(), saved);
+        assert_eq!(run_frames(&mut chip, 30), first_run);
+    }
+
+    #[test]
+    fn load_state_rejects_a_stack_deeper_than_the_machine() {
+        let mut chip = machine(&RANDOM_DIGITS);
+        let mut state = chip.save_state();
+        state.stack_memory = vec![0x200; 17];
+        assert!(matches!(chip.load_state(&state), Err(SaveStateError::StackTooDeep { max: 16, found: 17 })));
+        state.stack_memory.truncate(16);
+        assert!(chip.load_state(&state).is_ok());
+    }
+
+    #[test]
+    fn load_state_rejects_another_rom() {
+        let mut chip = machine(&RANDOM_DIGITS);
+        let state = chip.save_state();
+        chip.load(&[0x12, 0x00]).unwrap();
+        assert!(matches!(chip.load_state(&state), Err(SaveStateError::RomMismatch)));
+    }
+
+    #[test]
+    fn machines_with_the_same_seed_run_identically() {
+        let seeded = |seed| {
+            let mut chip = machine(&RANDOM_DIGITS).with_quirks(Quirks { display_wait: false, ..Quirks::vip() });
+            chip.set_rng_seed(seed);
+            for _ in 0..10_000 {
+                chip.tick().unwrap();
             }
+            chip
+        };
+        let (first, second, other) = (seeded(7), seeded(7), seeded(8));
+        assert_eq!(first.registers(), second.registers());
+        assert_eq!(first.get_display(), second.get_display());
+        assert_ne!(first.get_display(), other.get_display());
+    }
+
+    #[test]
+    fn trace_shows_the_registers_an_instruction_reads() {
+        let mut chip = machine(&[0x6A, 0x0A, 0x6B, 0x03, 0x8A, 0xB4]);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        chip.set_trace_fn(move |event| sender.send(event.to_string()).unwrap());
+        for _ in 0..3 {
+            chip.tick().unwrap();
+        }
+        let lines: Vec<String> = receiver.try_iter().collect();
+        assert_eq!(lines, [
+            "0x0200  6A0A  LD VA, 0x0A     ",
+            "0x0202  6B03  LD VB, 0x03     ",
+            "0x0204  8AB4  ADD VA, VB       (VA=0x0A VB=0x03)"
+        ]);
+    }
+
+    #[test]
+    fn store_bcd_writes_hundreds_tens_and_ones() {
+        for (value, digits) in [(0, [0, 0, 0]), (7, [0, 0, 7]), (42, [0, 4, 2]), (255, [2, 5, 5])] {
+            let mut chip = machine(&[0xA3, 0x00, 0xF0, 0x33]);
+            chip.set_register(0, value);
+            chip.tick().unwrap();
+            chip.tick().unwrap();
+            assert_eq!(chip.memory()[0x300..0x303], digits, "{}", value);
+        }
+    }
 
+    #[test]
+    fn font_addresses_are_five_bytes_apart() {
+        for digit in 0..16 {
+            let mut chip = machine(&[0xF1, 0x29]);
+            chip.set_register(1, digit);
+            chip.tick().unwrap();
+            assert_eq!(chip.i(), FONT_ADDRESS + 5 * digit as Address);
         }
     }
 
-    pub fn disassemble(&self) -> Vec<Option<Instruction>> {
-        (0x200..self.memory.len())
-            .step_by(2)
-            .map(|address|self.get_instruction(address as u16))
-            .map(|instruction| Instruction::decode(instruction)).collect()
+    #[test]
+    fn a_digit_drawn_from_its_font_address_shows_its_glyph() {
+        // LD V1, 7; LD F, V1; LD V0, 0; DRW V0, V0, 5
+        let mut chip = machine(&[0x61, 0x07, 0xF1, 0x29, 0x60, 0x00, 0xD0, 0x05]);
+        for _ in 0..4 {
+            chip.tick().unwrap();
+        }
+        let width = chip.get_screen_size().0;
+        let rows: Vec<String> = (0..5)
+            .map(|y| (0..8).map(|x| if chip.get_display()[y * width + x] {'#'} else {'.'}).collect())
+            .collect();
+        assert_eq!(rows, ["####....", "...#....", "..#.....", ".#......", ".#......"]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn the_flag_is_written_last_even_when_vf_is_an_operand() {
+        // result and flag from VX and VY as read before the instruction
+        type Operation = fn(u8, u8) -> (u8, u8);
+        let operations: [(u8, Operation); 5] = [
+            (0x4, |x, y| (x.wrapping_add(y), x.checked_add(y).is_none() as u8)),
+            (0x5, |x, y| (x.wrapping_sub(y), (x >= y) as u8)),
+            (0x6, |_, y| (y >> 1, y & 1)),
+            (0x7, |x, y| (y.wrapping_sub(x), (y >= x) as u8)),
+            (0xE, |_, y| (y << 1, y >> 7))
+        ];
+        let registers = [(0xF, 0x1), (0x1, 0xF), (0x2, 0x2)];
+        let values = [(0x81, 0x7F), (0x05, 0xFE), (0xFF, 0x01)];
+        for (n, operation) in operations {
+            for (x, y) in registers {
+                for (vx, vy) in values {
+                    let mut chip = machine(&[0x80 | x, y << 4 | n]);
+                    chip.set_register(x, vx);
+                    chip.set_register(y, vy);
+                    let (vx, vy) = (chip.registers()[x as usize], chip.registers()[y as usize]);
+                    chip.tick().unwrap();
+                    let (result, flag) = operation(vx, vy);
+                    let case = format!("8{:X}{:X}{:X} with V{:X}={:02X} V{:X}={:02X}", x, y, n, x, vx, y, vy);
+                    assert_eq!(chip.registers()[0xF], flag, "flag of {}", case);
+                    if x != 0xF {
+                        assert_eq!(chip.registers()[x as usize], result, "result of {}", case);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn returning_first_underflows_the_empty_stack() {
+        let mut chip = machine(&[0x00, 0xEE]);
+        assert_eq!(chip.tick(), Err(Chip8Error::StackUnderflow));
+    }
+
+    #[test]
+    fn calls_past_the_stack_depth_overflow() {
+        // CALL 0x202 falls through to the next call, three deep on a two level stack
+        let mut chip = Chip8::builder().stack_depth(2).build().unwrap();
+        chip.load(&[0x22, 0x02, 0x22, 0x04, 0x22, 0x06]).unwrap();
+        assert!(chip.tick().is_ok());
+        assert!(chip.tick().is_ok());
+        assert_eq!(chip.stack(), [0x200, 0x202]);
+        assert_eq!(chip.tick(), Err(Chip8Error::StackOverflow));
+    }
+
+    #[test]
+    fn builder_rejects_memory_sizes_outside_the_address_space() {
+        for size in [0, 0x200, MAX_MEMORY + 1] {
+            assert!(matches!(Chip8::builder().memory(size).build(), Err(Chip8Error::InvalidMemorySize { .. })), "{}", size);
+        }
+        assert!(Chip8::builder().memory(MIN_MEMORY).build().is_ok());
+        assert!(Chip8::builder().memory(MAX_MEMORY).build().is_ok());
+        assert!(matches!(Chip8::builder().stack_depth(0).build(), Err(Chip8Error::InvalidStackDepth)));
+    }
+
+    #[test]
+    fn step_reports_what_each_instruction_did() {
+        // LD V0, 5; LD ST, V0; LD I, 0; DRW V0, V0, 5; LD V1, K
+        let mut chip = machine(&[0x60, 0x05, 0xF0, 0x18, 0xA0, 0x00, 0xD0, 0x05, 0xF1, 0x0A]);
+        let outcome = |instruction, pc_before: Address, pc_after, display_changed, waiting_for_key, sound_playing| StepOutcome {
+            status: TickStatus::Executed,
+            instruction: Some(instruction),
+            pc_before,
+            pc_after,
+            display_changed,
+            waiting_for_key,
+            sound_playing
+        };
+        let expected = [
+            outcome(Instruction::StoreToReg(0, 5), 0x200, 0x202, false, false, false),
+            outcome(Instruction::WriteSoundTimer(0), 0x202, 0x204, false, false, true),
+            outcome(Instruction::StoreAddressToI(0), 0x204, 0x206, false, false, true),
+            outcome(Instruction::DrawSprite(0, 0, 5), 0x206, 0x208, true, false, true),
+            outcome(Instruction::WaitForKey(1), 0x208, 0x208, false, true, true),
+            outcome(Instruction::WaitForKey(1), 0x208, 0x208, false, true, true)
+        ];
+        for expected in expected {
+            assert_eq!(chip.step().unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn step_stops_short_of_a_second_draw_in_one_frame() {
+        // DRW V0, V0, 5; DRW V0, V0, 5
+        let mut chip = machine(&[0xD0, 0x05, 0xD0, 0x05]);
+        assert_eq!(chip.step().unwrap().status, TickStatus::Executed);
+        let waiting = chip.step().unwrap();
+        assert_eq!(waiting.status, TickStatus::WaitingForFrame);
+        assert_eq!((waiting.instruction, waiting.pc_before, waiting.pc_after), (None, 0x202, 0x202));
+        chip.decrement_time();
+        assert_eq!(chip.step().unwrap().instruction, Some(Instruction::DrawSprite(0, 0, 5)));
+    }
+
+    #[test]
+    fn halting_on_an_unknown_opcode_leaves_pc_on_it() {
+        // LD V0, 1; an unknown opcode; LD V0, 2
+        let program = [0x60, 0x01, 0x51, 0x21, 0x60, 0x02];
+        let mut chip = machine(&program).with_unknown_policy(OpcodePolicy::Halt);
+        for _ in 0..4 {
+            chip.tick().unwrap();
+        }
+        assert_eq!(chip.tick(), Ok(TickStatus::Halted));
+        assert_eq!(chip.pc(), 0x202);
+        assert_eq!(chip.registers()[0], 1);
+        assert_eq!(chip.halted(), Some(&HaltReason::Rejected(Chip8Error::UnknownInstruction { opcode: RawInstruction(0x5121), pc: 0x202 })));
+
+        let mut chip = machine(&program).with_unknown_policy(OpcodePolicy::Ignore);
+        for _ in 0..3 {
+            chip.tick().unwrap();
+        }
+        assert_eq!(chip.registers()[0], 2);
+    }
+
+    #[test]
+    fn machine_code_calls_follow_their_own_policy() {
+        // SYS 0x123; LD V0, 2
+        let program = [0x01, 0x23, 0x60, 0x02];
+        let mut chip = machine(&program).with_sys_policy(OpcodePolicy::Halt).with_unknown_policy(OpcodePolicy::Ignore);
+        assert_eq!(chip.tick(), Ok(TickStatus::Halted));
+        assert_eq!(chip.pc(), 0x200);
+
+        let mut chip = machine(&program).with_sys_policy(OpcodePolicy::Ignore).with_unknown_policy(OpcodePolicy::Halt);
+        chip.tick().unwrap();
+        chip.tick().unwrap();
+        assert_eq!(chip.registers()[0], 2);
+        assert_eq!(chip.halted(), None);
+    }
+
+    fn lit_pixels(chip: &Chip8) -> Vec<(usize, usize)> {
+        let width = chip.get_screen_size().0;
+        chip.get_display().iter().enumerate().filter(|(_, lit)| **lit).map(|(i, _)| (i % width, i / width)).collect()
+    }
+
+    #[test]
+    fn sprites_at_the_largest_coordinates_clip_or_wrap() {
+        // LD V0, 0xFF; LD I, 0 (the 0 glyph); DRW V0, V0, 5
+        let program = [0x60, 0xFF, 0xA0, 0x00, 0xD0, 0x05];
+        let mut chip = machine(&program);
+        for _ in 0..3 {
+            chip.tick().unwrap();
+        }
+        assert_eq!(lit_pixels(&chip), [(63, 31)]);
+
+        let mut chip = machine(&program).with_quirks(Quirks { sprite_edges: SpriteEdge::Wrap, ..Quirks::vip() });
+        for _ in 0..3 {
+            chip.tick().unwrap();
+        }
+        let lit = lit_pixels(&chip);
+        assert_eq!(lit.len(), 14);
+        assert!([(63, 31), (0, 31), (2, 31), (63, 0), (2, 2), (0, 3)].iter().all(|pixel| lit.contains(pixel)));
+    }
+
+    #[test]
+    fn zero_height_sprites_are_16_by_16_only_past_plain_chip8() {
+        // LD V0, 0; LD I, 0x206; DRW V0, V0, 0; followed by 32 bytes of sprite
+        let mut program = vec![0x60, 0x00, 0xA2, 0x06, 0xD0, 0x00];
+        program.extend([0xFF; 32]);
+        let mut chip = machine(&program);
+        for _ in 0..3 {
+            chip.tick().unwrap();
+        }
+        assert!(lit_pixels(&chip).is_empty());
+
+        let mut chip = machine(&program).with_variant(Variant::SuperChip);
+        for _ in 0..3 {
+            chip.tick().unwrap();
+        }
+        let lit = lit_pixels(&chip);
+        assert_eq!(lit.len(), 256);
+        assert!(lit.iter().all(|(x, y)| *x < 16 && *y < 16));
+    }
+
+    #[test]
+    fn programs_start_at_their_origin() {
+        // LD V0, 1; LD V1, 2
+        let mut chip = Chip8::default().with_origin(0x600);
+        chip.load(&[0x60, 0x01, 0x61, 0x02]).unwrap();
+        assert_eq!(chip.pc(), 0x600);
+        assert_eq!(chip.memory()[0x600..0x604], [0x60, 0x01, 0x61, 0x02]);
+        assert_eq!(chip.step().unwrap().instruction, Some(Instruction::StoreToReg(0, 1)));
+        assert_eq!(chip.pc(), 0x602);
+        assert!(chip.memory()[0x200..0x600].iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn origins_over_the_fonts_or_past_memory_fail_to_load() {
+        let program = [0x60, 0x01];
+        let mut chip = Chip8::default().with_origin(0x50);
+        assert_eq!(chip.load(&program), Err(Chip8Error::OriginOverlapsFonts { origin: 0x50 }));
+        let mut chip = Chip8::default().with_origin(FONTS_END - 1);
+        assert_eq!(chip.load(&program), Err(Chip8Error::OriginOverlapsFonts { origin: FONTS_END - 1 }));
+        let mut chip = Chip8::default().with_origin(0x1000);
+        assert_eq!(chip.load(&program), Err(Chip8Error::ProgramTooLarge { size: 2, available: 0 }));
+
+        let mut chip = Chip8::default().with_origin(FONTS_END);
+        assert!(chip.load(&program).is_ok());
+        let mut chip = Chip8::default().with_origin(0x50).with_font_overwrite(true);
+        chip.load(&program).unwrap();
+        assert_eq!(chip.memory()[0x50..0x52], program);
+    }
+
+    #[test]
+    fn storing_registers_past_the_end_of_memory_wraps_or_fails() {
+        // LD I, 0xFFE; LD [I], V5
+        let program = [0xAF, 0xFE, 0xF5, 0x55];
+        let prepared = |policy| {
+            let mut chip = machine(&program).with_memory_policy(policy);
+            for register in 0..6 {
+                chip.set_register(register, register + 1);
+            }
+            chip.tick().unwrap();
+            chip
+        };
+        let mut chip = prepared(MemoryPolicy::Wrap);
+        chip.tick().unwrap();
+        assert_eq!(chip.memory()[0xFFE..], [1, 2]);
+        assert_eq!(chip.memory()[..4], [3, 4, 5, 6]);
+        assert_eq!(chip.i(), 0x004);
+
+        let mut chip = prepared(MemoryPolicy::Error);
+        assert!(matches!(chip.tick(), Err(Chip8Error::MemoryOutOfBounds { .. })));
+    }
+
+    #[test]
+    fn load_store_moves_i_per_quirk() {
+        // LD I, 0x300; LD [I], V3
+        for (increment, i) in [(IndexIncrement::XPlusOne, 0x304), (IndexIncrement::X, 0x303), (IndexIncrement::None, 0x300)] {
+            let mut chip = machine(&[0xA3, 0x00, 0xF3, 0x55]).with_quirks(Quirks { load_store_increment: increment, ..Quirks::vip() });
+            chip.tick().unwrap();
+            chip.tick().unwrap();
+            assert_eq!(chip.i(), i, "{}", increment);
+        }
+    }
+
+    #[test]
+    fn adding_to_i_past_0xfff_wraps_and_sets_vf_per_quirk() {
+        // LD I, 0xFFF; LD V0, 2; ADD I, V0
+        for (sets_vf, vf) in [(false, 0), (true, 1)] {
+            let mut chip = machine(&[0xAF, 0xFF, 0x60, 0x02, 0xF0, 0x1E]).with_quirks(Quirks { i_overflow_sets_vf: sets_vf, ..Quirks::vip() });
+            for _ in 0..3 {
+                chip.tick().unwrap();
+            }
+            assert_eq!(chip.i(), 0x001);
+            assert_eq!(chip.registers()[0xF], vf);
+        }
+    }
+
+    /// LD V0, 62; LD V1, 30; LD I, 0x20A; DRW V0, V1, 6; JP 0x206; an 8x6 block
+    const BLOCK_AT_62_30: [u8; 16] = [0x60, 0x3E, 0x61, 0x1E, 0xA2, 0x0A, 0xD0, 0x16, 0x12, 0x06, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+
+    fn draw_block_at_62_30(sprite_edges: SpriteEdge, draws: usize) -> Chip8 {
+        let mut chip = machine(&BLOCK_AT_62_30).with_quirks(Quirks { sprite_edges, display_wait: false, ..Quirks::vip() });
+        // the three setup instructions, then a draw and a jump back for each draw
+        for _ in 0..3 + draws * 2 - 1 {
+            chip.tick().unwrap();
+        }
+        chip
+    }
+
+    #[test]
+    fn sprites_past_the_corner_wrap_to_the_opposite_edges() {
+        let chip = draw_block_at_62_30(SpriteEdge::Wrap, 1);
+        let lit = lit_pixels(&chip);
+        assert_eq!(lit.len(), 48);
+        assert!(lit.iter().all(|(x, y)| (*x >= 62 || *x <= 5) && (*y >= 30 || *y <= 3)));
+        assert!(lit.contains(&(0, 0)) && lit.contains(&(5, 3)) && lit.contains(&(63, 31)));
+    }
+
+    #[test]
+    fn sprites_past_the_corner_clip_and_still_collide() {
+        let chip = draw_block_at_62_30(SpriteEdge::Clip, 1);
+        assert_eq!(lit_pixels(&chip), [(62, 30), (63, 30), (62, 31), (63, 31)]);
+        assert_eq!(chip.registers()[0xF], 0);
+        for sprite_edges in [SpriteEdge::Clip, SpriteEdge::Wrap] {
+            let chip = draw_block_at_62_30(sprite_edges, 2);
+            assert!(lit_pixels(&chip).is_empty(), "{}", sprite_edges);
+            assert_eq!(chip.registers()[0xF], 1, "{}", sprite_edges);
+        }
+    }
+
+    #[test]
+    fn the_sound_timer_counts_down_to_zero_and_stays_there() {
+        // LD V0, 3; LD ST, V0
+        let mut chip = machine(&[0x60, 0x03, 0xF0, 0x18]);
+        chip.tick().unwrap();
+        chip.tick().unwrap();
+        let mut remaining = Vec::new();
+        for _ in 0..5 {
+            remaining.push((chip.sound_timer(), chip.is_sound_active()));
+            chip.decrement_time();
+        }
+        assert_eq!(remaining, [(3, true), (2, true), (1, true), (0, false), (0, false)]);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "past the hex keypad")]
+    fn keys_past_f_panic_in_debug_builds() {
+        machine(&RANDOM_DIGITS).set_key_state(0x10, true);
+    }
+
+    #[test]
+    fn waiting_for_a_key_ignores_keys_held_before_the_wait() {
+        // LD V0, K; LD V1, 1
+        let mut chip = machine(&[0xF0, 0x0A, 0x61, 0x01]);
+        chip.set_key_state(5, true);
+        assert!(chip.step().unwrap().waiting_for_key);
+        chip.set_key_state(5, false);
+        assert!(chip.step().unwrap().waiting_for_key);
+        chip.set_key_state(7, true);
+        assert!(chip.step().unwrap().waiting_for_key);
+        chip.set_key_state(7, false);
+        let outcome = chip.step().unwrap();
+        assert!(!outcome.waiting_for_key);
+        assert_eq!((chip.registers()[0], outcome.pc_after), (7, 0x202));
+    }
+
+    #[test]
+    fn waiting_for_a_key_takes_the_first_press_once_it_is_released() {
+        let mut chip = machine(&[0xF0, 0x0A, 0x61, 0x01]);
+        chip.step().unwrap();
+        chip.set_key_state(3, true);
+        chip.set_key_state(4, true);
+        chip.set_key_state(4, false);
+        assert!(chip.step().unwrap().waiting_for_key);
+        chip.set_key_state(3, false);
+        assert!(!chip.step().unwrap().waiting_for_key);
+        assert_eq!(chip.registers()[0], 3);
+        // a key still held from the last wait doesn't end the next one
+        let mut chip = machine(&[0xF0, 0x0A, 0xF1, 0x0A]);
+        chip.step().unwrap();
+        chip.set_key_state(9, true);
+        chip.set_key_state(9, false);
+        chip.set_key_state(9, true);
+        chip.step().unwrap();
+        assert!(chip.step().unwrap().waiting_for_key);
+        chip.set_key_state(9, false);
+        assert!(chip.step().unwrap().waiting_for_key);
+    }
+
+    #[test]
+    fn the_font_can_be_drawn_straight_from_address_zero() {
+        // LD I, 0x000; DRW V0, V0, 5
+        let mut chip = machine(&[0xA0, 0x00, 0xD0, 0x05]);
+        chip.tick().unwrap();
+        chip.tick().unwrap();
+        assert_eq!(chip.i(), 0x000);
+        let mut lit = lit_pixels(&chip);
+        lit.sort();
+        let zero: Vec<(usize, usize)> = [(0, 0..5), (1, 0..1), (1, 4..5), (2, 0..1), (2, 4..5), (3, 0..5)].into_iter()
+            .flat_map(|(x, ys)| ys.map(move |y| (x, y)))
+            .collect();
+        assert_eq!(lit, zero);
+    }
+
+    #[test]
+    fn offset_jumps_add_the_offset_register() {
+        // LD V0, 0x10; LD V1, 0x20; JP V0, 0x123
+        let program = [0x60, 0x10, 0x61, 0x20, 0xB1, 0x23];
+        for (quirks, pc) in [(Quirks::vip(), 0x133), (Quirks::schip(), 0x143)] {
+            let mut chip = machine(&program).with_quirks(quirks);
+            for _ in 0..3 {
+                chip.tick().unwrap();
+            }
+            assert_eq!(chip.pc(), pc);
+        }
+    }
+
+    #[test]
+    fn load_checks_the_program_fits() {
+        let mut chip = Chip8::default();
+        assert_eq!(chip.load(&[0x12; 0x1000 - 0x200 + 1]), Err(Chip8Error::ProgramTooLarge { size: 0xE01, available: 0xE00 }));
+        assert_eq!(chip.load(&[]), Err(Chip8Error::EmptyProgram));
+        let filling = [0x12; 0x1000 - 0x200];
+        assert_eq!(chip.load(&filling), Ok(()));
+        assert_eq!(chip.memory()[0x200..], filling);
+    }
+
+    #[test]
+    fn nested_calls_return_in_order() {
+        let program = [
+            0x22, 0x06, // CALL 0x206
+            0x60, 0x01, // LD V0, 1
+            0x12, 0x04, // JP 0x204
+            0x22, 0x0A, // CALL 0x20A
+            0x00, 0xEE, // RET
+            0x61, 0x02, // LD V1, 2
+            0x00, 0xEE  // RET
+        ];
+        let mut chip = machine(&program);
+        let mut pcs = Vec::new();
+        while chip.tick().unwrap() != TickStatus::Halted {
+            pcs.push((chip.pc(), chip.stack().len()));
+        }
+        assert_eq!(pcs, [(0x206, 1), (0x20A, 2), (0x20C, 2), (0x208, 1), (0x202, 0), (0x204, 0)]);
+        assert_eq!(chip.registers()[..2], [1, 2]);
+    }
+
+    #[test]
+    fn running_off_the_end_of_memory_halts() {
+        // DRW V0, V0, 5 and then LD V1, 0 up to the end of memory
+        let mut program = vec![0xD0, 0x05];
+        program.extend([0x61, 0x00].repeat((0x1000 - 0x202) / 2));
+        let mut chip = machine(&program);
+        while chip.tick().unwrap() != TickStatus::Halted {}
+        assert_eq!(chip.halted(), Some(&HaltReason::PcOutOfBounds(0x1000)));
+        assert!(!lit_pixels(&chip).is_empty());
+        assert_eq!(chip.tick(), Ok(TickStatus::Halted));
+    }
+
+    #[test]
+    fn an_instruction_cut_off_by_the_end_of_memory_halts() {
+        // JP 0xFFF
+        let mut chip = machine(&[0x1F, 0xFF]);
+        chip.tick().unwrap();
+        assert_eq!(chip.tick(), Ok(TickStatus::Halted));
+        assert_eq!(chip.halted(), Some(&HaltReason::PcOutOfBounds(0xFFF)));
+    }
+
+    #[test]
+    fn a_large_add_to_i_near_the_top_wraps_and_sets_vf_per_quirk() {
+        // LD I, 0xFF0; LD VE, 0xFF; ADD I, VE
+        for (sets_vf, vf) in [(false, 0x55), (true, 1)] {
+            let mut chip = machine(&[0xAF, 0xF0, 0x6E, 0xFF, 0xFE, 0x1E]).with_quirks(Quirks { i_overflow_sets_vf: sets_vf, ..Quirks::vip() });
+            chip.set_register(0xF, 0x55);
+            for _ in 0..3 {
+                chip.tick().unwrap();
+            }
+            assert_eq!(chip.i(), 0x0EF);
+            assert_eq!(chip.registers()[0xF], vf);
+        }
+    }
+
+    #[test]
+    fn i_relative_accesses_at_the_last_byte_wrap_or_fail() {
+        // LD B, V0; LD [I], V2; LD V2, [I]; DRW V0, V0, 3
+        for opcode in [[0xF0, 0x33], [0xF2, 0x55], [0xF2, 0x65], [0xD0, 0x03]] {
+            // LD I, 0xFFF
+            let program = [0xAF, 0xFF, opcode[0], opcode[1]];
+            let mut chip = machine(&program).with_memory_policy(MemoryPolicy::Wrap);
+            chip.set_register(0, 123);
+            chip.tick().unwrap();
+            assert_eq!(chip.tick(), Ok(TickStatus::Executed), "{:02X}{:02X}", opcode[0], opcode[1]);
+
+            let mut chip = machine(&program).with_memory_policy(MemoryPolicy::Error);
+            chip.tick().unwrap();
+            assert!(matches!(chip.tick(), Err(Chip8Error::MemoryOutOfBounds { .. })), "{:02X}{:02X}", opcode[0], opcode[1]);
+        }
+        // the digits past the end land at the start of memory
+        let mut chip = machine(&[0xAF, 0xFF, 0xF0, 0x33]).with_memory_policy(MemoryPolicy::Wrap);
+        chip.set_register(0, 123);
+        chip.tick().unwrap();
+        chip.tick().unwrap();
+        assert_eq!((chip.memory()[0xFFF], chip.memory()[0], chip.memory()[1]), (1, 2, 3));
+    }
+
+    #[test]
+    fn write_protection_keeps_the_font_intact() {
+        // LD I, 0x010; LD [I], V3
+        let program = [0xA0, 0x10, 0xF3, 0x55];
+        let stored = |protection| {
+            let mut chip = machine(&program).with_write_protection(protection);
+            (0..4).for_each(|register| chip.set_register(register, 0xAA));
+            chip.tick().unwrap();
+            let result = chip.tick();
+            (result, chip.memory()[0x10..0x14].to_vec())
+        };
+        let font = machine(&program).memory()[0x10..0x14].to_vec();
+        assert_eq!(stored(WriteProtection::Ignore), (Ok(TickStatus::Executed), font.clone()));
+        assert_eq!(stored(WriteProtection::Error), (Err(Chip8Error::ProtectedWrite { addr: 0x10, pc: 0x202 }), font));
+        assert_eq!(stored(WriteProtection::Off), (Ok(TickStatus::Executed), vec![0xAA; 4]));
+    }
+
+    #[test]
+    fn write_protection_only_drops_the_bytes_below_the_program() {
+        // LD I, 0x1FE; LD [I], V3
+        let mut chip = machine(&[0xA1, 0xFE, 0xF3, 0x55]).with_write_protection(WriteProtection::Ignore);
+        (0..4).for_each(|register| chip.set_register(register, 0xAA));
+        chip.tick().unwrap();
+        chip.tick().unwrap();
+        assert_eq!(chip.memory()[0x1FE..0x202], [0x00, 0x00, 0xAA, 0xAA]);
+    }
+
+    #[test]
+    fn shifts_read_vy_or_shift_vx_in_place_per_quirk() {
+        // (opcode, in place, VX and VF afterwards) with V1 = 0x81 and V2 = 0x42 beforehand
+        let cases = [
+            ([0x81, 0x26], false, 0x21, 0),
+            ([0x81, 0x26], true, 0x40, 1),
+            ([0x81, 0x2E], false, 0x84, 0),
+            ([0x81, 0x2E], true, 0x02, 1),
+            ([0x81, 0x16], false, 0x40, 1),
+            ([0x81, 0x16], true, 0x40, 1),
+            ([0x81, 0x1E], false, 0x02, 1),
+            ([0x81, 0x1E], true, 0x02, 1)
+        ];
+        for (opcode, shift_in_place, vx, vf) in cases {
+            let mut chip = machine(&opcode).with_quirks(Quirks { shift_in_place, ..Quirks::vip() });
+            chip.set_register(1, 0x81);
+            chip.set_register(2, 0x42);
+            chip.tick().unwrap();
+            let case = format!("{:02X}{:02X} in place {}", opcode[0], opcode[1], shift_in_place);
+            assert_eq!((chip.registers()[1], chip.registers()[0xF]), (vx, vf), "{}", case);
+            assert_eq!(chip.registers()[2], 0x42, "{}", case);
+        }
+    }
+
+    #[test]
+    fn load_store_of_v0_and_vf_moves_i_per_quirk() {
+        let increments = [(IndexIncrement::XPlusOne, [1, 16]), (IndexIncrement::X, [0, 15]), (IndexIncrement::None, [0, 0])];
+        for (increment, moved) in increments {
+            let quirks = Quirks { load_store_increment: increment, ..Quirks::vip() };
+            for (x, moved) in [0u8, 0xF].into_iter().zip(moved) {
+                let count = x as usize + 1;
+                // LD I, 0x300; LD [I], VX
+                let mut chip = machine(&[0xA3, 0x00, 0xF0 | x, 0x55]).with_quirks(quirks);
+                (0..16).for_each(|register| chip.set_register(register, register + 1));
+                chip.tick().unwrap();
+                chip.tick().unwrap();
+                assert_eq!(chip.i(), 0x300 + moved, "store V0-V{:X} with {}", x, increment);
+                assert_eq!(chip.memory()[0x300..0x300 + count], (1..=count as u8).collect::<Vec<u8>>());
+                assert_eq!(chip.memory()[0x300 + count], 0);
+
+                // LD I, 0x300; LD VX, [I]; padding up to 0x300, followed by the values to load
+                let mut program = vec![0xA3, 0x00, 0xF0 | x, 0x65];
+                program.resize(0x100, 0);
+                program.extend(0x10..0x20);
+                let mut chip = machine(&program).with_quirks(quirks);
+                chip.tick().unwrap();
+                chip.tick().unwrap();
+                assert_eq!(chip.i(), 0x300 + moved, "load V0-V{:X} with {}", x, increment);
+                assert_eq!(chip.registers()[..count], (0x10..0x10 + count as u8).collect::<Vec<u8>>());
+                assert!(chip.registers()[count..].iter().all(|value| *value == 0));
+            }
+        }
+    }
+
+    #[test]
+    fn b234_jumps_with_v0_or_v2_per_quirk() {
+        for (jump_with_vx, pc) in [(false, 0x235), (true, 0x264)] {
+            let mut chip = machine(&[0xB2, 0x34]).with_quirks(Quirks { jump_with_vx, ..Quirks::vip() });
+            chip.set_register(0, 0x01);
+            chip.set_register(2, 0x30);
+            chip.tick().unwrap();
+            assert_eq!(chip.pc(), pc, "jump with VX {}", jump_with_vx);
+        }
+    }
+
+    #[test]
+    fn display_wait_allows_one_draw_per_frame() {
+        // DRW V0, V0, 1; ADD V3, 1; JP 0x200
+        let program = [0xD0, 0x01, 0x73, 0x01, 0x12, 0x00];
+        for (display_wait, draws) in [(true, [1, 2, 3]), (false, [10, 20, 30])] {
+            let mut chip = machine(&program).with_quirks(Quirks { display_wait, ..Quirks::vip() });
+            let counted: Vec<u8> = (0..3).map(|_| {
+                chip.run_frame(30).unwrap();
+                chip.registers()[3]
+            }).collect();
+            assert_eq!(counted, draws, "display wait {}", display_wait);
+        }
+    }
+
+    #[test]
+    fn random_numbers_repeat_with_the_seed() {
+        // RND V0, 0xFF up to RND VE, 0xFF
+        let program: Vec<u8> = (0..15).flat_map(|register| [0xC0 | register, 0xFF]).collect();
+        let registers = |seed| {
+            let mut chip = machine(&program).with_seed(seed);
+            for _ in 0..15 {
+                chip.tick().unwrap();
+            }
+            *chip.registers()
+        };
+        assert_eq!(registers(42), registers(42));
+        assert_ne!(registers(42), registers(43));
+    }
+
+    #[test]
+    fn key_skips_only_look_at_the_low_nibble() {
+        // SKP V0; LD V1, 1; SKNP V0; LD V2, 1
+        let program = [0xE0, 0x9E, 0x61, 0x01, 0xE0, 0xA1, 0x62, 0x01];
+        for (pressed, v1, v2) in [(0xA, 0, 1), (0x4, 1, 0)] {
+            let mut chip = machine(&program);
+            chip.set_register(0, 0x4A);
+            chip.set_key_state(pressed, true);
+            while chip.pc() < 0x208 {
+                chip.tick().unwrap();
+            }
+            assert_eq!((chip.registers()[1], chip.registers()[2]), (v1, v2), "key {:X} held", pressed);
+        }
+    }
+
+    #[test]
+    fn font_address_ignores_the_high_nibble_of_vx() {
+        let mut chip = machine(&[0xF1, 0x29]);
+        chip.set_register(1, 0x1A);
+        chip.tick().unwrap();
+        assert_eq!(chip.i(), FONT_ADDRESS + 5 * 0xA);
+    }
+
+    #[test]
+    fn a_jump_or_call_to_itself_halts_on_the_first_tick() {
+        for program in [[0x12, 0x00], [0x22, 0x00]] {
+            let mut chip = machine(&program);
+            assert_eq!(chip.tick().unwrap(), TickStatus::Halted, "{:02X?}", program);
+            assert_eq!(chip.halted(), Some(&HaltReason::SelfJump(0x200)));
+            assert_eq!(chip.tick().unwrap(), TickStatus::Halted);
+            assert_eq!((chip.pc(), chip.stack().len()), (0x200, 0));
+        }
+    }
+
+    #[test]
+    fn a_self_jump_after_other_code_halts_at_its_own_address() {
+        // LD V0, 5; JP 0x202
+        let mut chip = machine(&[0x60, 0x05, 0x12, 0x02]);
+        assert_eq!(chip.tick().unwrap(), TickStatus::Executed);
+        assert_eq!(chip.halted(), None);
+        assert_eq!(chip.tick().unwrap(), TickStatus::Halted);
+        assert_eq!(chip.halted(), Some(&HaltReason::SelfJump(0x202)));
+        assert_eq!(chip.registers()[0], 5);
+    }
+
+    #[test]
+    fn jumps_and_calls_to_the_bottom_of_memory_land_there() {
+        for (program, pc, depth) in [([0x10, 0x00], 0x000, 0), ([0x10, 0x01], 0x001, 0), ([0x20, 0x00], 0x000, 1)] {
+            let mut chip = machine(&program);
+            assert_eq!(chip.tick().unwrap(), TickStatus::Executed);
+            assert_eq!((chip.pc(), chip.stack().len()), (pc, depth), "{:02X?}", program);
+        }
+    }
+
+    #[test]
+    fn a_return_resumes_after_the_call() {
+        // CALL 0x206; LD V0, 1; ADD V0, 1; RET
+        let mut chip = machine(&[0x22, 0x06, 0x60, 0x01, 0x70, 0x01, 0x00, 0xEE]);
+        chip.tick().unwrap();
+        assert_eq!((chip.pc(), chip.stack().len()), (0x206, 1));
+        chip.tick().unwrap();
+        assert_eq!((chip.pc(), chip.stack().len()), (0x202, 0));
+        chip.tick().unwrap();
+        assert_eq!((chip.pc(), chip.registers()[0]), (0x204, 1));
+    }
+
+    #[test]
+    fn skips_step_over_exactly_one_instruction() {
+        // V0 = 5 and V1 = 5 going in, the skip is the first instruction
+        let skips: [([u8; 2], bool); 8] = [
+            ([0x30, 0x05], true), ([0x30, 0x06], false),
+            ([0x40, 0x06], true), ([0x40, 0x05], false),
+            ([0x50, 0x10], true), ([0x50, 0x20], false),
+            ([0x90, 0x20], true), ([0x90, 0x10], false),
+        ];
+        for (skip, taken) in skips {
+            let mut chip = machine(&[skip[0], skip[1], 0x12, 0x02]);
+            chip.set_register(0, 5);
+            chip.set_register(1, 5);
+            chip.tick().unwrap();
+            assert_eq!(chip.pc(), if taken {0x204} else {0x202}, "{:02X?}", skip);
+        }
+    }
+
+    #[test]
+    fn waiting_for_a_key_keeps_the_pc_on_the_wait() {
+        // LD V0, K; LD V1, 1
+        let mut chip = machine(&[0xF0, 0x0A, 0x61, 0x01]);
+        for _ in 0..3 {
+            chip.tick().unwrap();
+            assert_eq!(chip.pc(), 0x200);
+        }
+        chip.set_key_state(2, true);
+        chip.set_key_state(2, false);
+        chip.tick().unwrap();
+        assert_eq!((chip.pc(), chip.registers()[0]), (0x202, 2));
+    }
+
+    #[test]
+    fn released_keys_no_longer_skip() {
+        // SKP V0; LD V1, 1
+        let mut chip = machine(&[0xE0, 0x9E, 0x61, 0x01]);
+        chip.set_register(0, 6);
+        chip.set_key_state(6, true);
+        chip.set_key_state(0xC, true);
+        chip.release_all_keys();
+        assert_eq!(chip.keys(), &[false; 16]);
+        chip.tick().unwrap();
+        assert_eq!(chip.pc(), 0x202);
+    }
+
+    #[test]
+    fn releasing_all_keys_doesnt_end_a_key_wait() {
+        // LD V0, K; LD V1, 1
+        let mut chip = machine(&[0xF0, 0x0A, 0x61, 0x01]);
+        chip.tick().unwrap();
+        chip.set_key_state(4, true);
+        chip.release_all_keys();
+        assert!(chip.step().unwrap().waiting_for_key);
+        chip.set_key_state(8, true);
+        chip.set_key_state(8, false);
+        assert!(!chip.step().unwrap().waiting_for_key);
+        assert_eq!(chip.registers()[0], 8);
+    }
+
+    #[test]
+    fn the_display_between_steps_only_holds_whole_sprites() {
+        // LD I, 0x208; DRW V0, V0, 15; DRW V0, V0, 15; JP 0x206; then 15 full rows
+        let mut program = vec![0xA2, 0x08, 0xD0, 0x0F, 0xD0, 0x0F, 0x12, 0x06];
+        program.extend([0xFF; 15]);
+        let mut chip = machine(&program).with_quirks(Quirks { display_wait: false, ..Quirks::vip() });
+        let mut presented = Vec::new();
+        while chip.tick().unwrap() != TickStatus::Halted {
+            presented.push(lit_pixels(&chip).len());
+        }
+        assert_eq!(presented, [0, 8 * 15, 0]);
+    }
+
+    #[test]
+    fn the_display_holds_one_bool_per_logical_pixel() {
+        // LD V0, 3; LD V1, 2; LD I, 0x20C; DRW V0, V1, 1; CLS; HIGH; then a row of 0b1010_0001
+        let program = [0x60, 0x03, 0x61, 0x02, 0xA2, 0x0C, 0xD0, 0x11, 0x00, 0xE0, 0x00, 0xFF, 0xA1];
+        let mut chip = machine(&program).with_variant(Variant::SuperChip);
+        assert_eq!((chip.get_screen_size(), chip.get_display().len()), ((64, 32), 64 * 32));
+        for _ in 0..4 {
+            chip.tick().unwrap();
+        }
+        let row = &chip.get_display()[2 * 64..3 * 64];
+        assert_eq!(row[3..11], [true, false, true, false, false, false, false, true]);
+        assert_eq!(lit_pixels(&chip), [(3, 2), (5, 2), (10, 2)]);
+        chip.tick().unwrap();
+        assert!(chip.get_display().iter().all(|lit| !lit));
+        chip.tick().unwrap();
+        assert_eq!((chip.get_screen_size(), chip.get_display().len()), ((128, 64), 128 * 64));
+    }
+
+    #[test]
+    fn builder_errors_name_the_rejected_value() {
+        for size in [0, 0x200, MAX_MEMORY + 1] {
+            assert_eq!(Chip8::builder().memory(size).build().err(), Some(Chip8Error::InvalidMemorySize { size }));
+        }
+        assert_eq!(Chip8::builder().stack_depth(0).build().err(), Some(Chip8Error::InvalidStackDepth));
+        // memory is checked first
+        assert_eq!(Chip8::builder().memory(0).stack_depth(0).build().err(), Some(Chip8Error::InvalidMemorySize { size: 0 }));
+    }
+
+    #[test]
+    fn builder_defaults_to_the_classic_machine() {
+        let mut chip = Chip8::builder().build().unwrap();
+        assert_eq!(chip.memory().len(), Chip8::default().memory().len());
+        assert_eq!(chip.memory().len(), 0x1000);
+        // 17 calls falling through to each other, the last overflows a 16 level stack
+        let program: Vec<u8> = (0..17u16).flat_map(|n| (0x2202 + 2 * n).to_be_bytes()).collect();
+        chip.load(&program).unwrap();
+        for _ in 0..16 {
+            chip.tick().unwrap();
+        }
+        assert_eq!(chip.stack().len(), 16);
+        assert_eq!(chip.tick(), Err(Chip8Error::StackOverflow));
+    }
+
+    #[test]
+    fn each_runtime_error_comes_out_of_tick() {
+        let stack_full: Vec<u8> = (0..17u16).flat_map(|n| (0x2202 + 2 * n).to_be_bytes()).collect();
+        // LD I, 0xFFF; LD V1, [I]
+        let out_of_bounds = [0xAF, 0xFF, 0xF1, 0x65];
+        // LD I, 0x010; LD [I], V0
+        let protected = [0xA0, 0x10, 0xF0, 0x55];
+        let cases: [(&[u8], usize, Chip8Error); 4] = [
+            (&[0x00, 0xEE], 1, Chip8Error::StackUnderflow),
+            (&stack_full, 17, Chip8Error::StackOverflow),
+            (&out_of_bounds, 2, Chip8Error::MemoryOutOfBounds { addr: 0x1000 }),
+            (&protected, 2, Chip8Error::ProtectedWrite { addr: 0x10, pc: 0x202 })
+        ];
+        for (program, ticks, error) in cases {
+            let mut chip = machine(program).with_memory_policy(MemoryPolicy::Error).with_write_protection(WriteProtection::Error);
+            for _ in 1..ticks {
+                chip.tick().unwrap();
+            }
+            assert_eq!(chip.tick(), Err(error.clone()));
+        }
+        let mut chip = machine(&[0x51, 0x21]).with_unknown_policy(OpcodePolicy::Halt);
+        assert_eq!(chip.tick(), Ok(TickStatus::Halted));
+        let error = Chip8Error::UnknownInstruction { opcode: RawInstruction(0x5121), pc: 0x200 };
+        assert_eq!(chip.halted(), Some(&HaltReason::Rejected(error.clone())));
+        assert_eq!(error.to_string(), "Unknown instruction 5121 at 0x0200");
+    }
+
+    #[test]
+    fn accessors_show_the_state_after_a_few_instructions() {
+        let program = [
+            0x60, 0x2A, // LD V0, 0x2A
+            0xA3, 0x00, // LD I, 0x300
+            0xF0, 0x15, // LD DT, V0
+            0xF0, 0x18, // LD ST, V0
+            0xF0, 0x33, // LD B, V0
+            0x22, 0x0E, // CALL 0x20E
+            0x12, 0x0C, // JP 0x20C
+            0x61, 0x07  // LD V1, 7
+        ];
+        let mut chip = machine(&program);
+        for _ in 0..7 {
+            chip.tick().unwrap();
+        }
+        assert_eq!((chip.pc(), chip.i()), (0x210, 0x300));
+        assert_eq!(chip.registers()[..2], [0x2A, 0x07]);
+        assert_eq!((chip.delay_timer(), chip.sound_timer()), (0x2A, 0x2A));
+        assert_eq!(chip.stack().len(), 1);
+        assert_eq!(chip.memory()[0x300..0x303], [0, 4, 2]);
+        assert_eq!(chip.memory()[0x200..0x210], program);
+    }
+
+    #[test]
+    fn save_state_restores_tick_count_and_quirks() {
+        let quirks = Quirks { load_store_increment: IndexIncrement::X, sprite_edges: SpriteEdge::Wrap, jump_with_vx: true, ..Quirks::vip() };
+        let mut chip = machine(&RANDOM_DIGITS).with_quirks(quirks);
+        run_frames(&mut chip, 5);
+        let saved = chip.save_state().to_bytes();
+        let ticks = chip.tick_count();
+
+        let mut restored = machine(&RANDOM_DIGITS).with_quirks(Quirks::schip());
+        restored.load_state(&SaveState::from_bytes(&saved).unwrap()).unwrap();
+        assert_eq!((restored.tick_count(), restored.quirks()), (ticks, quirks));
+        assert_eq!(restored.save_state().to_bytes(), saved);
+        assert_eq!(run_frames(&mut restored, 10), run_frames(&mut chip, 10));
+    }
+
+    #[test]
+    fn save_state_rejects_older_versions_and_unknown_quirks() {
+        let mut saved = machine(&RANDOM_DIGITS).save_state().to_bytes();
+        saved[4] = 4;
+        assert!(matches!(SaveState::from_bytes(&saved), Err(SaveStateError::UnsupportedVersion(4))));
+        saved[4] = 5;
+        let increment = saved.len() - 5;
+        saved[increment] = 3;
+        assert!(matches!(SaveState::from_bytes(&saved), Err(SaveStateError::InvalidQuirks)));
+        saved[increment] = 2;
+        assert_eq!(SaveState::from_bytes(&saved).unwrap().quirks.load_store_increment, IndexIncrement::None);
+        assert!(matches!(SaveState::from_bytes(&saved[..saved.len() - 1]), Err(SaveStateError::Truncated)));
+    }
+
+    #[test]
+    fn run_frame_reports_draws_and_instruction_counts() {
+        // LD I, 0x20A; DRW V0, V0, 1; ADD V1, 1; JP 0x204; then a sprite row
+        let mut chip = machine(&[0xA2, 0x0A, 0xD0, 0x01, 0x71, 0x01, 0x12, 0x04, 0x00, 0x00, 0x80]);
+        let first = chip.run_frame(10).unwrap();
+        assert_eq!((first.instructions, first.cycles, first.display_changed), (10, 10, true));
+        assert_eq!((first.waiting_for_key, &first.stopped_by), (false, &None));
+        let second = chip.run_frame(10).unwrap();
+        assert_eq!((second.instructions, second.display_changed), (10, false));
+        assert_eq!(second.last_instruction, Some((0x206, Instruction::JumpToAddress(0x204))));
+    }
+
+    #[test]
+    fn run_frame_ends_early_and_counts_down_the_timers() {
+        // LD V0, 3; LD ST, V0; LD V1, K
+        let mut chip = machine(&[0x60, 0x03, 0xF0, 0x18, 0xF1, 0x0A]);
+        let frame = chip.run_frame(10).unwrap();
+        assert_eq!((frame.instructions, frame.waiting_for_key, frame.sound_active), (3, true, true));
+        assert_eq!(chip.sound_timer(), 2);
+        // a second draw waits for the next frame with the display wait quirk
+        let mut chip = machine(&[0xD0, 0x01, 0xD0, 0x01]).with_quirks(Quirks::vip());
+        let frame = chip.run_frame(10).unwrap();
+        assert_eq!((frame.instructions, frame.stopped_by), (1, Some(TickStatus::WaitingForFrame)));
+        assert_eq!(chip.run_frame(10).unwrap().instructions, 1);
+    }
+
+    #[test]
+    fn run_frame_stops_on_a_watch_without_counting_down() {
+        // LD V0, 5; LD DT, V0; ADD V2, 1; JP 0x204
+        let mut chip = machine(&[0x60, 0x05, 0xF0, 0x15, 0x72, 0x01, 0x12, 0x04]);
+        chip.add_register_watch(2);
+        let frame = chip.run_frame(10).unwrap();
+        assert!(matches!(frame.stopped_by, Some(TickStatus::WatchHit(WatchHit::Register { register: 2, old: 0, new: 1 }))));
+        assert_eq!(frame.last_instruction, Some((0x204, Instruction::AddToReg(2, 1))));
+        assert_eq!((frame.instructions, chip.delay_timer()), (3, 5));
+    }
+
+    #[test]
+    fn costed_frames_end_once_the_budget_is_spent() {
+        // ADD V0, 1; JP 0x200
+        let mut chip = machine(&[0x70, 0x01, 0x12, 0x00]);
+        let frame = chip.run_frame_costed(10, |_| 3).unwrap();
+        assert_eq!((frame.instructions, frame.cycles), (4, 12));
+        let frame = chip.run_frame_costed(10, |instruction| if matches!(instruction, Instruction::JumpToAddress(_)) {10} else {0}).unwrap();
+        assert_eq!((frame.instructions, frame.cycles, chip.registers()[0]), (2, 10, 3));
+    }
+
+    /// LD V0, 1; CALL 0x208; DRW V0, V0, 1; JP 0x206; ADD V0, 1; RET
+    const CALL_AND_DRAW: [u8; 12] = [0x60, 0x01, 0x22, 0x08, 0xD0, 0x01, 0x12, 0x06, 0x70, 0x01, 0x00, 0xEE];
+
+    #[test]
+    fn the_pre_instruction_hook_sees_every_instruction_in_order() {
+        let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut chip = machine(&CALL_AND_DRAW);
+        let log = seen.clone();
+        chip.set_pre_instruction_hook(move |pc, instruction, chip| {
+            log.lock().unwrap().push((pc, *instruction, chip.registers()[0]));
+            HookAction::Continue
+        });
+        while chip.tick().unwrap() != TickStatus::Halted {}
+        assert_eq!(*seen.lock().unwrap(), [
+            (0x200, Instruction::StoreToReg(0, 1), 0),
+            (0x202, Instruction::ExecSubroutine(0x208), 1),
+            (0x208, Instruction::AddToReg(0, 1), 1),
+            (0x20A, Instruction::ReturnFromSubroutine, 2),
+            (0x204, Instruction::DrawSprite(0, 0, 1), 2)
+        ]);
+    }
+
+    #[test]
+    fn a_pre_instruction_hook_pauses_before_the_instruction_runs() {
+        let calls = std::sync::Arc::new(std::sync::Mutex::new(0));
+        let mut chip = machine(&CALL_AND_DRAW);
+        let counter = calls.clone();
+        chip.set_pre_instruction_hook(move |pc, _, _| {
+            *counter.lock().unwrap() += 1;
+            if pc == 0x208 {HookAction::Pause} else {HookAction::Continue}
+        });
+        chip.tick().unwrap();
+        chip.tick().unwrap();
+        assert_eq!(chip.tick(), Ok(TickStatus::HookPaused(0x208)));
+        assert_eq!((chip.pc(), chip.registers()[0], chip.tick_count()), (0x208, 1, 2));
+        // the next tick runs the instruction without asking the hook again
+        assert_eq!(chip.tick(), Ok(TickStatus::Executed));
+        assert_eq!((chip.pc(), chip.registers()[0]), (0x20A, 2));
+        assert_eq!(*calls.lock().unwrap(), 3);
+    }
+
+    #[test]
+    fn the_draw_hook_runs_after_each_draw_and_can_pause() {
+        let draws = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut chip = machine(&CALL_AND_DRAW);
+        let log = draws.clone();
+        chip.set_draw_hook(move |pc, chip| {
+            log.lock().unwrap().push((pc, chip.get_display().iter().filter(|lit| **lit).count()));
+            HookAction::Pause
+        });
+        let statuses: Vec<TickStatus> = (0..5).map(|_| chip.tick().unwrap()).collect();
+        assert_eq!(statuses[4], TickStatus::HookPaused(0x204));
+        assert!(statuses[..4].iter().all(|status| *status == TickStatus::Executed));
+        // the sprite is already on screen and the PC past it
+        assert_eq!(*draws.lock().unwrap(), [(0x204, 4)]);
+        assert_eq!(chip.pc(), 0x206);
+        assert_eq!(chip.tick(), Ok(TickStatus::Halted));
+        assert_eq!(draws.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn the_unknown_instruction_hook_sees_the_opcode_before_the_policy() {
+        let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        // an unknown word; LD V0, 5
+        let mut chip = machine(&[0xFF, 0xFF, 0x60, 0x05]).with_unknown_policy(OpcodePolicy::Ignore);
+        let log = seen.clone();
+        chip.set_unknown_instruction_hook(move |pc, opcode, _| {
+            log.lock().unwrap().push((pc, opcode));
+            HookAction::Pause
+        });
+        assert_eq!(chip.tick(), Ok(TickStatus::HookPaused(0x200)));
+        assert_eq!(*seen.lock().unwrap(), [(0x200, RawInstruction(0xFFFF))]);
+        assert_eq!(chip.pc(), 0x202);
+
+        chip.clear_hooks();
+        chip.reset();
+        chip.tick().unwrap();
+        chip.tick().unwrap();
+        assert_eq!(seen.lock().unwrap().len(), 1);
+        assert_eq!(chip.registers()[0], 5);
+    }
+
+    /// LD V1, 7; LD I, 0x20A; SYS 0xFF0; SYS 0xFFF; LD V2, 9; 0xAB
+    const SYSCALLS: [u8; 11] = [0x61, 0x07, 0xA2, 0x0A, 0x0F, 0xF0, 0x0F, 0xFF, 0x62, 0x09, 0xAB];
+
+    /// Logs the registers on `SYS 0xFF0` and doubles V1 into V3, halts on `SYS 0xFFF`
+    fn syscall_machine(log: std::sync::Arc<std::sync::Mutex<Vec<String>>>) -> Chip8 {
+        let mut chip = machine(&SYSCALLS);
+        chip.set_ml_call_handler(move |state, address| match address {
+            0xFF0 => {
+                log.lock().unwrap().push(format!("{:02x?} I={:03X} at {:03X} reads {:02X}",
+                    &state.registers()[..4], state.i(), state.pc(), state.memory()[state.i() as usize]));
+                state.set_register(3, state.register(1) * 2);
+                MlCallResult::Continue
+            }
+            0xFFF => MlCallResult::Halt,
+            _ => MlCallResult::Unhandled
+        });
+        chip
+    }
+
+    #[test]
+    fn the_ml_call_handler_reads_the_machine_and_halts_it() {
+        let log = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut chip = syscall_machine(log.clone());
+        let statuses: Vec<TickStatus> = (0..5).map(|_| chip.tick().unwrap()).collect();
+        assert_eq!(statuses, [TickStatus::Executed, TickStatus::Executed, TickStatus::Executed, TickStatus::Halted, TickStatus::Halted]);
+        assert_eq!(*log.lock().unwrap(), ["[00, 07, 00, 00] I=20A at 204 reads AB"]);
+        assert_eq!(chip.halted(), Some(&HaltReason::MlCall(0xFFF)));
+        // left on the call, the instruction after it never runs
+        assert_eq!(chip.pc(), 0x206);
+        assert_eq!(chip.registers()[..4], [0, 7, 0, 14]);
+    }
+
+    #[test]
+    fn calls_the_handler_doesnt_know_go_to_the_sys_policy() {
+        // SYS 0x123
+        let mut chip = machine(&[0x01, 0x23]).with_sys_policy(OpcodePolicy::Halt);
+        chip.set_ml_call_handler(|_, _| MlCallResult::Unhandled);
+        assert_eq!(chip.tick(), Ok(TickStatus::Halted));
+        let error = Chip8Error::UnknownInstruction { opcode: RawInstruction(0x0123), pc: 0x200 };
+        assert_eq!(chip.halted(), Some(&HaltReason::Rejected(error)));
+
+        // without a handler SYS 0xFF0 is just another call the policy rejects
+        let mut chip = syscall_machine(Default::default()).with_sys_policy(OpcodePolicy::Halt);
+        chip.clear_ml_call_handler();
+        for _ in 0..3 {
+            chip.tick().unwrap();
+        }
+        assert!(matches!(chip.halted(), Some(HaltReason::Rejected(_))));
+        assert_eq!((chip.pc(), chip.registers()[3]), (0x204, 0));
+    }
+}
@@ -0,0 +1,42 @@
+#[cfg(feature = "std")]
+use std::error::Error;
+use core::fmt::{Display, Formatter};
+use alloc::format;
+use crate::chip8::{MAX_MEMORY, MIN_MEMORY};
+use crate::chip8_instruction_set::{Address, RawInstruction};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Chip8Error {
+    MemoryOutOfBounds { addr: usize },
+    StackUnderflow,
+    StackOverflow,
+    UnknownInstruction { opcode: RawInstruction, pc: Address },
+    ProgramTooLarge { size: usize, available: usize },
+    ProtectedWrite { addr: usize, pc: Address },
+    EmptyProgram,
+    OriginOverlapsFonts { origin: Address },
+    InvalidMemorySize { size: usize },
+    InvalidStackDepth
+}
+
+impl Display for Chip8Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Chip8Error::MemoryOutOfBounds { addr } => f.write_str(&format!("Memory access out of bounds at 0x{:04x}", addr)),
+            Chip8Error::StackUnderflow => f.write_str("Returned from subroutine with an empty stack"),
+            Chip8Error::StackOverflow => f.write_str("Subroutine call exceeded the stack size"),
+            Chip8Error::UnknownInstruction { opcode, pc } => f.write_str(&format!("Unknown instruction {:04x} at 0x{:04x}", opcode.0, pc)),
+            Chip8Error::ProgramTooLarge { size, available } => f.write_str(&format!("Program is {} bytes but only {} fit in memory", size, available)),
+            Chip8Error::ProtectedWrite { addr, pc } => f.write_str(&format!("Write to protected memory at 0x{:04x} by the instruction at 0x{:04x}", addr, pc)),
+            Chip8Error::EmptyProgram => f.write_str("Program is empty"),
+            Chip8Error::OriginOverlapsFonts { origin } => f.write_str(&format!("Loading at 0x{:03x} would overwrite the fonts", origin)),
+            Chip8Error::InvalidMemorySize { size } => f.write_str(&format!("Memory size must be between {} and {} bytes, not {}", MIN_MEMORY, MAX_MEMORY, size)),
+            Chip8Error::InvalidStackDepth => f.write_str("Stack depth must be at least 1")
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for Chip8Error {
+
+}
@@ -1,13 +1,64 @@
-use std::ops::{BitAnd, Shl, Shr};
-use crate::chip8_instruction_set::Instruction::{AddToReg, AddWithCarry, AndRegister, DrawSprite, FillRegisters, GetSpriteDataAddress, IncrementIWithReg, JumpToAddress, JumpWithOffset, MoveValue, OrRegister, RandWithMask, ReadDelayTimer, ShiftLeft, ShiftRight, SkipFollowingIfRegEq, SkipFollowingIfRegEqReg, SkipFollowingIfRegNeq, SkipIfKeyNotPressed, SkipIfKeyPressed, SkipIfNE, StoreAddressToI, StoreBCD, StoreRegisters, StoreToReg, SubWithCarry, SubWithCarry2, WaitForKey, WriteDelayTimer, WriteSoundTimer, XorRegister};
+use core::fmt::{Display, Formatter};
+use core::ops::{BitAnd, Shl, Shr};
+use core::str::FromStr;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use crate::chip8_instruction_set::Instruction::{AddToReg, AddWithCarry, AndRegister, DrawSprite, FillRegisters, GetSpriteDataAddress, IncrementIWithReg, JumpToAddress, JumpWithOffset, MoveValue, OrRegister, RandWithMask, ReadDelayTimer, ReturnFromSubroutine, ShiftLeft, ShiftRight, SkipFollowingIfRegEq, SkipFollowingIfRegEqReg, SkipFollowingIfRegNeq, SkipIfKeyNotPressed, SkipIfKeyPressed, SkipIfNE, StoreAddressToI, StoreBCD, StoreRegisters, StoreToReg, SubWithCarry, SubWithCarry2, WaitForKey, WriteDelayTimer, WriteSoundTimer, XorRegister};
 
 pub type RegisterTo = u8;
 pub type Register = u8;
 pub type Address = u16;
 pub type Value = u8;
-pub type RawInstruction = (u8, u8);
 
-#[derive(Debug)]
+/// One instruction word as it sits in memory, high byte first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RawInstruction(pub u16);
+
+impl RawInstruction {
+    /// The top nibble, which group of instructions this belongs to
+    pub fn class(&self) -> u8 {
+        self.0.shr(12u16) as u8
+    }
+
+    /// The second nibble, usually the first register
+    pub fn x(&self) -> Register {
+        self.0.shr(8u16).bitand(0xf) as Register
+    }
+
+    /// The third nibble, usually the second register
+    pub fn y(&self) -> Register {
+        self.0.shr(4u16).bitand(0xf) as Register
+    }
+
+    /// The lowest nibble
+    pub fn n(&self) -> u8 {
+        self.0.bitand(0xf) as u8
+    }
+
+    /// The low byte
+    pub fn nn(&self) -> Value {
+        self.0.bitand(0xff) as Value
+    }
+
+    /// The low 12 bits, an address
+    pub fn nnn(&self) -> Address {
+        self.0.bitand(0xfff)
+    }
+}
+
+impl From<(u8, u8)> for RawInstruction {
+    fn from((upper, lower): (u8, u8)) -> Self {
+        RawInstruction(u16::from_be_bytes([upper, lower]))
+    }
+}
+
+/// Machine cycles the COSMAC VIP runs per 60 Hz frame, its 1.76 MHz clock takes 8 clock cycles per machine cycle.
+/// Display DMA steals part of this on the real machine, which the timing model leaves out.
+pub const VIP_CYCLES_PER_FRAME: u32 = 3668;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Instruction {
     ExecSubroutineML(Address),
     ClearScreen,
@@ -43,12 +94,28 @@ pub enum Instruction {
     GetSpriteDataAddress(Register),
     StoreBCD(Register),
     StoreRegisters(Register),
-    FillRegisters(Register)
+    FillRegisters(Register),
+    ScrollDown(Value),
+    ScrollRight,
+    ScrollLeft,
+    Exit,
+    LowResolution,
+    HighResolution,
+    GetBigSpriteDataAddress(Register),
+    StoreFlags(Register),
+    LoadFlags(Register),
+    ScrollUp(Value),
+    SaveRange(Register, Register),
+    LoadRange(Register, Register),
+    LoadILong(Address),
+    SelectPlanes(Value),
+    LoadAudioPattern
 }
 
 impl Instruction {
-    pub(crate) fn decode(instruction: RawInstruction) -> Option<Self> {
-        match instruction.0.shr(4u8) {
+    /// Decodes a single word. XO-CHIP's `F000 NNNN` takes two words and decodes to `None` here, use `decode_long`
+    pub fn decode(instruction: RawInstruction) -> Option<Self> {
+        match instruction.class() {
             0 => Self::decode_0_class_instruction(instruction),
             1 => Self::decode_1_class_instruction(instruction),
             2 => Self::decode_2_class_instruction(instruction),
@@ -64,193 +131,852 @@ impl Instruction {
             0xC => Self::decode_c_class_instruction(instruction),
             0xD => Self::decode_d_class_instruction(instruction),
             0xE => Self::decode_e_class_instruction(instruction),
-            0xF => Self::decode_f_class_instruction(instruction),
-            _ => None
+            _ => Self::decode_f_class_instruction(instruction)
+        }
+    }
+
+    /// Like `decode`, but also decodes `F000 NNNN` when the word after the instruction is given
+    pub fn decode_long(instruction: RawInstruction, next: Option<RawInstruction>) -> Option<Self> {
+        match (instruction, next) {
+            (RawInstruction(0xF000), Some(next)) => Some(Instruction::LoadILong(next.0)),
+            _ => Instruction::decode(instruction)
+        }
+    }
+
+    /// Whether `SYS address` decodes back to itself. 0x000 and the SUPER-CHIP and XO-CHIP `00CN`, `00DN`, `00E0`, `00EE`
+    /// and `00FB` to `00FF` instructions take up the rest of the class.
+    pub fn is_sys_target(address: Address) -> bool {
+        address <= 0xFFF && Instruction::decode(RawInstruction(address)) == Some(Instruction::ExecSubroutineML(address))
+    }
+
+    /// Size of the instruction in bytes, 4 for `F000 NNNN` and 2 for everything else
+    pub fn size(&self) -> usize {
+        match self {
+            Instruction::LoadILong(_) => 4,
+            _ => 2
+        }
+    }
+
+    /// Encodes the instruction including the address word following `F000`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let [upper, lower] = self.encode().0.to_be_bytes();
+        match self {
+            Instruction::LoadILong(addr) => vec![upper, lower, addr.shr(8) as u8, addr.bitand(0xff) as u8],
+            _ => vec![upper, lower]
+        }
+    }
+
+    /// Inverse of `decode`, `F000 NNNN` only encodes its first word, see `to_bytes`. Operands wider than their field (addresses past 12 bits, registers or sprite
+    /// lengths past 4 bits) would turn into another instruction, debug builds panic on them and release builds mask them down to fit.
+    pub fn encode(&self) -> RawInstruction {
+        let opcode = match self {
+            Instruction::ExecSubroutineML(addr) => Instruction::encode_address(0x0, *addr),
+            Instruction::ClearScreen => 0x00E0,
+            Instruction::ReturnFromSubroutine => 0x00EE,
+            JumpToAddress(addr) => Instruction::encode_address(0x1, *addr),
+            Instruction::ExecSubroutine(addr) => Instruction::encode_address(0x2, *addr),
+            SkipFollowingIfRegEq(reg0, value) => Instruction::encode_register_value(0x3, *reg0, *value),
+            SkipFollowingIfRegNeq(reg0, value) => Instruction::encode_register_value(0x4, *reg0, *value),
+            SkipFollowingIfRegEqReg(reg0, reg1) => Instruction::encode_registers(0x5, *reg0, *reg1, 0x0),
+            StoreToReg(reg0, value) => Instruction::encode_register_value(0x6, *reg0, *value),
+            AddToReg(reg0, value) => Instruction::encode_register_value(0x7, *reg0, *value),
+            MoveValue(reg0, reg1) => Instruction::encode_registers(0x8, *reg0, *reg1, 0x0),
+            OrRegister(reg0, reg1) => Instruction::encode_registers(0x8, *reg0, *reg1, 0x1),
+            AndRegister(reg0, reg1) => Instruction::encode_registers(0x8, *reg0, *reg1, 0x2),
+            XorRegister(reg0, reg1) => Instruction::encode_registers(0x8, *reg0, *reg1, 0x3),
+            AddWithCarry(reg0, reg1) => Instruction::encode_registers(0x8, *reg0, *reg1, 0x4),
+            SubWithCarry(reg0, reg1) => Instruction::encode_registers(0x8, *reg0, *reg1, 0x5),
+            ShiftRight(reg0, reg1) => Instruction::encode_registers(0x8, *reg0, *reg1, 0x6),
+            SubWithCarry2(reg0, reg1) => Instruction::encode_registers(0x8, *reg0, *reg1, 0x7),
+            ShiftLeft(reg0, reg1) => Instruction::encode_registers(0x8, *reg0, *reg1, 0xE),
+            SkipIfNE(reg0, reg1) => Instruction::encode_registers(0x9, *reg0, *reg1, 0x0),
+            StoreAddressToI(addr) => Instruction::encode_address(0xA, *addr),
+            JumpWithOffset(addr) => Instruction::encode_address(0xB, *addr),
+            RandWithMask(reg0, mask) => Instruction::encode_register_value(0xC, *reg0, *mask),
+            DrawSprite(reg0, reg1, len) => Instruction::encode_registers(0xD, *reg0, *reg1, *len),
+            SkipIfKeyPressed(reg0) => Instruction::encode_register_value(0xE, *reg0, 0x9E),
+            SkipIfKeyNotPressed(reg0) => Instruction::encode_register_value(0xE, *reg0, 0xA1),
+            ReadDelayTimer(reg0) => Instruction::encode_register_value(0xF, *reg0, 0x07),
+            WaitForKey(reg0) => Instruction::encode_register_value(0xF, *reg0, 0x0A),
+            WriteDelayTimer(reg0) => Instruction::encode_register_value(0xF, *reg0, 0x15),
+            WriteSoundTimer(reg0) => Instruction::encode_register_value(0xF, *reg0, 0x18),
+            IncrementIWithReg(reg0) => Instruction::encode_register_value(0xF, *reg0, 0x1E),
+            GetSpriteDataAddress(reg0) => Instruction::encode_register_value(0xF, *reg0, 0x29),
+            StoreBCD(reg0) => Instruction::encode_register_value(0xF, *reg0, 0x33),
+            StoreRegisters(reg0) => Instruction::encode_register_value(0xF, *reg0, 0x55),
+            FillRegisters(reg0) => Instruction::encode_register_value(0xF, *reg0, 0x65),
+            Instruction::ScrollDown(rows) => 0x00C0 | Instruction::nibble(*rows) as u16,
+            Instruction::ScrollRight => 0x00FB,
+            Instruction::ScrollLeft => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::LowResolution => 0x00FE,
+            Instruction::HighResolution => 0x00FF,
+            Instruction::GetBigSpriteDataAddress(reg0) => Instruction::encode_register_value(0xF, *reg0, 0x30),
+            Instruction::StoreFlags(reg0) => Instruction::encode_register_value(0xF, *reg0, 0x75),
+            Instruction::LoadFlags(reg0) => Instruction::encode_register_value(0xF, *reg0, 0x85),
+            Instruction::ScrollUp(rows) => 0x00D0 | Instruction::nibble(*rows) as u16,
+            Instruction::SaveRange(reg0, reg1) => Instruction::encode_registers(0x5, *reg0, *reg1, 0x2),
+            Instruction::LoadRange(reg0, reg1) => Instruction::encode_registers(0x5, *reg0, *reg1, 0x3),
+            Instruction::LoadILong(_) => 0xF000,
+            Instruction::SelectPlanes(planes) => Instruction::encode_register_value(0xF, *planes, 0x01),
+            Instruction::LoadAudioPattern => 0xF002
+        };
+        RawInstruction(opcode)
+    }
+
+    fn encode_address(class: u16, address: Address) -> u16 {
+        debug_assert!(address <= 0x0fff, "address 0x{:X} doesn't fit in 12 bits", address);
+        class.shl(12) | address.bitand(0x0fff)
+    }
+
+    fn encode_register_value(class: u16, register: Register, value: Value) -> u16 {
+        class.shl(12) | (Instruction::nibble(register) as u16).shl(8) | value as u16
+    }
+
+    fn encode_registers(class: u16, reg0: Register, reg1: Register, nibble: u8) -> u16 {
+        class.shl(12) | (Instruction::nibble(reg0) as u16).shl(8) | (Instruction::nibble(reg1) as u16).shl(4) | Instruction::nibble(nibble) as u16
+    }
+
+    fn nibble(operand: u8) -> u8 {
+        debug_assert!(operand <= 0x0f, "operand 0x{:X} doesn't fit in 4 bits", operand);
+        operand.bitand(0x0f)
+    }
+
+    /// Registers whose values the instruction uses as inputs
+    pub fn registers_read(&self) -> Vec<Register> {
+        match self {
+            SkipFollowingIfRegEq(reg0, _) | SkipFollowingIfRegNeq(reg0, _) | AddToReg(reg0, _) => vec![*reg0],
+            SkipFollowingIfRegEqReg(reg0, reg1) | OrRegister(reg0, reg1) | AndRegister(reg0, reg1) |
+            XorRegister(reg0, reg1) | AddWithCarry(reg0, reg1) | SubWithCarry(reg0, reg1) |
+            SubWithCarry2(reg0, reg1) | SkipIfNE(reg0, reg1) | DrawSprite(reg0, reg1, _) => vec![*reg0, *reg1],
+            MoveValue(_, reg1) | ShiftRight(_, reg1) | ShiftLeft(_, reg1) => vec![*reg1],
+            JumpWithOffset(_) => vec![0],
+            SkipIfKeyPressed(reg0) | SkipIfKeyNotPressed(reg0) | WriteDelayTimer(reg0) | WriteSoundTimer(reg0) |
+            IncrementIWithReg(reg0) | GetSpriteDataAddress(reg0) | StoreBCD(reg0) |
+            Instruction::GetBigSpriteDataAddress(reg0) => vec![*reg0],
+            StoreRegisters(reg0) | Instruction::StoreFlags(reg0) => (0..=*reg0).collect(),
+            Instruction::SaveRange(reg0, reg1) => if reg0 <= reg1 {(*reg0..=*reg1).collect()} else {(*reg1..=*reg0).rev().collect()},
+            _ => vec![]
         }
     }
 
-    fn get_instruction_class(upper_byte: u8) -> u8 {
-        upper_byte.rotate_right(4)
+    /// Instructions added by SUPER-CHIP that plain CHIP-8 doesn't have
+    pub fn is_schip(&self) -> bool {
+        matches!(self,
+            Instruction::ScrollDown(_) | Instruction::ScrollRight | Instruction::ScrollLeft | Instruction::Exit |
+            Instruction::LowResolution | Instruction::HighResolution | Instruction::GetBigSpriteDataAddress(_) |
+            Instruction::StoreFlags(_) | Instruction::LoadFlags(_)
+        )
     }
 
-    fn get_address(instruction: RawInstruction) -> Address {
-        ((instruction.0.bitand(0x0f) as u16).shl(8) + instruction.1 as u16) as Address
+    /// Instructions added by XO-CHIP on top of SUPER-CHIP
+    pub fn is_xochip(&self) -> bool {
+        matches!(self,
+            Instruction::ScrollUp(_) | Instruction::SaveRange(..) | Instruction::LoadRange(..) | Instruction::LoadILong(_) |
+            Instruction::SelectPlanes(_) | Instruction::LoadAudioPattern
+        )
     }
 
-    fn get_registers(instruction: RawInstruction) -> (Register, Register) {
-        (instruction.0.bitand(0x0f) as Register, instruction.1.shr(4) as Register)
+    /// Approximate COSMAC VIP machine cycles to run the instruction, fetch and decode included.
+    /// SUPER-CHIP and XO-CHIP instructions never ran on the VIP and cost as much as a register operation.
+    pub fn cycle_cost(&self) -> u32 {
+        match self {
+            Instruction::ExecSubroutineML(_) => 40,
+            Instruction::ClearScreen => 3078,
+            ReturnFromSubroutine => 50,
+            JumpToAddress(_) => 52,
+            Instruction::ExecSubroutine(_) => 66,
+            SkipFollowingIfRegEq(..) | SkipFollowingIfRegNeq(..) | SkipFollowingIfRegEqReg(..) | SkipIfNE(..) => 54,
+            StoreToReg(..) => 46,
+            AddToReg(..) => 50,
+            MoveValue(..) | OrRegister(..) | AndRegister(..) | XorRegister(..) => 68,
+            AddWithCarry(..) | SubWithCarry(..) | ShiftRight(..) | SubWithCarry2(..) | ShiftLeft(..) => 72,
+            StoreAddressToI(_) => 52,
+            JumpWithOffset(_) => 62,
+            RandWithMask(..) => 76,
+            // every row is shifted into place and XORed into two display bytes
+            DrawSprite(_, _, rows) => 170 + 92 * *rows as u32,
+            SkipIfKeyPressed(_) | SkipIfKeyNotPressed(_) => 52,
+            ReadDelayTimer(_) | WaitForKey(_) | WriteDelayTimer(_) | WriteSoundTimer(_) => 48,
+            IncrementIWithReg(_) => 52,
+            GetSpriteDataAddress(_) => 68,
+            // the digits are found by repeated subtraction, this is about the average
+            StoreBCD(_) => 400,
+            StoreRegisters(reg0) | FillRegisters(reg0) => 64 + 28 * (*reg0 as u32 + 1),
+            _ => 46
+        }
     }
 
     fn decode_0_class_instruction(instruction: RawInstruction) -> Option<Instruction> {
-        match instruction.0 {
-            0x00 => {
-                match instruction.1 {
-                    0xE0 => Some(Instruction::ClearScreen),
-                    0xEE => Some(Instruction::ReturnFromSubroutine),
-                    _ => None
-                }
-            },
-            0x02..=0x0f => Some(Instruction::ExecSubroutineML(Instruction::get_address(instruction))),
-            _ => None
+        match instruction.nnn() {
+            0x0C0..=0x0CF => Some(Instruction::ScrollDown(instruction.n())),
+            0x0D0..=0x0DF => Some(Instruction::ScrollUp(instruction.n())),
+            0x0E0 => Some(Instruction::ClearScreen),
+            0x0EE => Some(Instruction::ReturnFromSubroutine),
+            0x0FB => Some(Instruction::ScrollRight),
+            0x0FC => Some(Instruction::ScrollLeft),
+            0x0FD => Some(Instruction::Exit),
+            0x0FE => Some(Instruction::LowResolution),
+            0x0FF => Some(Instruction::HighResolution),
+            // all zeroes is almost always uninitialised memory rather than a call to 0x000
+            0x000 => None,
+            address => Some(Instruction::ExecSubroutineML(address))
         }
     }
 
     fn decode_1_class_instruction(instruction: RawInstruction) -> Option<Instruction>{
-        match instruction.0 {
-            0x12..=0x1F => Some(Instruction::JumpToAddress(Instruction::get_address(instruction))),
-            _ => None
-        }
+        Some(JumpToAddress(instruction.nnn()))
     }
 
     fn decode_2_class_instruction(instruction: RawInstruction) -> Option<Instruction>{
-        match instruction.0 {
-            0x22..=0x2f => Some(Instruction::ExecSubroutine(Instruction::get_address(instruction))),
-            _ => None
-        }
+        Some(Instruction::ExecSubroutine(instruction.nnn()))
     }
 
     fn decode_3_class_instruction(instruction: RawInstruction) -> Option<Instruction>{
-        match instruction.0 {
-            0x30..=0x3f => Some(SkipFollowingIfRegEq(Instruction::get_registers(instruction).0, instruction.1)),
-            _ => None
-        }
+        Some(SkipFollowingIfRegEq(instruction.x(), instruction.nn()))
     }
 
     fn decode_4_class_instruction(instruction: RawInstruction) -> Option<Instruction>{
-        match instruction.0 {
-            0x40..=0x4f => Some(SkipFollowingIfRegNeq(Instruction::get_registers(instruction).0, instruction.1)),
-            _ => None
-        }
+        Some(SkipFollowingIfRegNeq(instruction.x(), instruction.nn()))
     }
 
     fn decode_5_class_instruction(instruction: RawInstruction) -> Option<Instruction>{
-        let registers = Instruction::get_registers(instruction);
-        match instruction.0 {
-            0x50..=0x5f => if instruction.1.bitand(0x0f) == 0 {Some(SkipFollowingIfRegEqReg(registers.0, registers.1))} else {None},
+        let (reg0, reg1) = (instruction.x(), instruction.y());
+        match instruction.n() {
+            0 => Some(SkipFollowingIfRegEqReg(reg0, reg1)),
+            2 => Some(Instruction::SaveRange(reg0, reg1)),
+            3 => Some(Instruction::LoadRange(reg0, reg1)),
             _ => None
         }
     }
 
     fn decode_6_class_instruction(instruction: RawInstruction) -> Option<Instruction>{
-        match instruction.0 {
-            0x60..=0x6f => Some(StoreToReg(Instruction::get_registers(instruction).0, instruction.1)),
-            _ => None
-        }
-
+        Some(StoreToReg(instruction.x(), instruction.nn()))
     }
 
     fn decode_7_class_instruction(instruction: RawInstruction) -> Option<Instruction>{
-        match instruction.0 {
-            0x70..=0x7f => Some(AddToReg(Instruction::get_registers(instruction).0, instruction.1)),
-            _ => None
-        }
+        Some(AddToReg(instruction.x(), instruction.nn()))
     }
 
     fn decode_8_class_instruction(instruction: RawInstruction) -> Option<Instruction>{
-        let registers = Instruction::get_registers(instruction);
-        match instruction.0 {
-            0x80..=0x8f => match instruction.1.bitand(0x0f) {
-                0 => Some(MoveValue(registers.0, registers.1)),
-                1 => Some(OrRegister(registers.0, registers.1)),
-                2 => Some(AndRegister(registers.0, registers.1)),
-                3 => Some(XorRegister(registers.0, registers.1)),
-                4 => Some(AddWithCarry(registers.0, registers.1)),
-                5 => Some(SubWithCarry(registers.0, registers.1)),
-                6 => Some(ShiftRight(registers.0, registers.1)),
-                7 => Some(SubWithCarry2(registers.0, registers.1)),
-                0xE => Some(ShiftLeft(registers.0, registers.1)),
-                _ => None
-            },
+        let (reg0, reg1) = (instruction.x(), instruction.y());
+        match instruction.n() {
+            0 => Some(MoveValue(reg0, reg1)),
+            1 => Some(OrRegister(reg0, reg1)),
+            2 => Some(AndRegister(reg0, reg1)),
+            3 => Some(XorRegister(reg0, reg1)),
+            4 => Some(AddWithCarry(reg0, reg1)),
+            5 => Some(SubWithCarry(reg0, reg1)),
+            6 => Some(ShiftRight(reg0, reg1)),
+            7 => Some(SubWithCarry2(reg0, reg1)),
+            0xE => Some(ShiftLeft(reg0, reg1)),
             _ => None
         }
     }
 
     fn decode_9_class_instruction(instruction: RawInstruction) -> Option<Instruction>{
-        match instruction.0 {
-            0x90..=0x9F => {
-                if instruction.1.bitand(0x0F) == 0 {
-                    let reg = Instruction::get_registers(instruction);
-                    Some(SkipIfNE(reg.0, reg.1))
-                }else{
-                    None
-                }
-            },
+        match instruction.n() {
+            0 => Some(SkipIfNE(instruction.x(), instruction.y())),
             _ => None
         }
     }
 
     fn decode_a_class_instruction(instruction: RawInstruction) -> Option<Instruction>{
-        match instruction.0 {
-            0xA2..=0xAF => {
-                Some(
-                    StoreAddressToI(Instruction::get_address(instruction))
-                )
-            },
-            _ => None
-        }
+        Some(StoreAddressToI(instruction.nnn()))
     }
 
     fn decode_b_class_instruction(instruction: RawInstruction) -> Option<Instruction>{
-        match instruction.0 {
-            0xB2..=0xBF => {
-                Some(
-                    JumpWithOffset(Instruction::get_address(instruction))
-                )
-            },
-            _ => None
-        }
+        Some(JumpWithOffset(instruction.nnn()))
     }
 
     fn decode_c_class_instruction(instruction: RawInstruction) -> Option<Instruction> {
-        match instruction.0 {
-            0xC0..=0xCF => {
-                Some(RandWithMask(Instruction::get_registers(instruction).0, instruction.1 as Value))
-            },
-            _ => None
-        }
+        Some(RandWithMask(instruction.x(), instruction.nn()))
     }
 
     fn decode_d_class_instruction(instruction: RawInstruction) -> Option<Instruction> {
-        match instruction.0 {
-            0xD0..=0xDF => {
-                let regs = Instruction::get_registers(instruction);
-                Some(DrawSprite(regs.0, regs.1, instruction.1.bitand(0x0f)))
-            },
-            _ => None
-        }
+        Some(DrawSprite(instruction.x(), instruction.y(), instruction.n()))
     }
 
     fn decode_e_class_instruction(instruction: RawInstruction) -> Option<Instruction>{
-        match instruction.0 {
-            0xE0..=0xEF => {
-                match instruction.1 {
-                    0x9E => Some(SkipIfKeyPressed(Instruction::get_registers(instruction).0)),
-                    0xA1 => Some(SkipIfKeyNotPressed(Instruction::get_registers(instruction).0)),
-                    _ => None
-                }
-            },
+        match instruction.nn() {
+            0x9E => Some(SkipIfKeyPressed(instruction.x())),
+            0xA1 => Some(SkipIfKeyNotPressed(instruction.x())),
             _ => None
         }
     }
 
     fn decode_f_class_instruction(instruction: RawInstruction) -> Option<Instruction>{
-        match instruction.0 {
-            0xF0..=0xFF => {
-                let reg = Instruction::get_registers(instruction).0;
-                match instruction.1 {
-                    0x07 => Some(ReadDelayTimer(reg)),
-                    0x0A => Some(WaitForKey(reg)),
-                    0x15 => Some(WriteDelayTimer(reg)),
-                    0x18 => Some(WriteSoundTimer(reg)),
-                    0x1E => Some(IncrementIWithReg(reg)),
-                    0x29 => Some(GetSpriteDataAddress(reg)),
-                    0x33 => Some(StoreBCD(reg)),
-                    0x55 => Some(StoreRegisters(reg)),
-                    0x65 => Some(FillRegisters(reg)),
-                    _ => None
+        let reg = instruction.x();
+        match instruction.nn() {
+            0x07 => Some(ReadDelayTimer(reg)),
+            0x0A => Some(WaitForKey(reg)),
+            0x15 => Some(WriteDelayTimer(reg)),
+            0x18 => Some(WriteSoundTimer(reg)),
+            0x1E => Some(IncrementIWithReg(reg)),
+            0x29 => Some(GetSpriteDataAddress(reg)),
+            0x33 => Some(StoreBCD(reg)),
+            0x55 => Some(StoreRegisters(reg)),
+            0x65 => Some(FillRegisters(reg)),
+            0x30 => Some(Instruction::GetBigSpriteDataAddress(reg)),
+            0x75 => Some(Instruction::StoreFlags(reg)),
+            0x85 => Some(Instruction::LoadFlags(reg)),
+            0x01 => Some(Instruction::SelectPlanes(reg)),
+            0x02 if reg == 0 => Some(Instruction::LoadAudioPattern),
+            _ => None
+        }
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Instruction::ExecSubroutineML(addr) => write!(f, "SYS 0x{:03X}", addr),
+            Instruction::ClearScreen => write!(f, "CLS"),
+            Instruction::ReturnFromSubroutine => write!(f, "RET"),
+            JumpToAddress(addr) => write!(f, "JP 0x{:03X}", addr),
+            Instruction::ExecSubroutine(addr) => write!(f, "CALL 0x{:03X}", addr),
+            SkipFollowingIfRegEq(reg0, value) => write!(f, "SE V{:X}, 0x{:02X}", reg0, value),
+            SkipFollowingIfRegNeq(reg0, value) => write!(f, "SNE V{:X}, 0x{:02X}", reg0, value),
+            SkipFollowingIfRegEqReg(reg0, reg1) => write!(f, "SE V{:X}, V{:X}", reg0, reg1),
+            StoreToReg(reg0, value) => write!(f, "LD V{:X}, 0x{:02X}", reg0, value),
+            AddToReg(reg0, value) => write!(f, "ADD V{:X}, 0x{:02X}", reg0, value),
+            MoveValue(reg0, reg1) => write!(f, "LD V{:X}, V{:X}", reg0, reg1),
+            OrRegister(reg0, reg1) => write!(f, "OR V{:X}, V{:X}", reg0, reg1),
+            AndRegister(reg0, reg1) => write!(f, "AND V{:X}, V{:X}", reg0, reg1),
+            XorRegister(reg0, reg1) => write!(f, "XOR V{:X}, V{:X}", reg0, reg1),
+            AddWithCarry(reg0, reg1) => write!(f, "ADD V{:X}, V{:X}", reg0, reg1),
+            SubWithCarry(reg0, reg1) => write!(f, "SUB V{:X}, V{:X}", reg0, reg1),
+            ShiftRight(reg0, reg1) => write!(f, "SHR V{:X}, V{:X}", reg0, reg1),
+            SubWithCarry2(reg0, reg1) => write!(f, "SUBN V{:X}, V{:X}", reg0, reg1),
+            ShiftLeft(reg0, reg1) => write!(f, "SHL V{:X}, V{:X}", reg0, reg1),
+            SkipIfNE(reg0, reg1) => write!(f, "SNE V{:X}, V{:X}", reg0, reg1),
+            StoreAddressToI(addr) => write!(f, "LD I, 0x{:03X}", addr),
+            JumpWithOffset(addr) => write!(f, "JP V0, 0x{:03X}", addr),
+            RandWithMask(reg0, mask) => write!(f, "RND V{:X}, 0x{:02X}", reg0, mask),
+            DrawSprite(reg0, reg1, len) => write!(f, "DRW V{:X}, V{:X}, {}", reg0, reg1, len),
+            SkipIfKeyPressed(reg0) => write!(f, "SKP V{:X}", reg0),
+            SkipIfKeyNotPressed(reg0) => write!(f, "SKNP V{:X}", reg0),
+            ReadDelayTimer(reg0) => write!(f, "LD V{:X}, DT", reg0),
+            WaitForKey(reg0) => write!(f, "LD V{:X}, K", reg0),
+            WriteDelayTimer(reg0) => write!(f, "LD DT, V{:X}", reg0),
+            WriteSoundTimer(reg0) => write!(f, "LD ST, V{:X}", reg0),
+            IncrementIWithReg(reg0) => write!(f, "ADD I, V{:X}", reg0),
+            GetSpriteDataAddress(reg0) => write!(f, "LD F, V{:X}", reg0),
+            StoreBCD(reg0) => write!(f, "LD B, V{:X}", reg0),
+            StoreRegisters(reg0) => write!(f, "LD [I], V{:X}", reg0),
+            FillRegisters(reg0) => write!(f, "LD V{:X}, [I]", reg0),
+            Instruction::ScrollDown(rows) => write!(f, "SCD {}", rows),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::LowResolution => write!(f, "LOW"),
+            Instruction::HighResolution => write!(f, "HIGH"),
+            Instruction::GetBigSpriteDataAddress(reg0) => write!(f, "LD HF, V{:X}", reg0),
+            Instruction::StoreFlags(reg0) => write!(f, "LD R, V{:X}", reg0),
+            Instruction::LoadFlags(reg0) => write!(f, "LD V{:X}, R", reg0),
+            Instruction::ScrollUp(rows) => write!(f, "SCU {}", rows),
+            Instruction::SaveRange(reg0, reg1) => write!(f, "SAVE V{:X}, V{:X}", reg0, reg1),
+            Instruction::LoadRange(reg0, reg1) => write!(f, "LOAD V{:X}, V{:X}", reg0, reg1),
+            Instruction::LoadILong(addr) => write!(f, "LD I, LONG 0x{:04X}", addr),
+            Instruction::SelectPlanes(planes) => write!(f, "PLANE {}", planes),
+            Instruction::LoadAudioPattern => write!(f, "AUDIO")
+        }
+    }
+}
+
+/// Why a string isn't an instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseInstructionError {
+    UnknownMnemonic(String),
+    WrongOperandCount { mnemonic: String, expected: usize, found: usize },
+    /// The mnemonic exists but not with these kinds of operands, e.g. `LD DT, 5`
+    InvalidOperands(String),
+    /// A token that isn't a register, a number or one of I, [I], DT, ST, K, F, HF, B, R and LONG
+    UnknownOperand(String),
+    RegisterOutOfRange(String),
+    /// A number too large for 16 bits, or not a number at all after `0x`
+    InvalidNumber(String),
+    ValueOutOfRange { value: u16, max: u16 },
+    /// A `SYS` target whose encoding is another instruction, e.g. `SYS 0x0E0` is `CLS`, see `Instruction::is_sys_target`
+    SysAddressTaken(Address)
+}
+
+impl Display for ParseInstructionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ParseInstructionError::UnknownMnemonic(mnemonic) => f.write_str(&format!("Unknown mnemonic '{}'", mnemonic)),
+            ParseInstructionError::WrongOperandCount { mnemonic, expected, found } => f.write_str(&format!("{} expected {} operands, got {}", mnemonic, expected, found)),
+            ParseInstructionError::InvalidOperands(instruction) => f.write_str(&format!("Invalid operands in '{}'", instruction)),
+            ParseInstructionError::UnknownOperand(token) => f.write_str(&format!("Unknown operand '{}'", token)),
+            ParseInstructionError::RegisterOutOfRange(token) => f.write_str(&format!("Operand '{}' is out of range for a V register, expected V0 to VF", token)),
+            ParseInstructionError::InvalidNumber(token) => f.write_str(&format!("'{}' is not a number between 0 and 0xFFFF", token)),
+            ParseInstructionError::ValueOutOfRange { value, max } => f.write_str(&format!("Operand 0x{:X} is out of range, expected at most 0x{:X}", value, max)),
+            ParseInstructionError::SysAddressTaken(address) => f.write_str(&format!("SYS 0x{:03X} would encode as another instruction", address))
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseInstructionError {
+
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Operand {
+    V(Register),
+    I,
+    IndirectI,
+    DelayTimer,
+    SoundTimer,
+    Key,
+    Font,
+    BigFont,
+    Bcd,
+    Flags,
+    Long,
+    Number(u16)
+}
+
+impl FromStr for Operand {
+    type Err = ParseInstructionError;
+
+    /// Case insensitive, numbers are decimal or 0x-prefixed hex
+    fn from_str(token: &str) -> Result<Self, Self::Err> {
+        let upper = token.to_uppercase();
+        let operand = match upper.as_str() {
+            "I" => Operand::I,
+            "[I]" => Operand::IndirectI,
+            "DT" => Operand::DelayTimer,
+            "ST" => Operand::SoundTimer,
+            "K" => Operand::Key,
+            "F" => Operand::Font,
+            "HF" => Operand::BigFont,
+            "B" => Operand::Bcd,
+            "R" => Operand::Flags,
+            "LONG" => Operand::Long,
+            _ if upper.len() > 1 && upper.starts_with('V') && upper[1..].chars().all(|c| c.is_ascii_hexdigit()) => {
+                match u8::from_str_radix(&upper[1..], 16) {
+                    Ok(register) if register <= 0xf => Operand::V(register),
+                    _ => return Err(ParseInstructionError::RegisterOutOfRange(token.to_string()))
                 }
+            }
+            _ if upper.starts_with(|c: char| c.is_ascii_digit()) => {
+                let parsed = match upper.strip_prefix("0X") {
+                    Some(hex) => u16::from_str_radix(hex, 16),
+                    None => upper.parse::<u16>()
+                };
+                Operand::Number(parsed.map_err(|_| ParseInstructionError::InvalidNumber(token.to_string()))?)
+            }
+            _ => return Err(ParseInstructionError::UnknownOperand(token.to_string()))
+        };
+        Ok(operand)
+    }
+}
+
+/// How many operands each mnemonic takes, `JP` and `LD` have forms with different counts
+fn operand_counts(mnemonic: &str) -> Option<&'static [usize]> {
+    let counts: &'static [usize] = match mnemonic {
+        "CLS" | "RET" | "SCR" | "SCL" | "EXIT" | "LOW" | "HIGH" | "AUDIO" => &[0],
+        "SYS" | "CALL" | "SKP" | "SKNP" | "SCD" | "SCU" | "PLANE" => &[1],
+        "SE" | "SNE" | "ADD" | "OR" | "AND" | "XOR" | "SUB" | "SHR" | "SUBN" | "SHL" | "RND" | "SAVE" | "LOAD" => &[2],
+        "DRW" => &[3],
+        "JP" => &[1, 2],
+        "LD" => &[2, 3],
+        _ => return None
+    };
+    Some(counts)
+}
+
+fn number(operand: &Operand, max: u16) -> Result<u16, ParseInstructionError> {
+    match operand {
+        Operand::Number(value) if *value <= max => Ok(*value),
+        Operand::Number(value) => Err(ParseInstructionError::ValueOutOfRange { value: *value, max }),
+        // only called once the operand is known to be a number
+        _ => unreachable!()
+    }
+}
+
+impl Instruction {
+    /// The instruction written as `mnemonic` followed by `tokens`, `mnemonic` in upper case. `operand` parses each token,
+    /// the assemblers resolve labels in it.
+    pub(crate) fn from_tokens<F: FnMut(&str) -> Result<Operand, ParseInstructionError>>(mnemonic: &str, tokens: &[&str], mut operand: F) -> Result<Instruction, ParseInstructionError> {
+        use Operand::*;
+        let counts = operand_counts(mnemonic).ok_or_else(|| ParseInstructionError::UnknownMnemonic(mnemonic.to_string()))?;
+        if !counts.contains(&tokens.len()) {
+            return Err(ParseInstructionError::WrongOperandCount { mnemonic: mnemonic.to_string(), expected: counts[0], found: tokens.len() });
+        }
+        let operands = tokens.iter()
+            .map(|token| operand(token))
+            .collect::<Result<Vec<Operand>, ParseInstructionError>>()?;
+        let address = |index: usize| number(&operands[index], 0xFFF);
+        let byte = |index: usize| number(&operands[index], 0xFF).map(|v| v as u8);
+        let nibble = |index: usize| number(&operands[index], 0xF).map(|v| v as u8);
+        let instruction = match (mnemonic, operands.as_slice()) {
+            ("SYS", [Number(_)]) => match address(0)? {
+                target if Instruction::is_sys_target(target) => Instruction::ExecSubroutineML(target),
+                target => return Err(ParseInstructionError::SysAddressTaken(target))
             },
-            _ => None
+            ("CLS", []) => Instruction::ClearScreen,
+            ("RET", []) => Instruction::ReturnFromSubroutine,
+            ("JP", [Number(_)]) => JumpToAddress(address(0)?),
+            ("JP", [V(0), Number(_)]) => JumpWithOffset(address(1)?),
+            ("CALL", [Number(_)]) => Instruction::ExecSubroutine(address(0)?),
+            ("SE", [V(x), V(y)]) => SkipFollowingIfRegEqReg(*x, *y),
+            ("SE", [V(x), Number(_)]) => SkipFollowingIfRegEq(*x, byte(1)?),
+            ("SNE", [V(x), V(y)]) => SkipIfNE(*x, *y),
+            ("SNE", [V(x), Number(_)]) => SkipFollowingIfRegNeq(*x, byte(1)?),
+            ("LD", [V(x), V(y)]) => MoveValue(*x, *y),
+            ("LD", [V(x), Number(_)]) => StoreToReg(*x, byte(1)?),
+            ("LD", [I, Number(_)]) => StoreAddressToI(address(1)?),
+            ("LD", [I, Long, Number(_)]) => Instruction::LoadILong(number(&operands[2], 0xFFFF)?),
+            ("LD", [V(x), DelayTimer]) => ReadDelayTimer(*x),
+            ("LD", [V(x), Key]) => WaitForKey(*x),
+            ("LD", [DelayTimer, V(x)]) => WriteDelayTimer(*x),
+            ("LD", [SoundTimer, V(x)]) => WriteSoundTimer(*x),
+            ("LD", [Font, V(x)]) => GetSpriteDataAddress(*x),
+            ("LD", [BigFont, V(x)]) => Instruction::GetBigSpriteDataAddress(*x),
+            ("LD", [Bcd, V(x)]) => StoreBCD(*x),
+            ("LD", [IndirectI, V(x)]) => StoreRegisters(*x),
+            ("LD", [V(x), IndirectI]) => FillRegisters(*x),
+            ("LD", [Flags, V(x)]) => Instruction::StoreFlags(*x),
+            ("LD", [V(x), Flags]) => Instruction::LoadFlags(*x),
+            ("ADD", [V(x), V(y)]) => AddWithCarry(*x, *y),
+            ("ADD", [V(x), Number(_)]) => AddToReg(*x, byte(1)?),
+            ("ADD", [I, V(x)]) => IncrementIWithReg(*x),
+            ("OR", [V(x), V(y)]) => OrRegister(*x, *y),
+            ("AND", [V(x), V(y)]) => AndRegister(*x, *y),
+            ("XOR", [V(x), V(y)]) => XorRegister(*x, *y),
+            ("SUB", [V(x), V(y)]) => SubWithCarry(*x, *y),
+            ("SHR", [V(x), V(y)]) => ShiftRight(*x, *y),
+            ("SUBN", [V(x), V(y)]) => SubWithCarry2(*x, *y),
+            ("SHL", [V(x), V(y)]) => ShiftLeft(*x, *y),
+            ("RND", [V(x), Number(_)]) => RandWithMask(*x, byte(1)?),
+            ("DRW", [V(x), V(y), Number(_)]) => DrawSprite(*x, *y, nibble(2)?),
+            ("SKP", [V(x)]) => SkipIfKeyPressed(*x),
+            ("SKNP", [V(x)]) => SkipIfKeyNotPressed(*x),
+            ("SCD", [Number(_)]) => Instruction::ScrollDown(nibble(0)?),
+            ("SCR", []) => Instruction::ScrollRight,
+            ("SCL", []) => Instruction::ScrollLeft,
+            ("EXIT", []) => Instruction::Exit,
+            ("LOW", []) => Instruction::LowResolution,
+            ("HIGH", []) => Instruction::HighResolution,
+            ("SCU", [Number(_)]) => Instruction::ScrollUp(nibble(0)?),
+            ("SAVE", [V(x), V(y)]) => Instruction::SaveRange(*x, *y),
+            ("LOAD", [V(x), V(y)]) => Instruction::LoadRange(*x, *y),
+            ("PLANE", [Number(_)]) => Instruction::SelectPlanes(number(&operands[0], 0xF)? as u8),
+            ("AUDIO", []) => Instruction::LoadAudioPattern,
+            _ => return Err(ParseInstructionError::InvalidOperands(format!("{} {}", mnemonic, tokens.join(", "))))
+        };
+        Ok(instruction)
+    }
+}
+
+/// The inverse of `Display`, e.g. `"LD V3, 0x2A".parse::<Instruction>()`. Mnemonics and operands are case insensitive,
+/// operands are separated by commas or spaces and numbers are decimal or 0x-prefixed hex.
+impl FromStr for Instruction {
+    type Err = ParseInstructionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (mnemonic, rest) = s.split_once(char::is_whitespace).unwrap_or((s, ""));
+        let tokens = rest.split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|token| !token.is_empty())
+            .collect::<Vec<&str>>();
+        Instruction::from_tokens(&mnemonic.to_uppercase(), &tokens, str::parse)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_decodable_opcode_encodes_back_to_itself() {
+        for opcode in 0..=u16::MAX {
+            if let Some(instruction) = Instruction::decode(RawInstruction(opcode)) {
+                assert_eq!(instruction.encode(), RawInstruction(opcode), "{}", instruction);
+                assert_eq!(Instruction::decode(instruction.encode()), Some(instruction));
+            }
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn cycle_costs_match_the_vip() {
+        assert_eq!(VIP_CYCLES_PER_FRAME, 3668);
+        assert_eq!(Instruction::ClearScreen.cycle_cost(), 3078);
+        assert_eq!(StoreToReg(0x3, 0x2A).cycle_cost(), 46);
+        assert_eq!(DrawSprite(0x0, 0x1, 0).cycle_cost(), 170);
+        assert_eq!(DrawSprite(0x0, 0x1, 5).cycle_cost(), 170 + 5 * 92);
+        assert_eq!(StoreRegisters(0x0).cycle_cost(), 64 + 28);
+        assert_eq!(FillRegisters(0xF).cycle_cost(), 64 + 16 * 28);
+        assert_eq!(StoreBCD(0x7).cycle_cost(), 400);
+        // instructions the VIP never had cost as much as a register load
+        assert_eq!(Instruction::HighResolution.cycle_cost(), 46);
+    }
+
+    #[test]
+    fn raw_instructions_split_into_their_fields() {
+        let raw = RawInstruction(0xD123);
+        assert_eq!((raw.class(), raw.x(), raw.y(), raw.n()), (0xD, 0x1, 0x2, 0x3));
+        assert_eq!((raw.nn(), raw.nnn()), (0x23, 0x123));
+        assert_eq!(RawInstruction::from((0xD1, 0x23)), raw);
+    }
+
+    #[test]
+    fn opcodes_decode_across_every_class() {
+        let table = [
+            (0x0000, None),
+            (0x0123, Some(Instruction::ExecSubroutineML(0x123))),
+            (0x00C4, Some(Instruction::ScrollDown(4))),
+            (0x00D4, Some(Instruction::ScrollUp(4))),
+            (0x00E0, Some(Instruction::ClearScreen)),
+            (0x00EE, Some(Instruction::ReturnFromSubroutine)),
+            (0x00FB, Some(Instruction::ScrollRight)),
+            (0x00FC, Some(Instruction::ScrollLeft)),
+            (0x00FD, Some(Instruction::Exit)),
+            (0x00FE, Some(Instruction::LowResolution)),
+            (0x00FF, Some(Instruction::HighResolution)),
+            (0x1ABC, Some(JumpToAddress(0xABC))),
+            (0x2ABC, Some(Instruction::ExecSubroutine(0xABC))),
+            (0x3A42, Some(SkipFollowingIfRegEq(0xA, 0x42))),
+            (0x4A42, Some(SkipFollowingIfRegNeq(0xA, 0x42))),
+            (0x5AB0, Some(SkipFollowingIfRegEqReg(0xA, 0xB))),
+            (0x5AB1, None),
+            (0x5AB2, Some(Instruction::SaveRange(0xA, 0xB))),
+            (0x5AB3, Some(Instruction::LoadRange(0xA, 0xB))),
+            (0x6A42, Some(StoreToReg(0xA, 0x42))),
+            (0x7A42, Some(AddToReg(0xA, 0x42))),
+            (0x8AB0, Some(MoveValue(0xA, 0xB))),
+            (0x8AB1, Some(OrRegister(0xA, 0xB))),
+            (0x8AB2, Some(AndRegister(0xA, 0xB))),
+            (0x8AB3, Some(XorRegister(0xA, 0xB))),
+            (0x8AB4, Some(AddWithCarry(0xA, 0xB))),
+            (0x8AB5, Some(SubWithCarry(0xA, 0xB))),
+            (0x8AB6, Some(ShiftRight(0xA, 0xB))),
+            (0x8AB7, Some(SubWithCarry2(0xA, 0xB))),
+            (0x8AB8, None),
+            (0x8ABE, Some(ShiftLeft(0xA, 0xB))),
+            (0x9AB0, Some(SkipIfNE(0xA, 0xB))),
+            (0x9AB1, None),
+            (0xAABC, Some(StoreAddressToI(0xABC))),
+            (0xBABC, Some(JumpWithOffset(0xABC))),
+            (0xCA42, Some(RandWithMask(0xA, 0x42))),
+            (0xDAB5, Some(DrawSprite(0xA, 0xB, 5))),
+            (0xDAB0, Some(DrawSprite(0xA, 0xB, 0))),
+            (0xEA9E, Some(SkipIfKeyPressed(0xA))),
+            (0xEAA1, Some(SkipIfKeyNotPressed(0xA))),
+            (0xEA00, None),
+            (0xF000, None),
+            (0xF201, Some(Instruction::SelectPlanes(2))),
+            (0xF002, Some(Instruction::LoadAudioPattern)),
+            (0xFA07, Some(ReadDelayTimer(0xA))),
+            (0xFA0A, Some(WaitForKey(0xA))),
+            (0xFA15, Some(WriteDelayTimer(0xA))),
+            (0xFA18, Some(WriteSoundTimer(0xA))),
+            (0xFA1E, Some(IncrementIWithReg(0xA))),
+            (0xFA29, Some(GetSpriteDataAddress(0xA))),
+            (0xFA30, Some(Instruction::GetBigSpriteDataAddress(0xA))),
+            (0xFA33, Some(StoreBCD(0xA))),
+            (0xFA55, Some(StoreRegisters(0xA))),
+            (0xFA65, Some(FillRegisters(0xA))),
+            (0xFA75, Some(Instruction::StoreFlags(0xA))),
+            (0xFA85, Some(Instruction::LoadFlags(0xA))),
+            (0xFA99, None)
+        ];
+        for (opcode, expected) in table {
+            assert_eq!(Instruction::decode(RawInstruction(opcode)), expected, "0x{:04X}", opcode);
+        }
+        assert_eq!(Instruction::decode_long(RawInstruction(0xF000), Some(RawInstruction(0xABCD))), Some(Instruction::LoadILong(0xABCD)));
+    }
+
+    #[test]
+    fn every_instruction_formats_as_its_mnemonic() {
+        let table = [
+            (Instruction::ExecSubroutineML(0x12), "SYS 0x012"),
+            (Instruction::ClearScreen, "CLS"),
+            (Instruction::ReturnFromSubroutine, "RET"),
+            (JumpToAddress(0xABC), "JP 0xABC"),
+            (Instruction::ExecSubroutine(0x3F0), "CALL 0x3F0"),
+            (SkipFollowingIfRegEq(0xA, 0x4), "SE VA, 0x04"),
+            (SkipFollowingIfRegNeq(0xA, 0x42), "SNE VA, 0x42"),
+            (SkipFollowingIfRegEqReg(0xA, 0xB), "SE VA, VB"),
+            (StoreToReg(0x3, 0xFF), "LD V3, 0xFF"),
+            (AddToReg(0x3, 0x01), "ADD V3, 0x01"),
+            (MoveValue(0x0, 0xF), "LD V0, VF"),
+            (OrRegister(0x1, 0x2), "OR V1, V2"),
+            (AndRegister(0x1, 0x2), "AND V1, V2"),
+            (XorRegister(0x1, 0x2), "XOR V1, V2"),
+            (AddWithCarry(0x1, 0x2), "ADD V1, V2"),
+            (SubWithCarry(0x1, 0x2), "SUB V1, V2"),
+            (ShiftRight(0x1, 0x2), "SHR V1, V2"),
+            (SubWithCarry2(0x1, 0x2), "SUBN V1, V2"),
+            (ShiftLeft(0x1, 0x2), "SHL V1, V2"),
+            (SkipIfNE(0xC, 0xD), "SNE VC, VD"),
+            (StoreAddressToI(0x2A), "LD I, 0x02A"),
+            (JumpWithOffset(0x300), "JP V0, 0x300"),
+            (RandWithMask(0xE, 0x0F), "RND VE, 0x0F"),
+            (DrawSprite(0x0, 0x1, 15), "DRW V0, V1, 15"),
+            (SkipIfKeyPressed(0x5), "SKP V5"),
+            (SkipIfKeyNotPressed(0x5), "SKNP V5"),
+            (ReadDelayTimer(0x6), "LD V6, DT"),
+            (WaitForKey(0x6), "LD V6, K"),
+            (WriteDelayTimer(0x6), "LD DT, V6"),
+            (WriteSoundTimer(0x6), "LD ST, V6"),
+            (IncrementIWithReg(0x7), "ADD I, V7"),
+            (GetSpriteDataAddress(0x7), "LD F, V7"),
+            (StoreBCD(0x7), "LD B, V7"),
+            (StoreRegisters(0x8), "LD [I], V8"),
+            (FillRegisters(0x8), "LD V8, [I]"),
+            (Instruction::ScrollDown(4), "SCD 4"),
+            (Instruction::ScrollRight, "SCR"),
+            (Instruction::ScrollLeft, "SCL"),
+            (Instruction::Exit, "EXIT"),
+            (Instruction::LowResolution, "LOW"),
+            (Instruction::HighResolution, "HIGH"),
+            (Instruction::GetBigSpriteDataAddress(0x9), "LD HF, V9"),
+            (Instruction::StoreFlags(0x9), "LD R, V9"),
+            (Instruction::LoadFlags(0x9), "LD V9, R"),
+            (Instruction::ScrollUp(12), "SCU 12"),
+            (Instruction::SaveRange(0x2, 0x5), "SAVE V2, V5"),
+            (Instruction::LoadRange(0x5, 0x2), "LOAD V5, V2"),
+            (Instruction::LoadILong(0x1234), "LD I, LONG 0x1234"),
+            (Instruction::SelectPlanes(3), "PLANE 3"),
+            (Instruction::LoadAudioPattern, "AUDIO")
+        ];
+        for (instruction, expected) in table {
+            assert_eq!(instruction.to_string(), expected, "{:?}", instruction);
+        }
+    }
+
+    #[test]
+    fn every_decodable_opcode_parses_back_from_its_mnemonic() {
+        for opcode in 0..=u16::MAX {
+            if let Some(instruction) = Instruction::decode(RawInstruction(opcode)) {
+                let text = instruction.to_string();
+                assert_eq!(text.parse::<Instruction>(), Ok(instruction), "{}", text);
+                assert_eq!(text.parse::<Instruction>().unwrap().encode(), RawInstruction(opcode), "{}", text);
+            }
+        }
+        let long = Instruction::LoadILong(0xABCD);
+        assert_eq!(long.to_string().parse::<Instruction>(), Ok(long));
+    }
+
+    #[test]
+    fn mnemonics_parse_with_any_case_spacing_and_number_base() {
+        let expected = Ok(Instruction::StoreToReg(3, 0x2A));
+        assert_eq!("LD V3, 0x2A".parse::<Instruction>(), expected);
+        assert_eq!("  ld   v3 ,42 ".parse::<Instruction>(), expected);
+        assert_eq!("Ld V3 0X2a".parse::<Instruction>(), expected);
+    }
+
+    #[test]
+    fn malformed_mnemonics_say_what_was_wrong() {
+        let cases = [
+            ("LD V16, 5", ParseInstructionError::RegisterOutOfRange("V16".to_string())),
+            ("JP 0x1000", ParseInstructionError::ValueOutOfRange { value: 0x1000, max: 0xFFF }),
+            ("LD V0, 0x100", ParseInstructionError::ValueOutOfRange { value: 0x100, max: 0xFF }),
+            ("DRW V0, V1, 16", ParseInstructionError::ValueOutOfRange { value: 16, max: 0xF }),
+            ("JMP 0x200", ParseInstructionError::UnknownMnemonic("JMP".to_string())),
+            ("ADD V1", ParseInstructionError::WrongOperandCount { mnemonic: "ADD".to_string(), expected: 2, found: 1 }),
+            ("LD DT, 5", ParseInstructionError::InvalidOperands("LD DT, 5".to_string())),
+            ("CALL loop", ParseInstructionError::UnknownOperand("loop".to_string())),
+            ("JP 0x1G", ParseInstructionError::InvalidNumber("0x1G".to_string()))
+        ];
+        for (text, error) in cases {
+            assert_eq!(text.parse::<Instruction>(), Err(error), "{}", text);
+        }
+    }
+
+    #[test]
+    fn sys_targets_taken_by_other_instructions_are_rejected() {
+        for address in [0x000, 0x0C0, 0x0CF, 0x0D5, 0x0E0, 0x0EE, 0x0FB, 0x0FF] {
+            let text = format!("SYS 0x{:03X}", address);
+            assert!(!Instruction::is_sys_target(address), "{}", text);
+            assert_eq!(text.parse::<Instruction>(), Err(ParseInstructionError::SysAddressTaken(address)), "{}", text);
+        }
+        for address in [0x001, 0x0BF, 0x0E1, 0x0FA, 0x100, 0xFFF] {
+            let instruction = format!("SYS 0x{:03X}", address).parse::<Instruction>().unwrap();
+            assert_eq!(instruction, Instruction::ExecSubroutineML(address));
+            assert_eq!(Instruction::decode(instruction.encode()), Some(instruction));
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "doesn't fit in 12 bits")]
+    fn encoding_an_address_past_12_bits_panics_in_debug_builds() {
+        // would otherwise encode as JP 0x234
+        Instruction::JumpToAddress(0x1234).encode();
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "doesn't fit in 4 bits")]
+    fn encoding_a_register_past_vf_panics_in_debug_builds() {
+        // would otherwise encode as LD V0, 5
+        Instruction::StoreToReg(0x10, 5).encode();
+    }
+
+    #[test]
+    fn long_load_encodes_the_address_word() {
+        let instruction = Instruction::LoadILong(0x1234);
+        assert_eq!(instruction.to_bytes(), vec![0xF0, 0x00, 0x12, 0x34]);
+        assert_eq!(Instruction::decode_long(RawInstruction(0xF000), Some(RawInstruction(0x1234))), Some(instruction));
+        assert_eq!(Instruction::decode(RawInstruction(0xF000)), None);
+    }
+
+    #[test]
+    fn low_addresses_decode_across_the_whole_class() {
+        let cases = [
+            (0x1000, Some(Instruction::JumpToAddress(0x000))),
+            (0x11FF, Some(Instruction::JumpToAddress(0x1FF))),
+            (0x2000, Some(Instruction::ExecSubroutine(0x000))),
+            (0xA000, Some(Instruction::StoreAddressToI(0x000))),
+            (0xA1FF, Some(Instruction::StoreAddressToI(0x1FF))),
+            (0xB000, Some(Instruction::JumpWithOffset(0x000))),
+            (0xB1FF, Some(Instruction::JumpWithOffset(0x1FF))),
+            (0x0FFF, Some(Instruction::ExecSubroutineML(0xFFF))),
+            (0x0001, Some(Instruction::ExecSubroutineML(0x001))),
+            (0x0000, None)
+        ];
+        for (opcode, expected) in cases {
+            assert_eq!(Instruction::decode(RawInstruction(opcode)), expected, "{:04X}", opcode);
+        }
+    }
+
+    #[test]
+    fn jumps_decode_for_every_target() {
+        for (opcode, target) in [(0x1000, 0x000), (0x11FF, 0x1FF), (0x1FFF, 0xFFF)] {
+            assert_eq!(Instruction::decode(RawInstruction(opcode)), Some(Instruction::JumpToAddress(target)));
+        }
+    }
+
+    #[test]
+    fn calls_decode_for_every_target() {
+        for (opcode, target) in [(0x2000, 0x000), (0x2100, 0x100), (0x2FFF, 0xFFF)] {
+            assert_eq!(Instruction::decode(RawInstruction(opcode)), Some(Instruction::ExecSubroutine(target)));
+        }
+    }
+
+    #[test]
+    fn loads_of_i_decode_for_every_address() {
+        for (opcode, address) in [(0xA000, 0x000), (0xA050, 0x050), (0xA1FF, 0x1FF)] {
+            assert_eq!(Instruction::decode(RawInstruction(opcode)), Some(Instruction::StoreAddressToI(address)));
+        }
+    }
+
+    #[test]
+    fn offset_jumps_decode_for_every_address() {
+        for (opcode, address) in [(0xB000, 0x000), (0xB123, 0x123), (0xBFFF, 0xFFF)] {
+            assert_eq!(Instruction::decode(RawInstruction(opcode)), Some(Instruction::JumpWithOffset(address)));
+        }
+    }
+
+    #[test]
+    fn machine_code_calls_decode_below_0x200_but_not_at_zero() {
+        assert_eq!(Instruction::decode(RawInstruction(0x0100)), Some(Instruction::ExecSubroutineML(0x100)));
+        assert_eq!(Instruction::decode(RawInstruction(0x01FF)), Some(Instruction::ExecSubroutineML(0x1FF)));
+        // uninitialised memory rather than a call, it goes to the unknown opcode policy
+        assert_eq!(Instruction::decode(RawInstruction(0x0000)), None);
+    }
+}
@@ -1,10 +1,59 @@
-use std::fmt::{Debug, Display, Formatter, Write};
-use std::num::ParseIntError;
+use std::fmt::{Debug, Display, Formatter};
 use std::str::FromStr;
 use std::error::Error;
 use raqote::Color;
+use std::ops::Range;
+use chip8_rs_dmfg::chip8::{MAX_MEMORY, MIN_MEMORY};
+use chip8_rs_dmfg::chip8_instruction_set::Address;
 
-#[derive(Debug)]
+/// Parses a decimal or 0x-prefixed hexadecimal address
+pub fn parse_address(s: &str) -> Result<Address, String> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => Address::from_str_radix(hex, 16),
+        None => s.parse::<Address>()
+    };
+    parsed.map_err(|e| format!("Invalid address '{}': {}", s, e))
+}
+
+/// Parses a register name like V3 or vA
+pub fn parse_register(s: &str) -> Result<u8, String> {
+    s.strip_prefix('V').or_else(|| s.strip_prefix('v'))
+        .and_then(|index| u8::from_str_radix(index, 16).ok())
+        .filter(|index| *index <= 0xF)
+        .ok_or_else(|| format!("Invalid register '{}', expected V0 to VF", s))
+}
+
+/// Parses an address range written start..end, the end is exclusive
+pub fn parse_address_range(s: &str) -> Result<Range<Address>, String> {
+    let (start, end) = s.split_once("..").ok_or_else(|| format!("Invalid range '{}', expected start..end", s))?;
+    let (start, end) = (parse_address(start)?, parse_address(end)?);
+    if start < end {
+        Ok(start..end)
+    }else{
+        Err(format!("Range '{}' is empty", s))
+    }
+}
+
+/// Parses a memory size, programs start at 0x200 and only XO-CHIP can address past the first 4 KiB
+pub fn parse_memory_size(s: &str) -> Result<usize, String> {
+    let size = s.parse::<usize>().map_err(|e| format!("Invalid memory size '{}': {}", s, e))?;
+    if (MIN_MEMORY..=MAX_MEMORY).contains(&size) {
+        Ok(size)
+    }else{
+        Err(format!("Memory size must be between {} and {} bytes", MIN_MEMORY, MAX_MEMORY))
+    }
+}
+
+pub fn parse_stack_size(s: &str) -> Result<usize, String> {
+    let size = s.parse::<usize>().map_err(|e| format!("Invalid stack size '{}': {}", s, e))?;
+    if size > 0 {
+        Ok(size)
+    }else{
+        Err("Stack size must be at least 1".to_string())
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct CliColor {
     color: (u8, u8, u8, u8)
 }
@@ -17,13 +66,22 @@ impl CliColor {
     }
 }
 
-impl Into<Color> for CliColor {
-    fn into(self) -> Color {
-        let (a, r, g, b) = self.color;
+impl From<CliColor> for Color {
+    fn from(color: CliColor) -> Color {
+        let (a, r, g, b) = color.color;
         Color::new(a, r, g, b)
     }
 }
 
+impl CliColor {
+    /// Per channel average of two colors
+    pub fn blend(&self, other: &CliColor) -> CliColor {
+        let ((a0, r0, g0, b0), (a1, r1, g1, b1)) = (self.color, other.color);
+        let average = |x: u8, y: u8| ((x as u16 + y as u16) / 2) as u8;
+        CliColor::new(average(a0, a1), average(r0, r1), average(g0, g1), average(b0, b1))
+    }
+}
+
 impl Display for CliColor {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let (a, r, g, b) = self.color;
@@ -31,26 +89,89 @@ impl Display for CliColor {
     }
 }
 
+type Argb = (u8, u8, u8, u8);
+
+const NAMED_COLORS: [(&str, Argb); 10] = [
+    ("black", (255, 0, 0, 0)),
+    ("white", (255, 255, 255, 255)),
+    ("red", (255, 255, 0, 0)),
+    ("green", (255, 0, 255, 0)),
+    ("blue", (255, 0, 0, 255)),
+    ("yellow", (255, 255, 255, 0)),
+    ("amber", (255, 255, 176, 0)),
+    ("cyan", (255, 0, 255, 255)),
+    ("magenta", (255, 255, 0, 255)),
+    ("navy", (255, 0, 0, 128))
+];
+
 #[derive(Debug)]
 pub enum CliColorErr {
     ParseValueError(String),
-    NotEnoughValuesError(usize)
+    NotEnoughValuesError(usize),
+    InvalidHexDigit(String),
+    InvalidHexLength(String),
+    UnknownColorName(String)
 }
 
 impl Display for CliColorErr {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            CliColorErr::ParseValueError(s) => f.write_str(&format!("Failed to parse input color '{}'", s)),
-            CliColorErr::NotEnoughValuesError(v) => f.write_str(&format!("Not enough values provided for color, 4 needed {} provided", v))
+            CliColorErr::ParseValueError(s) => f.write_str(&format!("Failed to parse input color '{}', each of a,r,g,b must be 0-255", s)),
+            CliColorErr::NotEnoughValuesError(v) => f.write_str(&format!("Not enough values provided for color, 4 needed {} provided", v)),
+            CliColorErr::InvalidHexDigit(s) => f.write_str(&format!("Hex color '{}' contains a character that is not a hex digit", s)),
+            CliColorErr::InvalidHexLength(s) => f.write_str(&format!("Hex color '{}' should have 3, 6 or 8 digits (#RGB, #RRGGBB or #AARRGGBB)", s)),
+            CliColorErr::UnknownColorName(s) => {
+                let names = NAMED_COLORS.iter().map(|(name, _)| *name).collect::<Vec<&str>>();
+                f.write_str(&format!("Unknown color '{}', use a,r,g,b values, #RRGGBB hex or one of: {}", s, names.join(", ")))
+            }
         }
     }
 }
 
+impl CliColor {
+    /// Parses `#RGB`, `#RRGGBB` or `#AARRGGBB`, alpha defaults to opaque
+    fn from_hex(s: &str) -> Result<Self, CliColorErr> {
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(CliColorErr::InvalidHexDigit(s.to_string()));
+        }
+        let digits = match hex.len() {
+            3 => hex.chars().flat_map(|c| [c, c]).collect::<String>(),
+            6 => hex.to_string(),
+            8 => hex.to_string(),
+            _ => return Err(CliColorErr::InvalidHexLength(s.to_string()))
+        };
+        let values = (0..digits.len()).step_by(2)
+            .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap())
+            .collect::<Vec<u8>>();
+        match values[..] {
+            [r, g, b] => Ok(CliColor::new(255, r, g, b)),
+            [a, r, g, b] => Ok(CliColor::new(a, r, g, b)),
+            _ => unreachable!()
+        }
+    }
+}
+
+/// Accepts `a,r,g,b` values, hex (`#F00`, `#FF0000`, `#FFFF0000`) or a color name
 impl FromStr for CliColor {
     type Err = CliColorErr;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let argb = s.split(",").map(|c|c.parse::<u8>());
+        let s = s.trim();
+        if s.starts_with('#') {
+            return CliColor::from_hex(s);
+        }
+        if !s.contains(',') {
+            if let Some((_, (a, r, g, b))) = NAMED_COLORS.iter().find(|(name, _)| name.eq_ignore_ascii_case(s)) {
+                return Ok(CliColor::new(*a, *r, *g, *b));
+            }
+            // hex without the leading #
+            if s.chars().all(|c| c.is_ascii_hexdigit()) {
+                return CliColor::from_hex(s);
+            }
+            return Err(CliColorErr::UnknownColorName(s.to_string()));
+        }
+        let argb = s.split(",").map(|c|c.trim().parse::<u8>());
         let mut values = Vec::new();
         for v in argb {
             match v {
@@ -75,4 +196,146 @@ impl FromStr for CliColor {
 
 impl Error for CliColorErr {
 
-}
\ No newline at end of file
+}
+
+/// Foreground and background color presets
+#[derive(Debug, Clone, Copy)]
+pub enum Theme {
+    Gameboy,
+    Amber,
+    Paperwhite
+}
+
+impl Theme {
+    /// Returns the (foreground, background) colors of the theme
+    pub fn colors(&self) -> (CliColor, CliColor) {
+        match self {
+            Theme::Gameboy => (CliColor::new(255, 15, 56, 15), CliColor::new(255, 155, 188, 15)),
+            Theme::Amber => (CliColor::new(255, 255, 176, 0), CliColor::new(255, 40, 20, 0)),
+            Theme::Paperwhite => (CliColor::new(255, 32, 32, 32), CliColor::new(255, 245, 245, 235))
+        }
+    }
+}
+
+impl FromStr for Theme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "gameboy" => Ok(Theme::Gameboy),
+            "amber" => Ok(Theme::Amber),
+            "paperwhite" => Ok(Theme::Paperwhite),
+            _ => Err(format!("Unknown theme '{}', expected gameboy, amber or paperwhite", s))
+        }
+    }
+}
+/// How many instructions run per frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timing {
+    /// A fixed number of instructions, every instruction costs the same
+    Simple,
+    /// Each frame runs the COSMAC VIP's budget of machine cycles, so slow instructions like DXYN leave fewer for the rest
+    Vip
+}
+
+impl FromStr for Timing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "simple" => Ok(Timing::Simple),
+            "vip" => Ok(Timing::Vip),
+            _ => Err(format!("Unknown timing '{}', expected simple or vip", s))
+        }
+    }
+}
+
+impl Display for Timing {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Timing::Simple => f.write_str("simple"),
+            Timing::Vip => f.write_str("vip")
+        }
+    }
+}
+
+/// Window library the --plain loop runs with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrontendKind {
+    Minifb,
+    /// Only available when built with the `sdl2` feature
+    Sdl,
+    /// Half block characters in the terminal, for running over SSH
+    Terminal
+}
+
+impl FromStr for FrontendKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "minifb" => Ok(FrontendKind::Minifb),
+            "sdl" if cfg!(feature = "sdl2") => Ok(FrontendKind::Sdl),
+            "sdl" => Err("This build has no SDL support, rebuild with --features sdl2".to_string()),
+            "terminal" => Ok(FrontendKind::Terminal),
+            _ => Err(format!("Unknown frontend '{}', expected minifb, sdl or terminal", s))
+        }
+    }
+}
+
+impl Display for FrontendKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FrontendKind::Minifb => f.write_str("minifb"),
+            FrontendKind::Sdl => f.write_str("sdl"),
+            FrontendKind::Terminal => f.write_str("terminal")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn argb(s: &str) -> Argb {
+        s.parse::<CliColor>().unwrap_or_else(|e| panic!("{}: {}", s, e)).color
+    }
+
+    #[test]
+    fn hex_colors_parse_in_any_case() {
+        for s in ["#F80", "#f80", "#FF8800", "#ff8800", "#FFFF8800", "#ffff8800", "F80", "ff8800", "FFFF8800"] {
+            assert_eq!(argb(s), (255, 255, 136, 0), "{}", s);
+        }
+        assert_eq!(argb("#80FF8800"), (128, 255, 136, 0));
+        assert_eq!(argb("#8aBcDeF0"), (0x8A, 0xBC, 0xDE, 0xF0));
+    }
+
+    #[test]
+    fn named_colors_parse_in_any_case() {
+        for (name, expected) in NAMED_COLORS {
+            assert_eq!(argb(name), expected, "{}", name);
+            assert_eq!(argb(&name.to_uppercase()), expected, "{}", name);
+        }
+        assert_eq!(argb("  Amber "), (255, 255, 176, 0));
+    }
+
+    #[test]
+    fn argb_values_parse() {
+        assert_eq!(argb("255,1,2,3"), (255, 1, 2, 3));
+        assert_eq!(argb(" 0, 10 ,20,30"), (0, 10, 20, 30));
+    }
+
+    #[test]
+    fn malformed_colors_say_what_was_wrong() {
+        let error = |s: &str| s.parse::<CliColor>().unwrap_err();
+        assert!(matches!(error("256,0,0,0"), CliColorErr::ParseValueError(_)));
+        assert!(matches!(error("1,2,x,4"), CliColorErr::ParseValueError(_)));
+        assert!(matches!(error("1,2,3"), CliColorErr::NotEnoughValuesError(3)));
+        assert!(matches!(error("1,2,3,4,5"), CliColorErr::NotEnoughValuesError(5)));
+        assert!(matches!(error("#12345"), CliColorErr::InvalidHexLength(_)));
+        assert!(matches!(error("#"), CliColorErr::InvalidHexLength(_)));
+        assert!(matches!(error("#GG0000"), CliColorErr::InvalidHexDigit(_)));
+        assert!(matches!(error("##F00"), CliColorErr::InvalidHexDigit(_)));
+        assert!(matches!(error("nocolor"), CliColorErr::UnknownColorName(_)));
+    }
+}
@@ -0,0 +1,75 @@
+use std::{thread, time};
+use minifb::{Key, KeyRepeat, Window, WindowOptions};
+use raqote::{Color, DrawTarget, SolidSource};
+use chip8_rs_dmfg::chip8::{Chip8, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use crate::keymap::Keymap;
+use crate::{draw_display, fill_pixel};
+
+/// Runs two machines side by side in one window, A on the left and B on the right.
+/// Both get the same keys and frames, so any difference on screen comes from their settings or ROMs.
+/// With `highlight` set, pixels that differ between the two are drawn in that color on both sides.
+pub fn run(mut machines: [Chip8; 2], keymap: &Keymap, palette: &[Color; 4], highlight: Option<Color>, scale: usize, title: &str) {
+    let (half_width, height) = (DISPLAY_WIDTH * scale, DISPLAY_HEIGHT * scale);
+    let width = half_width * 2;
+    let mut screen = DrawTarget::new(width as i32, height as i32);
+    let mut window = Window::new(&format!("{} (A | B)", title), width, height, WindowOptions::default()).unwrap();
+    let mut last_frame = time::Instant::now();
+    let mut paused = false;
+    while window.is_open() {
+        if !paused {
+            for (name, chip) in ["A", "B"].iter().zip(machines.iter_mut()) {
+                if let Err(e) = chip.step() {
+                    eprintln!("Emulation stopped on {}: {}", name, e);
+                    eprintln!("{}", crate::dump::format_dump(chip));
+                    std::process::exit(1);
+                }
+            }
+        }else{
+            thread::sleep(time::Duration::from_millis(1));
+        }
+        if last_frame.elapsed().as_secs_f32() >= 1f32/60f32 {
+            last_frame = time::Instant::now();
+            if !paused {
+                machines.iter_mut().for_each(|chip| chip.decrement_time());
+            }
+            // differences can appear anywhere, so both sides are redrawn in full
+            screen.clear(SolidSource::from(palette[0]));
+            for (side, chip) in machines.iter().enumerate() {
+                draw_display(chip, &mut screen, palette, (side * half_width) as f32, half_width as f32, u64::MAX);
+            }
+            if let Some(color) = highlight {
+                draw_differences(&machines, &mut screen, color, half_width as f32);
+            }
+            window.update_with_buffer(screen.get_data(), width, height).unwrap();
+
+            let pressed_keys = window.get_keys_pressed(KeyRepeat::No);
+            for key in pressed_keys.iter().filter_map(|k|keymap.hex_key(k)) {
+                machines.iter_mut().for_each(|chip| chip.set_key_state(key, true));
+            }
+            for key in window.get_keys_released().iter().filter_map(|k|keymap.hex_key(k)) {
+                machines.iter_mut().for_each(|chip| chip.set_key_state(key, false));
+            }
+            if pressed_keys.contains(&Key::F1) {
+                machines.iter_mut().for_each(|chip| chip.reset());
+            }
+            if pressed_keys.contains(&Key::P) {
+                paused = !paused;
+            }
+        }
+    }
+}
+
+/// Only compares displays at the same resolution, pixels don't line up otherwise
+fn draw_differences(machines: &[Chip8; 2], screen: &mut DrawTarget, color: Color, half_width: f32) {
+    let [a, b] = machines;
+    if a.get_screen_size() != b.get_screen_size() {
+        return;
+    }
+    let (display_width, _) = a.get_screen_size();
+    let pixel_size = half_width / display_width as f32;
+    for i in (0..a.get_display().len()).filter(|i| a.pixel_color(*i) != b.pixel_color(*i)) {
+        let (x, y) = ((i % display_width) as f32 * pixel_size, (i / display_width) as f32 * pixel_size);
+        fill_pixel(screen, x, y, pixel_size, color);
+        fill_pixel(screen, half_width + x, y, pixel_size, color);
+    }
+}
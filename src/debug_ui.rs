@@ -0,0 +1,227 @@
+use eframe::egui::{self, Color32, RichText, Sense};
+use minifb::Key;
+use raqote::Color;
+use chip8_rs_dmfg::chip8::{Chip8, TickStatus, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use chip8_rs_dmfg::disassembler::disassemble;
+use chip8_rs_dmfg::frame_clock::FrameClock;
+use chip8_rs_dmfg::frontend::AudioSink;
+use crate::keymap::Keymap;
+
+/// Bytes of memory shown before the PC in the disassembly, the rest of the window comes after it
+const DISASSEMBLY_BEFORE: usize = 16;
+const DISASSEMBLY_AFTER: usize = 32;
+
+/// egui keys a keymap can name. Keymaps are written in terms of minifb keys and egui has no names for the rest.
+const KEYS: [(egui::Key, Key); 43] = [
+    (egui::Key::Num0, Key::Key0), (egui::Key::Num1, Key::Key1), (egui::Key::Num2, Key::Key2), (egui::Key::Num3, Key::Key3),
+    (egui::Key::Num4, Key::Key4), (egui::Key::Num5, Key::Key5), (egui::Key::Num6, Key::Key6), (egui::Key::Num7, Key::Key7),
+    (egui::Key::Num8, Key::Key8), (egui::Key::Num9, Key::Key9),
+    (egui::Key::A, Key::A), (egui::Key::B, Key::B), (egui::Key::C, Key::C), (egui::Key::D, Key::D), (egui::Key::E, Key::E),
+    (egui::Key::F, Key::F), (egui::Key::G, Key::G), (egui::Key::H, Key::H), (egui::Key::I, Key::I), (egui::Key::J, Key::J),
+    (egui::Key::K, Key::K), (egui::Key::L, Key::L), (egui::Key::M, Key::M), (egui::Key::N, Key::N), (egui::Key::O, Key::O),
+    (egui::Key::P, Key::P), (egui::Key::Q, Key::Q), (egui::Key::R, Key::R), (egui::Key::S, Key::S), (egui::Key::T, Key::T),
+    (egui::Key::U, Key::U), (egui::Key::V, Key::V), (egui::Key::W, Key::W), (egui::Key::X, Key::X), (egui::Key::Y, Key::Y),
+    (egui::Key::Z, Key::Z),
+    (egui::Key::ArrowUp, Key::Up), (egui::Key::ArrowDown, Key::Down), (egui::Key::ArrowLeft, Key::Left),
+    (egui::Key::ArrowRight, Key::Right), (egui::Key::Space, Key::Space), (egui::Key::Enter, Key::Enter), (egui::Key::Tab, Key::Tab)
+];
+
+/// The game with a panel of machine state next to it, in one egui window
+pub struct DebugApp {
+    chip: Chip8,
+    keymap: Keymap,
+    palette: [Color32; 4],
+    audio: Box<dyn AudioSink>,
+    instructions_per_frame: u32,
+    frame_clock: FrameClock,
+    paused: bool,
+    // the last error or halt, shown until the machine runs again
+    status: Option<String>
+}
+
+impl DebugApp {
+    pub fn new(chip: Chip8, keymap: Keymap, palette: &[Color; 4], audio: Box<dyn AudioSink>, instructions_per_frame: u32) -> Self {
+        DebugApp {
+            chip,
+            keymap,
+            palette: palette.map(|color| Color32::from_rgb(color.r(), color.g(), color.b())),
+            audio,
+            instructions_per_frame,
+            frame_clock: FrameClock::new(),
+            paused: false,
+            status: None
+        }
+    }
+
+    fn run_frames(&mut self, frames: u32) {
+        for _ in 0..frames {
+            match self.chip.run_frame(self.instructions_per_frame) {
+                Ok(frame) => {
+                    self.audio.set_beep(frame.sound_active);
+                    if let Some(status) = frame.stopped_by.filter(|status| *status != TickStatus::WaitingForFrame) {
+                        self.stopped(status);
+                        return;
+                    }
+                }
+                Err(e) => {
+                    self.status = Some(e.to_string());
+                    self.paused = true;
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Runs one instruction for the Step button
+    fn step(&mut self) {
+        match self.chip.step() {
+            Ok(outcome) => self.stopped(outcome.status),
+            Err(e) => {
+                self.status = Some(e.to_string());
+                self.paused = true;
+            }
+        }
+    }
+
+    /// Pauses on anything that needs a look before running on
+    fn stopped(&mut self, status: TickStatus) {
+        match status {
+            TickStatus::Executed | TickStatus::WaitingForFrame => {}
+            TickStatus::Halted => {
+                self.status = self.chip.halted().map(|reason| reason.to_string());
+                self.paused = true;
+            }
+            _ => self.paused = true
+        }
+    }
+
+    /// Keys go to the game unless a text field or drag value in the panel has focus
+    fn forward_keys(&mut self, ctx: &egui::Context) {
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+        let events = ctx.input(|input| input.events.clone());
+        for event in events {
+            if let egui::Event::Key { key, pressed, .. } = event {
+                let hex_key = KEYS.iter()
+                    .find(|(egui_key, _)| *egui_key == key)
+                    .and_then(|(_, key)| self.keymap.hex_key(key));
+                if let Some(hex_key) = hex_key {
+                    self.chip.set_key_state(hex_key, pressed);
+                }
+            }
+        }
+    }
+
+    fn controls(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui.button(if self.paused {"Run"} else {"Pause"}).clicked() {
+                self.paused = !self.paused;
+                self.status = None;
+            }
+            if ui.add_enabled(self.paused, egui::Button::new("Step")).clicked() {
+                self.status = None;
+                self.step();
+            }
+            if ui.button("Reset").clicked() {
+                self.chip.reset();
+                self.status = None;
+            }
+        });
+        if let Some(status) = &self.status {
+            ui.label(RichText::new(status.as_str()).color(Color32::YELLOW));
+        }
+    }
+
+    fn registers(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Registers");
+        egui::Grid::new("registers").num_columns(4).show(ui, |ui| {
+            for register in 0..16u8 {
+                let mut value = self.chip.registers()[register as usize];
+                ui.label(RichText::new(format!("V{:X}", register)).monospace());
+                let edited = ui.add(egui::DragValue::new(&mut value).clamp_range(0..=255).hexadecimal(2, false, true));
+                if edited.changed() {
+                    self.chip.set_register(register, value);
+                }
+                if register % 2 == 1 {
+                    ui.end_row();
+                }
+            }
+        });
+        ui.label(RichText::new(format!("PC  0x{:03X}", self.chip.pc())).monospace());
+        ui.label(RichText::new(format!("I   0x{:03X}", self.chip.i())).monospace());
+        ui.label(RichText::new(format!("DT  {}", self.chip.delay_timer())).monospace());
+        ui.label(RichText::new(format!("ST  {}", self.chip.sound_timer())).monospace());
+    }
+
+    fn stack(&self, ui: &mut egui::Ui) {
+        ui.heading("Stack");
+        if self.chip.stack().is_empty() {
+            ui.label("empty");
+        }
+        // innermost call first
+        for address in self.chip.stack().iter().rev() {
+            ui.label(RichText::new(format!("0x{:03X}", address)).monospace());
+        }
+    }
+
+    fn disassembly(&self, ui: &mut egui::Ui) {
+        ui.heading("Disassembly");
+        let pc = self.chip.pc() as usize;
+        let memory = self.chip.memory();
+        // starting an even number of bytes before the PC keeps the decoding in step with it
+        let start = pc.saturating_sub(DISASSEMBLY_BEFORE).min(memory.len());
+        let end = (pc + DISASSEMBLY_AFTER).min(memory.len());
+        for word in disassemble(&memory[start..end], start as u16) {
+            let text = RichText::new(word.to_string()).monospace();
+            ui.label(if word.address as usize == pc {text.color(Color32::YELLOW)} else {text});
+        }
+    }
+
+    fn display(&self, ui: &mut egui::Ui) {
+        let (width, height) = self.chip.get_screen_size();
+        let available = ui.available_size();
+        let pixel_size = (available.x / width as f32).min(available.y / height as f32).max(1.0);
+        let (response, painter) = ui.allocate_painter(egui::vec2(pixel_size * width as f32, pixel_size * height as f32), Sense::hover());
+        let origin = response.rect.min;
+        for i in 0..width * height {
+            let position = origin + egui::vec2((i % width) as f32 * pixel_size, (i / width) as f32 * pixel_size);
+            painter.rect_filled(egui::Rect::from_min_size(position, egui::vec2(pixel_size, pixel_size)), 0.0, self.palette[self.chip.pixel_color(i)]);
+        }
+    }
+}
+
+impl eframe::App for DebugApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.forward_keys(ctx);
+        let frames = self.frame_clock.frames_elapsed();
+        if self.paused {
+            self.audio.set_beep(false);
+        }else{
+            self.run_frames(frames);
+        }
+        egui::SidePanel::right("debugger").show(ctx, |ui| {
+            self.controls(ui);
+            ui.separator();
+            self.registers(ui);
+            ui.separator();
+            self.stack(ui);
+            ui.separator();
+            self.disassembly(ui);
+        });
+        egui::CentralPanel::default().show(ctx, |ui| self.display(ui));
+        // the machine keeps running between input events
+        ctx.request_repaint();
+    }
+}
+
+/// Runs the machine in a window with the debugger panel until the window is closed
+pub fn run(mut app: DebugApp, paused: bool, scale: usize, title: &str) -> Result<(), eframe::Error> {
+    app.paused = paused;
+    let options = eframe::NativeOptions {
+        // room for the panel next to the display
+        initial_window_size: Some(egui::vec2((DISPLAY_WIDTH * scale) as f32 + 320.0, (DISPLAY_HEIGHT * scale).max(640) as f32)),
+        ..Default::default()
+    };
+    eframe::run_native(title, options, Box::new(|_| Box::new(app)))
+}
@@ -0,0 +1,576 @@
+use std::{fs, thread, time};
+use std::time::{Duration, Instant};
+use indicatif::{ProgressBar, ProgressStyle};
+use log::LevelFilter;
+use raqote::Color;
+use chip8_rs_dmfg::chip8::{Chip8, FrameResult, HaltReason, StepOutcome, TickStatus};
+use chip8_rs_dmfg::chip8_error::Chip8Error;
+use chip8_rs_dmfg::chip8_instruction_set::{Address, Instruction, VIP_CYCLES_PER_FRAME};
+use chip8_rs_dmfg::emulation_thread::{Command, EmulationThread, FrameRunner};
+use chip8_rs_dmfg::frame_clock::FrameClock;
+use chip8_rs_dmfg::frontend::{AudioSink, Control, InputSource, KeyEvent, NoAudio, Renderer};
+use chip8_rs_dmfg::recording::{InputEvent, Recording, Replay};
+use chip8_rs_dmfg::rewind::RewindBuffer;
+use chip8_rs_dmfg::rom_database;
+use chip8_rs_dmfg::save_state::SaveState;
+use crate::audio::Beeper;
+use crate::capture::{self, GifRecorder};
+use crate::cli::Timing;
+use crate::gamepad::Gamepads;
+use crate::keymap::Keymap;
+use crate::minifb_frontend::MinifbFrontend;
+use crate::stats::{RunState, Stats, format_status};
+use crate::{Args, file_name, finish_gif, format_machine_state, pick_rom, start_gif, stop_with_error, window_title, write_dump};
+
+/// How long each frame runs instructions for with --ipf 0, leaving the rest of the frame to the commands
+const UNLIMITED_SLICE: Duration = Duration::from_millis(10);
+
+/// How many instructions a frame runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pace {
+    /// --timing simple, this many instructions
+    Instructions(u32),
+    /// --timing vip, as many as fit in the COSMAC VIP's cycles for a frame
+    VipCycles,
+    /// --ipf 0, as many as fit in `UNLIMITED_SLICE`
+    Unlimited
+}
+
+impl Pace {
+    fn new(timing: Timing, ipf: u32) -> Self {
+        match timing {
+            Timing::Vip => Pace::VipCycles,
+            Timing::Simple if ipf == 0 => Pace::Unlimited,
+            Timing::Simple => Pace::Instructions(ipf)
+        }
+    }
+}
+
+/// Runs the default window's machine on the emulation thread, with the recording, replay, rewinding and GIF
+/// capture that happen once a frame
+pub struct DesktopRunner {
+    pace: Pace,
+    /// VIP cycles the last frame ran over its budget by, taken from the next one
+    carry: u32,
+    replay: Option<Replay>,
+    recording: Option<Recording>,
+    rewind: RewindBuffer,
+    rewinding: bool,
+    /// Emulation stops while the window is in the background
+    focused: bool,
+    gif: Option<GifRecorder>,
+    record_seconds: Option<u64>,
+    rom_path: String,
+    dump_to: Option<String>,
+    spinner: ProgressBar
+}
+
+impl DesktopRunner {
+    /// Starts the --record-gif recording right away, a recording or replay turns rewinding off
+    pub fn new(args: &Args, rom_path: &str, palette: &[Color; 4], replay: Option<Replay>, recording: Option<Recording>) -> Self {
+        let spinner = ProgressBar::new_spinner();
+        // wide_msg cuts the status short instead of wrapping it on a narrow terminal
+        spinner.set_style(ProgressStyle::with_template("{spinner} {elapsed} {wide_msg}").unwrap());
+        let scripted = recording.is_some() || replay.is_some();
+        DesktopRunner {
+            pace: Pace::new(args.timing, args.ipf),
+            carry: 0,
+            replay,
+            recording,
+            rewind: RewindBuffer::new(
+                if scripted {0} else {args.rewind_seconds * 60 / args.rewind_interval.max(1)},
+                args.rewind_interval,
+                args.rewind_memory * 1024 * 1024
+            ),
+            rewinding: false,
+            focused: true,
+            gif: args.record_gif.as_deref().and_then(|path| start_gif(path, palette, &spinner)),
+            record_seconds: args.record_seconds,
+            rom_path: rom_path.to_string(),
+            dump_to: args.dump_to.clone(),
+            spinner
+        }
+    }
+
+    fn costed_frame(&mut self, chip: &mut Chip8) -> Result<FrameResult, Chip8Error> {
+        match self.pace {
+            Pace::Instructions(instructions) => chip.run_frame(instructions),
+            Pace::VipCycles => {
+                let result = chip.run_frame_costed(VIP_CYCLES_PER_FRAME.saturating_sub(self.carry), Instruction::cycle_cost)?;
+                // an instruction that ran past the end of the budget takes its extra cycles from the next frame
+                self.carry = (self.carry + result.cycles).saturating_sub(VIP_CYCLES_PER_FRAME);
+                Ok(result)
+            }
+            Pace::Unlimited => {
+                let deadline = Instant::now() + UNLIMITED_SLICE;
+                chip.run_frame_costed(1, |_| (Instant::now() >= deadline) as u32)
+            }
+        }
+    }
+
+    /// A replay counts the timers down at the recorded frames rather than at the end of each `run_frame`, so its
+    /// frames step the machine one instruction at a time with the recorded input applied before each
+    fn replay_frame(&mut self, chip: &mut Chip8) -> Result<FrameResult, Chip8Error> {
+        let deadline = Instant::now() + UNLIMITED_SLICE;
+        let (budget, vip) = match self.pace {
+            Pace::Instructions(instructions) => (instructions, false),
+            Pace::VipCycles => (VIP_CYCLES_PER_FRAME.saturating_sub(self.carry), true),
+            Pace::Unlimited => (1, false)
+        };
+        let mut result = FrameResult::default();
+        while result.cycles < budget {
+            let outcome = self.step_instruction(chip)?;
+            result.display_changed |= outcome.display_changed;
+            if let Some(instruction) = outcome.instruction {
+                result.instructions += 1;
+                result.cycles += match self.pace {
+                    Pace::Instructions(_) => 1,
+                    Pace::VipCycles => instruction.cycle_cost(),
+                    Pace::Unlimited => (Instant::now() >= deadline) as u32
+                };
+                result.last_instruction = Some((outcome.pc_before, instruction));
+            }
+            result.waiting_for_key = outcome.waiting_for_key;
+            match outcome.status {
+                // the recorded presses come at the recorded ticks, so a key wait keeps stepping like any other instruction
+                TickStatus::Executed => {}
+                TickStatus::WaitingForFrame => {
+                    result.stopped_by = Some(TickStatus::WaitingForFrame);
+                    break;
+                }
+                status => {
+                    result.stopped_by = Some(status);
+                    return Ok(result);
+                }
+            }
+        }
+        if vip {
+            self.carry = (self.carry + result.cycles).saturating_sub(VIP_CYCLES_PER_FRAME);
+        }
+        result.sound_active = chip.is_sound_active();
+        // the replay ran out partway through, the keyboard and the clock take over
+        if self.replay.is_none() {
+            chip.decrement_time();
+        }
+        Ok(result)
+    }
+
+    fn step_instruction(&mut self, chip: &mut Chip8) -> Result<StepOutcome, Chip8Error> {
+        if let Some(replay) = self.replay.as_mut() {
+            replay.apply(chip);
+            if replay.is_finished(chip) {
+                self.spinner.println("Replay finished, reading the keyboard again");
+                self.replay = None;
+            }
+        }
+        chip.step()
+    }
+
+    /// Runs one instruction while paused, for N
+    fn step(&mut self, chip: &mut Chip8) {
+        let mut outcome = self.step_instruction(chip);
+        if outcome.as_ref().is_ok_and(|outcome| outcome.status == TickStatus::WaitingForFrame) {
+            // no frames pass while paused, so stepping over a display wait moves on to the next frame
+            chip.decrement_time();
+            outcome = self.step_instruction(chip);
+        }
+        match outcome {
+            Ok(outcome) => {
+                self.report(chip, &outcome.status, outcome.instruction.map(|instruction| (outcome.pc_before, instruction)));
+                if outcome.status == TickStatus::Executed {
+                    self.spinner.println(format_machine_state(chip));
+                }
+            }
+            Err(e) => stop_with_error(chip, &self.spinner, e)
+        }
+    }
+
+    /// Prints why the machine stopped, with a dump for a halt worth looking into
+    fn report(&self, chip: &Chip8, status: &TickStatus, last_instruction: Option<(Address, Instruction)>) {
+        match status {
+            TickStatus::Executed | TickStatus::WaitingForFrame => {}
+            TickStatus::BreakpointHit(address) => {
+                self.spinner.println(format!("Breakpoint hit at 0x{:04x}", address));
+                self.spinner.println(format_machine_state(chip));
+            }
+            TickStatus::WatchHit(hit) => {
+                let (address, instruction) = last_instruction.map_or((chip.pc(), String::new()), |(address, instruction)| (address, instruction.to_string()));
+                self.spinner.println(format!("Watch hit at 0x{:04x} ({}): {}", address, instruction, hit));
+                self.spinner.println(format_machine_state(chip));
+            }
+            TickStatus::HookPaused(address) => {
+                self.spinner.println(format!("Paused by a hook at 0x{:04x}", address));
+                self.spinner.println(format_machine_state(chip));
+            }
+            TickStatus::Exited => self.spinner.println("Program exited"),
+            TickStatus::Halted => {
+                self.spinner.println(format!("Halted: {}", chip.halted().map_or(String::new(), |reason| reason.to_string())));
+                if matches!(chip.halted(), Some(HaltReason::Rejected(_) | HaltReason::PcOutOfBounds(_))) {
+                    write_dump(chip, &self.rom_path, &self.dump_to);
+                }
+            }
+        }
+    }
+
+    fn set_focused(&mut self, chip: &mut Chip8, focused: bool) {
+        self.focused = focused;
+        // the recording holds the key state during a replay
+        if !focused && self.replay.is_none() {
+            // key releases are missed while unfocused, so let go of everything to avoid stuck keys
+            chip.release_all_keys();
+            if let Some(recording) = self.recording.as_mut() {
+                recording.record(chip, InputEvent::ReleaseAll);
+            }
+        }
+    }
+
+    fn push_gif_frame(&mut self, chip: &Chip8) {
+        if let Some(recorder) = self.gif.as_mut() {
+            if let Err(e) = recorder.push(chip) {
+                self.spinner.println(format!("Failed to write GIF {}: {}", recorder.path(), e));
+                self.gif = None;
+            }
+        }
+        if self.gif.as_ref().is_some_and(|recorder| Some(recorder.seconds()) == self.record_seconds) {
+            if let Some(recorder) = self.gif.take() {
+                finish_gif(recorder, &self.spinner);
+            }
+        }
+    }
+}
+
+impl FrameRunner for DesktopRunner {
+    fn run_frame(&mut self, chip: &mut Chip8) -> Result<FrameResult, Chip8Error> {
+        if self.rewinding {
+            return Ok(FrameResult {display_changed: self.rewind.pop(chip), ..FrameResult::default()});
+        }
+        if !self.focused {
+            return Ok(FrameResult::default());
+        }
+        let result = if self.replay.is_some() {self.replay_frame(chip)?} else {self.costed_frame(chip)?};
+        match &result.stopped_by {
+            // anything else stops before the timers count down and the frame ends
+            None | Some(TickStatus::WaitingForFrame) => {
+                if let Some(recording) = self.recording.as_mut() {
+                    recording.record(chip, InputEvent::Frame);
+                }
+                self.rewind.push(chip);
+                self.push_gif_frame(chip);
+            }
+            Some(status) => self.report(chip, status, result.last_instruction)
+        }
+        Ok(result)
+    }
+
+    fn paused_frame(&mut self, chip: &mut Chip8) {
+        if self.rewinding {
+            self.rewind.pop(chip);
+        }
+    }
+
+    fn set_pressed(&mut self, chip: &mut Chip8, key: u8, pressed: bool) {
+        // the recording holds the key state during a replay
+        if self.replay.is_some() {
+            return;
+        }
+        chip.set_key_state(key, pressed);
+        if let Some(recording) = self.recording.as_mut() {
+            recording.record(chip, if pressed {InputEvent::Press(key)} else {InputEvent::Release(key)});
+        }
+    }
+}
+
+/// The default window: the machine runs on an `EmulationThread` with `runner` while this thread draws, plays the
+/// sound and turns the emulator's keys into commands
+pub fn run(mut chip: Chip8, runner: DesktopRunner, args: &Args, mut title: String, palette: [Color; 4], keymap: Keymap) {
+    // anything that moves the machine outside of the recorded input would desync the session
+    let scripted = runner.recording.is_some() || runner.replay.is_some();
+    let spinner = runner.spinner.clone();
+    let mut rom_path = runner.rom_path.clone();
+    let mut state_path = format!("{}.state0", rom_path);
+    let mut rom_name = file_name(&rom_path);
+    let mut frontend = MinifbFrontend::new(&title, args.display_scale as usize, palette, keymap.clone());
+    log::set_max_level(LevelFilter::Info);
+    args.breakpoints.iter().for_each(|address| chip.add_breakpoint(*address));
+    args.watch_reg.iter().for_each(|register| chip.add_register_watch(*register));
+    args.watch_mem.iter().for_each(|range| chip.add_memory_watch(range.clone()));
+    let mut paused = args.pause_on_start;
+    if paused {
+        spinner.println(format_machine_state(&chip));
+    }
+    // controllers are optional, plugging one in while running picks it up
+    let mut gamepads = Gamepads::new(keymap);
+    // without an audio device the emulator just runs silently
+    let mut audio: Box<dyn AudioSink> = match Beeper::new(args.min_beep) {
+        Ok(beeper) => Box::new(beeper),
+        Err(e) => {
+            spinner.println(format!("No audio output, running without sound: {}", e));
+            Box::new(NoAudio)
+        }
+    };
+    let worker = if paused {EmulationThread::spawn_paused(chip, runner)} else {EmulationThread::spawn(chip, runner)};
+    let mut frame_clock = FrameClock::new();
+    let mut stats = Stats::new();
+    let mut shown_title = title.clone();
+    let mut exited = false;
+    let mut waiting_for_key = false;
+    let mut focused = true;
+    let mut rewinding = false;
+    let mut halted_at = None;
+    // an error ends the emulation thread, join hands it over
+    'running: while !worker.is_finished() {
+        if frame_clock.frames_elapsed() == 0 {
+            thread::sleep(time::Duration::from_millis(1));
+            continue;
+        }
+        // every frame's sound gets played but only the newest display is drawn, with the rows changed in all of them
+        let mut dirty_rows = 0;
+        let mut latest = None;
+        for update in worker.updates() {
+            audio.set_beep(update.sound_active);
+            stats.sample_ticks(update.tick_count);
+            paused = update.paused;
+            halted_at = update.halted.as_ref().map(|_| update.pc);
+            if let Some(result) = &update.result {
+                waiting_for_key = result.waiting_for_key;
+                match result.stopped_by {
+                    Some(TickStatus::Exited) if args.exit_on_halt => break 'running,
+                    Some(TickStatus::Halted) if args.exit_on_halt && matches!(update.halted, Some(HaltReason::SelfJump(_))) => break 'running,
+                    Some(TickStatus::Exited) => exited = true,
+                    _ => {}
+                }
+            }
+            dirty_rows |= update.dirty_rows;
+            latest = update.display.or(latest);
+        }
+        if let Some(frame) = latest {
+            frontend.present(&frame, dirty_rows);
+            stats.frame_drawn();
+        }
+        let mut events = frontend.poll();
+        if focused {
+            events.extend(gamepads.as_mut().map(|pads| pads.poll()).unwrap_or_default());
+        }
+        for event in events {
+            match event {
+                KeyEvent::Pressed(key) => worker.send(Command::Key(key, true)),
+                KeyEvent::Released(key) => worker.send(Command::Key(key, false)),
+                KeyEvent::Focus(now_focused) => {
+                    focused = now_focused;
+                    worker.send(Command::With(Box::new(move |chip, runner| runner.set_focused(chip, now_focused))));
+                }
+                KeyEvent::Control(Control::Rewind(held)) => {
+                    rewinding = held;
+                    worker.send(Command::With(Box::new(move |_, runner| runner.rewinding = held)));
+                }
+                KeyEvent::Control(Control::Reset) if !scripted => {
+                    worker.with(|chip, runner| {
+                        chip.reset();
+                        runner.rewind.clear();
+                    });
+                    worker.send(Command::Pause(false));
+                    stats.clear();
+                    paused = false;
+                    exited = false;
+                    spinner.println("Reset");
+                }
+                // F2 reloads the ROM from disk and F3 picks another one, a ROM that fails to load leaves the current one running
+                KeyEvent::Control(control @ (Control::Reload | Control::Open)) if !scripted => {
+                    let path = match control {
+                        Control::Reload => Some(rom_path.clone()),
+                        _ => pick_rom()
+                    };
+                    let Some(path) = path else {continue};
+                    let loaded_path = path.clone();
+                    let loaded = fs::read(&path).map_err(|e| e.to_string()).and_then(|program| {
+                        let load = move |chip: &mut Chip8, runner: &mut DesktopRunner| chip.load(&program).map(|_| {
+                            runner.rewind.clear();
+                            runner.rom_path = loaded_path;
+                            program
+                        });
+                        worker.with(load).map_or_else(|| Err("the emulation stopped".to_string()), |loaded| loaded.map_err(|e| e.to_string()))
+                    });
+                    match loaded {
+                        Ok(program) => {
+                            rom_path = path;
+                            state_path = format!("{}.state0", rom_path);
+                            rom_name = file_name(&rom_path);
+                            title = window_title(&rom_name, if args.no_autodetect {None} else {rom_database::lookup(&program)});
+                            worker.send(Command::Pause(false));
+                            stats.clear();
+                            paused = false;
+                            exited = false;
+                            spinner.println(format!("Loaded {}", rom_path));
+                        }
+                        Err(e) => spinner.println(format!("Failed to load {}: {}", path, e))
+                    }
+                }
+                KeyEvent::Control(Control::SaveState) => {
+                    let saved = worker.with(|chip, _| chip.save_state())
+                        .map_or(Ok(()), |state| fs::write(&state_path, state.to_bytes()));
+                    match saved {
+                        Ok(_) => spinner.println(format!("Saved state to {}", state_path)),
+                        Err(e) => spinner.println(format!("Failed to save state to {}: {}", state_path, e))
+                    }
+                }
+                KeyEvent::Control(Control::LoadState) if !scripted => {
+                    let loaded = fs::read(&state_path).map_err(|e| e.to_string())
+                        .and_then(|bytes| SaveState::from_bytes(&bytes).map_err(|e| e.to_string()))
+                        .and_then(|state| worker.with(move |chip, _| chip.load_state(&state)).map_or(Ok(()), |loaded| loaded.map_err(|e| e.to_string())));
+                    match loaded {
+                        Ok(_) => spinner.println(format!("Loaded state from {}", state_path)),
+                        Err(e) => spinner.println(format!("Failed to load state from {}: {}", state_path, e))
+                    }
+                }
+                KeyEvent::Control(Control::Pause) => {
+                    paused = !paused;
+                    exited = false;
+                    worker.send(Command::Pause(paused));
+                    if paused {
+                        if let Some(state) = worker.with(|chip, _| format_machine_state(chip)) {
+                            spinner.println(state);
+                        }
+                    }
+                }
+                KeyEvent::Control(Control::Step) if paused => {
+                    worker.with(|chip, runner| runner.step(chip));
+                }
+                KeyEvent::Control(Control::Dump) => {
+                    worker.with(|chip, runner| write_dump(chip, &runner.rom_path, &runner.dump_to));
+                }
+                KeyEvent::Control(Control::ToggleGif) => match worker.with(|_, runner| runner.gif.take()).flatten() {
+                    Some(recorder) => finish_gif(recorder, &spinner),
+                    None => {
+                        if let Some(recorder) = start_gif(&capture::capture_path(&rom_path, "gif"), &palette, &spinner) {
+                            worker.send(Command::With(Box::new(move |_, runner| runner.gif = Some(recorder))));
+                        }
+                    }
+                }
+                KeyEvent::Control(Control::Screenshot) => {
+                    let path = capture::capture_path(&rom_path, "png");
+                    let scale = args.display_scale as usize;
+                    let png_path = path.clone();
+                    let written = worker.with(move |chip, _| capture::write_png(chip, &png_path, &palette, scale).map_err(|e| e.to_string()));
+                    match written {
+                        Some(Err(e)) => spinner.println(format!("Failed to save screenshot to {}: {}", path, e)),
+                        _ => spinner.println(format!("Saved screenshot to {}", path))
+                    }
+                }
+                KeyEvent::Control(_) => {}
+                KeyEvent::Quit => break 'running
+            }
+        }
+
+        let state = match halted_at {
+            _ if rewinding => RunState::Rewinding,
+            _ if !focused => RunState::Unfocused,
+            _ if exited => RunState::Exited,
+            Some(pc) => RunState::Halted(pc),
+            None if paused => RunState::Paused,
+            None if waiting_for_key => RunState::WaitingForKey,
+            None => RunState::Running
+        };
+        let state_title = if state == RunState::Running {title.clone()} else {format!("{} ({})", title, state)};
+        if state_title != shown_title {
+            frontend.set_title(&state_title);
+            shown_title = state_title;
+        }
+        spinner.set_message(format_status(&rom_name, &stats, state));
+        spinner.tick();
+    }
+    audio.set_beep(false);
+    let (mut chip, mut runner, result) = worker.join();
+    if let Err(e) = result {
+        stop_with_error(&mut chip, &spinner, e);
+    }
+    if let Some(recorder) = runner.gif.take() {
+        finish_gif(recorder, &spinner);
+    }
+    spinner.finish();
+    if args.dump_on_exit {
+        write_dump(&chip, &rom_path, &args.dump_to);
+    }
+    if let (Some(recording), Some(path)) = (runner.recording.as_mut(), &args.record) {
+        recording.finish(&chip);
+        if let Err(e) = fs::write(path, recording.to_string()) {
+            eprintln!("Failed to write recording to {}: {}", path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ADD V0, 1; JP 0x200
+    const COUNTER: [u8; 4] = [0x70, 0x01, 0x12, 0x00];
+
+    fn runner(pace: Pace) -> DesktopRunner {
+        DesktopRunner {
+            pace,
+            carry: 0,
+            replay: None,
+            recording: None,
+            rewind: RewindBuffer::new(10, 1, 1024 * 1024),
+            rewinding: false,
+            focused: true,
+            gif: None,
+            record_seconds: None,
+            rom_path: String::new(),
+            dump_to: None,
+            spinner: ProgressBar::hidden()
+        }
+    }
+
+    fn counter() -> Chip8 {
+        let mut chip = Chip8::default();
+        chip.load(&COUNTER).unwrap();
+        chip
+    }
+
+    #[test]
+    fn timing_options_pick_the_pace() {
+        assert_eq!(Pace::new(Timing::Simple, 15), Pace::Instructions(15));
+        assert_eq!(Pace::new(Timing::Simple, 0), Pace::Unlimited);
+        assert_eq!(Pace::new(Timing::Vip, 0), Pace::VipCycles);
+    }
+
+    #[test]
+    fn vip_frames_take_the_cycles_run_over_from_the_next_frame() {
+        let mut chip = counter();
+        let mut runner = runner(Pace::VipCycles);
+        let mut spent = 0;
+        for _ in 0..10 {
+            spent += runner.run_frame(&mut chip).unwrap().cycles;
+        }
+        assert_eq!(spent - runner.carry, 10 * VIP_CYCLES_PER_FRAME);
+    }
+
+    #[test]
+    fn losing_focus_stops_the_machine_and_lets_go_of_the_keys() {
+        let mut chip = counter();
+        let mut runner = runner(Pace::Instructions(4));
+        runner.set_pressed(&mut chip, 3, true);
+        runner.set_focused(&mut chip, false);
+        assert!(!chip.keys()[3]);
+        assert_eq!(runner.run_frame(&mut chip).unwrap().instructions, 0);
+        assert_eq!(chip.registers()[0], 0);
+    }
+
+    #[test]
+    fn rewinding_goes_back_a_frame_at_a_time() {
+        let mut chip = counter();
+        // one ADD per frame
+        let mut runner = runner(Pace::Instructions(2));
+        for _ in 0..3 {
+            runner.run_frame(&mut chip).unwrap();
+        }
+        assert_eq!(chip.registers()[0], 3);
+        runner.rewinding = true;
+        chip.set_register(0, 0);
+        assert!(runner.run_frame(&mut chip).unwrap().display_changed);
+        assert_eq!(chip.registers()[0], 3);
+        runner.run_frame(&mut chip).unwrap();
+        assert_eq!(chip.registers()[0], 2);
+    }
+}
@@ -0,0 +1,146 @@
+use core::fmt::{Display, Formatter};
+use core::ops::Range;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use crate::analysis::{analyze, BasicBlock};
+use crate::chip8_instruction_set::{Address, Instruction};
+
+pub struct DisassembledWord {
+    pub address: Address,
+    pub bytes: Vec<u8>,
+    pub instruction: Option<Instruction>
+}
+
+impl Display for DisassembledWord {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let raw = self.bytes.iter().map(|b| format!("{:02X}", b)).collect::<String>();
+        match (&self.instruction, self.bytes.as_slice()) {
+            (Some(instruction), _) => write!(f, "0x{:03X}: {:<4}  {}", self.address, raw, instruction),
+            (None, [_, _]) => write!(f, "0x{:03X}: {:<4}  .word 0x{}", self.address, raw, raw),
+            (None, _) => {
+                let data = self.bytes.iter().map(|b| format!("0x{:02X}", b)).collect::<Vec<String>>().join(" ");
+                write!(f, "0x{:03X}: {:<4}  .byte {}", self.address, raw, data)
+            }
+        }
+    }
+}
+
+/// Decodes `program` two bytes at a time as if it was loaded at `origin`, or four for XO-CHIP's `F000 NNNN`.
+/// Words that aren't instructions print as `.word` and a trailing odd byte as `.byte`.
+pub fn disassemble(program: &[u8], origin: Address) -> Vec<DisassembledWord> {
+    let mut words = Vec::with_capacity(program.len() / 2 + 1);
+    let mut offset = 0;
+    while offset < program.len() {
+        let instruction = match program[offset..] {
+            [upper, lower, next_upper, next_lower, ..] => Instruction::decode_long((upper, lower).into(), Some((next_upper, next_lower).into())),
+            [upper, lower, ..] => Instruction::decode((upper, lower).into()),
+            _ => None
+        };
+        let size = instruction.as_ref().map(|instruction| instruction.size()).unwrap_or(2).min(program.len() - offset);
+        words.push(DisassembledWord {
+            address: origin.wrapping_add(offset as Address),
+            bytes: program[offset..offset + size].to_vec(),
+            instruction
+        });
+        offset += size;
+    }
+    words
+}
+
+enum Section<'a> {
+    Code(&'a BasicBlock),
+    Data(Range<Address>)
+}
+
+impl Section<'_> {
+    fn start(&self) -> Address {
+        match self {
+            Section::Code(block) => block.start,
+            Section::Data(range) => range.start
+        }
+    }
+}
+
+/// Listing of only the code `analyze` can reach from `origin`, with `L_XXXX:` labels on branch targets.
+/// Everything else is printed as `.byte` data sections one byte per line, so sprite tables read as rows of pixels
+/// instead of bogus instructions.
+pub fn disassemble_smart(program: &[u8], origin: Address) -> Vec<String> {
+    let graph = analyze(program, origin);
+    let bytes_at = |address: Address, size: usize| {
+        let offset = address.wrapping_sub(origin) as usize;
+        program[offset..offset + size].to_vec()
+    };
+    let mut sections = graph.blocks.values().map(Section::Code)
+        .chain(graph.data_ranges().into_iter().map(Section::Data))
+        .collect::<Vec<Section>>();
+    sections.sort_by_key(|section| section.start());
+
+    let mut lines = Vec::new();
+    for section in sections {
+        if graph.branch_targets.contains(&section.start()) {
+            lines.push(format!("L_{:04X}:", section.start()));
+        }
+        match section {
+            Section::Code(block) => {
+                for (address, instruction) in block.instructions.iter() {
+                    let word = DisassembledWord {
+                        address: *address,
+                        bytes: bytes_at(*address, instruction.size()),
+                        instruction: Some(*instruction)
+                    };
+                    lines.push(word.to_string());
+                }
+            }
+            Section::Data(range) => {
+                lines.push("; data".to_string());
+                for address in range {
+                    let word = DisassembledWord {
+                        address,
+                        bytes: bytes_at(address, 1),
+                        instruction: None
+                    };
+                    lines.push(word.to_string());
+                }
+            }
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Chip8;
+
+    // CLS; LD V0, 0x2A; LD I, LONG 0x1234; an unknown word; JP 0x200; a trailing odd byte
+    const ROM: [u8; 13] = [0x00, 0xE0, 0x60, 0x2A, 0xF0, 0x00, 0x12, 0x34, 0xFF, 0xFF, 0x12, 0x00, 0xAB];
+
+    const LISTING: [&str; 6] = [
+        "0x200: 00E0  CLS",
+        "0x202: 602A  LD V0, 0x2A",
+        "0x204: F0001234  LD I, LONG 0x1234",
+        "0x208: FFFF  .word 0xFFFF",
+        "0x20A: 1200  JP 0x200",
+        "0x20C: AB    .byte 0xAB"
+    ];
+
+    #[test]
+    fn listing_prints_address_raw_bytes_and_mnemonic() {
+        let lines: Vec<String> = disassemble(&ROM, 0x200).iter().map(|word| word.to_string()).collect();
+        assert_eq!(lines, LISTING);
+    }
+
+    #[test]
+    fn addresses_past_0xfff_print_all_their_digits() {
+        assert_eq!(disassemble(&[0x00, 0xE0], 0x1000)[0].to_string(), "0x1000: 00E0  CLS");
+    }
+
+    #[test]
+    fn the_loaded_rom_is_listed_up_to_its_end() {
+        let mut chip = Chip8::default();
+        chip.load(&ROM).unwrap();
+        let lines: Vec<String> = chip.disassemble().iter().map(|word| word.to_string()).collect();
+        assert_eq!(lines, LISTING);
+    }
+}
@@ -0,0 +1,36 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use chip8_rs_dmfg::chip8::Chip8;
+use crate::format_machine_state;
+use crate::headless::render_text;
+
+/// Point in time dump of the whole machine: registers, stack, a hexdump of memory with the rows holding PC and I
+/// labelled, and the display
+pub fn format_dump(chip: &Chip8) -> String {
+    let mut lines = vec![format_machine_state(chip)];
+    let stack = chip.stack().iter().map(|address| format!("0x{:04x}", address)).collect::<Vec<String>>();
+    lines.push(format!("Stack: [{}]", stack.join(", ")));
+    lines.push(String::new());
+    let (pc, i) = (chip.pc() as usize, chip.i() as usize);
+    for (row, bytes) in chip.memory().chunks(16).enumerate() {
+        let address = row * 16;
+        let hex = bytes.iter().map(|b| format!("{:02x}", b)).collect::<Vec<String>>().join(" ");
+        let ascii = bytes.iter().map(|b| if b.is_ascii_graphic() {*b as char} else {'.'}).collect::<String>();
+        let mut labels = Vec::new();
+        if (address..address + 16).contains(&pc) {
+            labels.push(format!("<- PC 0x{:04x}", pc));
+        }
+        if (address..address + 16).contains(&i) {
+            labels.push(format!("<- I 0x{:04x}", i));
+        }
+        lines.push(format!("0x{:04x}  {:<47}  {:<16}  {}", address, hex, ascii, labels.join(" ")).trim_end().to_string());
+    }
+    lines.push(String::new());
+    lines.push(render_text(chip));
+    lines.join("\n")
+}
+
+/// Default dump file next to the ROM, named after the current time
+pub fn default_dump_path(rom_path: &str) -> String {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    format!("{}.{}.dump", rom_path, seconds)
+}
@@ -0,0 +1,356 @@
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use crate::chip8::{Chip8, FrameResult, HaltReason, TickStatus};
+use crate::chip8_error::Chip8Error;
+use crate::chip8_instruction_set::Address;
+use crate::frame_clock::FrameClock;
+use crate::frontend::Screen;
+use crate::save_state::SaveState;
+
+/// How long the worker waits for a command before checking the clock again
+const COMMAND_WAIT: Duration = Duration::from_millis(1);
+
+/// What the emulation thread does with the machine each 60 Hz frame. `InstructionsPerFrame` just runs the machine,
+/// frontends that record, replay or rewind bring their own.
+pub trait FrameRunner: Send + 'static {
+    /// Called once per frame while the thread isn't paused
+    fn run_frame(&mut self, chip: &mut Chip8) -> Result<FrameResult, Chip8Error>;
+
+    /// Called once per frame in place of `run_frame` while the thread is paused
+    fn paused_frame(&mut self, _chip: &mut Chip8) {}
+
+    /// A key pressed or released on the main thread
+    fn set_pressed(&mut self, chip: &mut Chip8, key: u8, pressed: bool) {
+        chip.set_key_state(key, pressed);
+    }
+}
+
+/// Runs this many instructions each frame with `Chip8::run_frame`
+pub struct InstructionsPerFrame(pub u32);
+
+impl FrameRunner for InstructionsPerFrame {
+    fn run_frame(&mut self, chip: &mut Chip8) -> Result<FrameResult, Chip8Error> {
+        chip.run_frame(self.0)
+    }
+}
+
+type WithFn<R> = Box<dyn FnOnce(&mut Chip8, &mut R) + Send>;
+/// The machine and runner as the thread left them, with the error that stopped it if there was one
+type Stopped<R> = (Chip8, R, Result<(), Chip8Error>);
+
+/// Sent from the main thread to the emulation thread, applied before the next frame runs
+pub enum Command<R = InstructionsPerFrame> {
+    /// Presses or releases a hex key
+    Key(u8, bool),
+    /// Stops running instructions and counting the timers down, frames keep coming with the sound off
+    Pause(bool),
+    Reset,
+    /// Replies with the machine as it is between two frames
+    SaveState(Sender<SaveState>),
+    /// Runs on the emulation thread with the machine and the runner, for anything the other commands don't cover
+    With(WithFn<R>),
+    Stop
+}
+
+/// The logical display as it was at the end of a frame, one palette index per pixel
+pub struct Frame {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>
+}
+
+impl Frame {
+    pub fn capture(chip: &Chip8) -> Self {
+        let (width, height) = chip.get_screen_size();
+        Frame {
+            width,
+            height,
+            pixels: (0..width * height).map(|i| chip.pixel_color(i) as u8).collect()
+        }
+    }
+}
+
+impl Screen for Frame {
+    fn get_screen_size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    fn pixel_color(&self, index: usize) -> usize {
+        self.pixels[index] as usize
+    }
+}
+
+/// Sent from the emulation thread after every 60 Hz frame
+pub struct FrameUpdate {
+    pub sound_active: bool,
+    /// Only captured when a row changed, bit N of `dirty_rows` is set for each one
+    pub display: Option<Frame>,
+    pub dirty_rows: u64,
+    /// What the runner did this frame, `None` while paused
+    pub result: Option<FrameResult>,
+    /// Paused by `Command::Pause`, or by the thread itself after a frame stopped on anything but the display wait
+    pub paused: bool,
+    pub pc: Address,
+    pub tick_count: u64,
+    pub halted: Option<HaltReason>
+}
+
+/// Runs a machine on its own thread at 60 frames a second, so a window that stalls while presenting doesn't hold the
+/// machine or its timers back. Stopped when dropped.
+pub struct EmulationThread<R: FrameRunner = InstructionsPerFrame> {
+    commands: Sender<Command<R>>,
+    updates: Receiver<FrameUpdate>,
+    worker: Option<JoinHandle<Stopped<R>>>
+}
+
+impl<R: FrameRunner> EmulationThread<R> {
+    /// The first update shows the display as `chip` has it now
+    pub fn spawn(chip: Chip8, runner: R) -> Self {
+        EmulationThread::start(chip, runner, false)
+    }
+
+    /// Like `spawn` but no frame runs until `Command::Pause(false)` is sent
+    pub fn spawn_paused(chip: Chip8, runner: R) -> Self {
+        EmulationThread::start(chip, runner, true)
+    }
+
+    fn start(chip: Chip8, runner: R, paused: bool) -> Self {
+        let (commands, command_receiver) = mpsc::channel();
+        let (update_sender, updates) = mpsc::channel();
+        let worker = thread::spawn(move || work(chip, runner, paused, command_receiver, update_sender));
+        EmulationThread {
+            commands,
+            updates,
+            worker: Some(worker)
+        }
+    }
+
+    /// Does nothing once the thread has stopped
+    pub fn send(&self, command: Command<R>) {
+        let _ = self.commands.send(command);
+    }
+
+    /// Runs `f` on the emulation thread after the commands sent before it and waits for its result,
+    /// `None` if the thread has stopped
+    pub fn with<T: Send + 'static>(&self, f: impl FnOnce(&mut Chip8, &mut R) -> T + Send + 'static) -> Option<T> {
+        let (reply, result) = mpsc::channel();
+        self.send(Command::With(Box::new(move |chip, runner| {
+            let _ = reply.send(f(chip, runner));
+        })));
+        result.recv().ok()
+    }
+
+    /// Every frame finished since the last call, oldest first
+    pub fn updates(&self) -> Vec<FrameUpdate> {
+        self.updates.try_iter().collect()
+    }
+
+    /// True once the machine stopped with an error, `join` returns it
+    pub fn is_finished(&self) -> bool {
+        self.worker.as_ref().is_none_or(|worker| worker.is_finished())
+    }
+
+    /// Stops the thread and hands the machine and runner back, with the error that stopped it if there was one
+    pub fn join(mut self) -> Stopped<R> {
+        self.send(Command::Stop);
+        match self.worker.take().map(|worker| worker.join()) {
+            Some(Ok(stopped)) => stopped,
+            Some(Err(panic)) => std::panic::resume_unwind(panic),
+            // only taken here and in drop
+            None => unreachable!()
+        }
+    }
+}
+
+impl<R: FrameRunner> Drop for EmulationThread<R> {
+    fn drop(&mut self) {
+        if let Some(worker) = self.worker.take() {
+            self.send(Command::Stop);
+            let _ = worker.join();
+        }
+    }
+}
+
+fn work<R: FrameRunner>(mut chip: Chip8, mut runner: R, mut paused: bool, commands: Receiver<Command<R>>, updates: Sender<FrameUpdate>) -> Stopped<R> {
+    let mut frame_clock = FrameClock::new();
+    let update = |chip: &mut Chip8, result: Option<FrameResult>, paused: bool, force_display: bool| {
+        let dirty_rows = chip.take_dirty_rows() | if force_display {u64::MAX} else {0};
+        FrameUpdate {
+            sound_active: result.as_ref().is_some_and(|result| result.sound_active),
+            display: (dirty_rows != 0).then(|| Frame::capture(chip)),
+            dirty_rows,
+            result,
+            paused,
+            pc: chip.pc(),
+            tick_count: chip.tick_count(),
+            halted: chip.halted().cloned()
+        }
+    };
+    let _ = updates.send(update(&mut chip, None, paused, true));
+    loop {
+        for _ in 0..frame_clock.frames_elapsed() {
+            let result = if paused {
+                runner.paused_frame(&mut chip);
+                None
+            }else{
+                match runner.run_frame(&mut chip) {
+                    Ok(result) => Some(result),
+                    Err(e) => return (chip, runner, Err(e))
+                }
+            };
+            // a breakpoint, watch, hook, exit or halt waits for the main thread to look at it
+            if result.as_ref().is_some_and(|result| result.stopped_by.as_ref().is_some_and(|status| *status != TickStatus::WaitingForFrame)) {
+                paused = true;
+            }
+            // nobody is listening anymore, the Stop command is on its way
+            let _ = updates.send(update(&mut chip, result, paused, false));
+        }
+        let mut command = commands.recv_timeout(COMMAND_WAIT);
+        // everything already sent applies before the next frame runs
+        loop {
+            match command {
+                Ok(Command::Key(key, pressed)) => runner.set_pressed(&mut chip, key, pressed),
+                Ok(Command::Pause(pause)) => paused = pause,
+                Ok(Command::Reset) => chip.reset(),
+                Ok(Command::SaveState(reply)) => {
+                    let _ = reply.send(chip.save_state());
+                }
+                Ok(Command::With(f)) => f(&mut chip, &mut runner),
+                Ok(Command::Stop) | Err(RecvTimeoutError::Disconnected) => return (chip, runner, Ok(())),
+                Err(RecvTimeoutError::Timeout) => break
+            }
+            command = commands.try_recv().map_err(|e| match e {
+                TryRecvError::Empty => RecvTimeoutError::Timeout,
+                TryRecvError::Disconnected => RecvTimeoutError::Disconnected
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Counts the frames it was asked to run, and the keys it was given
+    #[derive(Default)]
+    struct Counting {
+        frames: u32,
+        paused_frames: u32,
+        keys: Vec<(u8, bool)>
+    }
+
+    impl FrameRunner for Counting {
+        fn run_frame(&mut self, chip: &mut Chip8) -> Result<FrameResult, Chip8Error> {
+            self.frames += 1;
+            chip.run_frame(1)
+        }
+
+        fn paused_frame(&mut self, _chip: &mut Chip8) {
+            self.paused_frames += 1;
+        }
+
+        fn set_pressed(&mut self, chip: &mut Chip8, key: u8, pressed: bool) {
+            self.keys.push((key, pressed));
+            chip.set_key_state(key, pressed);
+        }
+    }
+
+    fn machine(program: &[u8]) -> Chip8 {
+        let mut chip = Chip8::default();
+        chip.load(program).unwrap();
+        chip
+    }
+
+    // ADD V0, 1; JP 0x200
+    const COUNTER: [u8; 4] = [0x70, 0x01, 0x12, 0x00];
+
+    /// Polls `query` on the emulation thread until it holds
+    fn wait_until<R: FrameRunner>(worker: &EmulationThread<R>, query: impl Fn(&mut Chip8, &mut R) -> bool + Send + Clone + 'static) {
+        while !worker.with(query.clone()).unwrap() {
+            thread::sleep(COMMAND_WAIT);
+        }
+    }
+
+    /// Collects updates until one matches `done`
+    fn updates_until<R: FrameRunner>(worker: &EmulationThread<R>, done: impl Fn(&FrameUpdate) -> bool) -> Vec<FrameUpdate> {
+        let mut updates = Vec::new();
+        while !updates.last().is_some_and(&done) {
+            let mut received = worker.updates();
+            if received.is_empty() {
+                thread::sleep(COMMAND_WAIT);
+            }
+            updates.append(&mut received);
+            if let Some(position) = updates.iter().position(&done) {
+                updates.truncate(position + 1);
+            }
+        }
+        updates
+    }
+
+    #[test]
+    fn commands_sent_before_a_query_are_applied_first() {
+        let worker = EmulationThread::spawn_paused(machine(&COUNTER), Counting::default());
+        worker.send(Command::Key(5, true));
+        worker.send(Command::Key(7, true));
+        worker.send(Command::Key(5, false));
+        let keys = worker.with(|chip, runner| (*chip.keys(), runner.keys.clone())).unwrap();
+        assert!(keys.0[7] && !keys.0[5]);
+        assert_eq!(keys.1, [(5, true), (7, true), (5, false)]);
+    }
+
+    #[test]
+    fn the_first_update_shows_the_whole_display() {
+        let worker = EmulationThread::spawn_paused(machine(&COUNTER), Counting::default());
+        let first = updates_until(&worker, |_| true).remove(0);
+        assert_eq!((first.dirty_rows, first.paused, first.pc), (u64::MAX, true, 0x200));
+        assert!(first.display.is_some_and(|frame| frame.get_screen_size() == (64, 32)));
+        assert!(first.result.is_none());
+    }
+
+    #[test]
+    fn paused_threads_only_run_paused_frames() {
+        let worker = EmulationThread::spawn_paused(machine(&COUNTER), Counting::default());
+        wait_until(&worker, |_, runner| runner.paused_frames >= 2);
+        assert_eq!(worker.with(|chip, runner| (runner.frames, chip.tick_count())).unwrap(), (0, 0));
+        worker.send(Command::Pause(false));
+        wait_until(&worker, |_, runner| runner.frames >= 2);
+        let running = updates_until(&worker, |update| update.result.is_some());
+        assert!(!running.last().unwrap().paused);
+    }
+
+    #[test]
+    fn a_breakpoint_pauses_the_thread() {
+        let mut chip = machine(&COUNTER);
+        chip.add_breakpoint(0x202);
+        let worker = EmulationThread::spawn(chip, InstructionsPerFrame(10));
+        let stopped = updates_until(&worker, |update| update.paused).pop().unwrap();
+        assert_eq!(stopped.result.unwrap().stopped_by, Some(TickStatus::BreakpointHit(0x202)));
+        assert_eq!((stopped.pc, stopped.tick_count), (0x202, 1));
+        // nothing runs until the main thread says so
+        assert_eq!(worker.with(|chip, _| chip.tick_count()), Some(1));
+    }
+
+    #[test]
+    fn an_error_ends_the_thread_and_comes_back_from_join() {
+        // RET
+        let worker = EmulationThread::spawn(machine(&[0x00, 0xEE]), InstructionsPerFrame(10));
+        while !worker.is_finished() {
+            thread::sleep(COMMAND_WAIT);
+        }
+        assert_eq!(worker.with(|chip, _| chip.pc()), None);
+        let (chip, _, result) = worker.join();
+        assert_eq!((result, chip.pc()), (Err(Chip8Error::StackUnderflow), 0x200));
+    }
+
+    #[test]
+    fn join_hands_back_the_machine_and_runner() {
+        let worker = EmulationThread::spawn_paused(machine(&COUNTER), Counting::default());
+        worker.send(Command::With(Box::new(|chip, runner| {
+            chip.set_register(3, 9);
+            runner.frames = 100;
+        })));
+        let (chip, runner, result) = worker.join();
+        assert_eq!((chip.registers()[3], runner.frames, result), (9, 100, Ok(())));
+    }
+}
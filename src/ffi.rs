@@ -0,0 +1,215 @@
+//! C bindings, see `include/chip8.h` (written by cbindgen when building with the `ffi` feature) and `examples/c/`.
+//! A panic never unwinds into C, every function catches it and reports `Chip8Status::Panic` instead.
+use std::panic::{self, AssertUnwindSafe};
+use std::slice;
+use crate::chip8::Chip8;
+
+/// Returned by every function that can fail
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chip8Status {
+    Ok = 0,
+    /// The machine or a buffer passed in was null
+    NullPointer = -1,
+    /// The emulator stopped with an error, e.g. a ROM too large for memory or a stack overflow
+    Error = -2,
+    /// Something went wrong inside the emulator that shouldn't have, the machine should be freed
+    Panic = -3,
+    /// The framebuffer passed in is smaller than `chip8_width() * chip8_height()`
+    BufferTooSmall = -4
+}
+
+fn guard<F: FnOnce() -> Chip8Status>(f: F) -> Chip8Status {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(Chip8Status::Panic)
+}
+
+/// Runs `f` on the machine behind `chip` unless it's null
+unsafe fn with_chip<F: FnOnce(&mut Chip8) -> Chip8Status>(chip: *mut Chip8, f: F) -> Chip8Status {
+    match chip.as_mut() {
+        Some(chip) => guard(|| f(chip)),
+        None => Chip8Status::NullPointer
+    }
+}
+
+/// A CHIP-8 machine with 4 KiB of memory, null if it couldn't be created. Free it with `chip8_free`.
+#[no_mangle]
+pub extern "C" fn chip8_new() -> *mut Chip8 {
+    panic::catch_unwind(|| Box::into_raw(Box::default())).unwrap_or(std::ptr::null_mut())
+}
+
+/// # Safety
+/// `chip` must come from `chip8_new` and not have been freed already, or be null
+#[no_mangle]
+pub unsafe extern "C" fn chip8_free(chip: *mut Chip8) {
+    if !chip.is_null() {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(chip))));
+    }
+}
+
+/// Loads `len` bytes at `rom` to 0x200 and resets the machine
+///
+/// # Safety
+/// `chip` must come from `chip8_new`, `rom` must point at `len` readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn chip8_load_rom(chip: *mut Chip8, rom: *const u8, len: usize) -> Chip8Status {
+    if rom.is_null() {
+        return Chip8Status::NullPointer;
+    }
+    let rom = slice::from_raw_parts(rom, len);
+    with_chip(chip, |chip| match chip.load(rom) {
+        Ok(_) => Chip8Status::Ok,
+        Err(_) => Chip8Status::Error
+    })
+}
+
+/// Runs one instruction, the timers are left alone
+///
+/// # Safety
+/// `chip` must come from `chip8_new`
+#[no_mangle]
+pub unsafe extern "C" fn chip8_tick(chip: *mut Chip8) -> Chip8Status {
+    with_chip(chip, |chip| match chip.step() {
+        Ok(_) => Chip8Status::Ok,
+        Err(_) => Chip8Status::Error
+    })
+}
+
+/// Runs up to `cycles` instructions and counts the timers down once, call it 60 times a second
+///
+/// # Safety
+/// `chip` must come from `chip8_new`
+#[no_mangle]
+pub unsafe extern "C" fn chip8_run_frame(chip: *mut Chip8, cycles: u32) -> Chip8Status {
+    with_chip(chip, |chip| match chip.run_frame(cycles) {
+        Ok(_) => Chip8Status::Ok,
+        Err(_) => Chip8Status::Error
+    })
+}
+
+/// `key` is the hex key, 0 to F, anything past F is an error
+///
+/// # Safety
+/// `chip` must come from `chip8_new`
+#[no_mangle]
+pub unsafe extern "C" fn chip8_key(chip: *mut Chip8, key: u8, pressed: bool) -> Chip8Status {
+    with_chip(chip, |chip| {
+        if key > 0xF {
+            return Chip8Status::Error;
+        }
+        chip.set_key_state(key, pressed);
+        Chip8Status::Ok
+    })
+}
+
+/// Width of the display in pixels, 128 while a SUPER-CHIP program is in high resolution. 0 if `chip` is null.
+///
+/// # Safety
+/// `chip` must come from `chip8_new`
+#[no_mangle]
+pub unsafe extern "C" fn chip8_width(chip: *const Chip8) -> usize {
+    chip.as_ref().map_or(0, |chip| chip.get_screen_size().0)
+}
+
+/// Height of the display in pixels, 0 if `chip` is null
+///
+/// # Safety
+/// `chip` must come from `chip8_new`
+#[no_mangle]
+pub unsafe extern "C" fn chip8_height(chip: *const Chip8) -> usize {
+    chip.as_ref().map_or(0, |chip| chip.get_screen_size().1)
+}
+
+/// Writes one byte per pixel to `out`, row by row. 0 is off, 1 the first plane, 2 the second XO-CHIP plane and 3 both.
+/// A buffer of 128 * 64 bytes fits every resolution.
+///
+/// # Safety
+/// `chip` must come from `chip8_new`, `out` must point at `out_len` writable bytes
+#[no_mangle]
+pub unsafe extern "C" fn chip8_framebuffer(chip: *mut Chip8, out: *mut u8, out_len: usize) -> Chip8Status {
+    if out.is_null() {
+        return Chip8Status::NullPointer;
+    }
+    let out = slice::from_raw_parts_mut(out, out_len);
+    with_chip(chip, |chip| {
+        let (width, height) = chip.get_screen_size();
+        if out.len() < width * height {
+            return Chip8Status::BufferTooSmall;
+        }
+        out.iter_mut()
+            .take(width * height)
+            .enumerate()
+            .for_each(|(i, pixel)| *pixel = chip.pixel_color(i) as u8);
+        Chip8Status::Ok
+    })
+}
+
+/// Whether the sound timer is running, false if `chip` is null
+///
+/// # Safety
+/// `chip` must come from `chip8_new`
+#[no_mangle]
+pub unsafe extern "C" fn chip8_sound_active(chip: *const Chip8) -> bool {
+    chip.as_ref().is_some_and(|chip| chip.is_sound_active())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn framebuffers_must_be_present_and_large_enough() {
+        let chip = chip8_new();
+        let mut pixels = vec![0xAA; 64 * 32];
+        unsafe {
+            assert_eq!(chip8_framebuffer(chip, std::ptr::null_mut(), 64 * 32), Chip8Status::NullPointer);
+            assert_eq!(chip8_framebuffer(std::ptr::null_mut(), pixels.as_mut_ptr(), pixels.len()), Chip8Status::NullPointer);
+            assert_eq!(chip8_framebuffer(chip, pixels.as_mut_ptr(), pixels.len() - 1), Chip8Status::BufferTooSmall);
+            // nothing is written to a buffer that's too small
+            assert!(pixels.iter().all(|pixel| *pixel == 0xAA));
+            assert_eq!(chip8_framebuffer(chip, pixels.as_mut_ptr(), pixels.len()), Chip8Status::Ok);
+            assert!(pixels.iter().all(|pixel| *pixel == 0));
+            chip8_free(chip);
+        }
+    }
+
+    #[test]
+    fn roms_too_large_for_memory_are_errors() {
+        let chip = chip8_new();
+        let too_large = vec![0; 4096 - 0x200 + 1];
+        unsafe {
+            assert_eq!(chip8_load_rom(chip, too_large.as_ptr(), too_large.len()), Chip8Status::Error);
+            assert_eq!(chip8_load_rom(chip, too_large.as_ptr(), too_large.len() - 1), Chip8Status::Ok);
+            assert_eq!(chip8_load_rom(chip, std::ptr::null(), 0), Chip8Status::NullPointer);
+            assert_eq!(chip8_load_rom(std::ptr::null_mut(), too_large.as_ptr(), 2), Chip8Status::NullPointer);
+            chip8_free(chip);
+        }
+    }
+
+    #[test]
+    fn frames_draw_into_the_framebuffer() {
+        let chip = chip8_new();
+        // LD F, V0; DRW V0, V0, 5
+        let rom = [0xF0, 0x29, 0xD0, 0x05];
+        let mut pixels = [0; 128 * 64];
+        unsafe {
+            assert_eq!(chip8_load_rom(chip, rom.as_ptr(), rom.len()), Chip8Status::Ok);
+            assert_eq!(chip8_run_frame(chip, 2), Chip8Status::Ok);
+            assert_eq!((chip8_width(chip), chip8_height(chip)), (64, 32));
+            assert_eq!(chip8_framebuffer(chip, pixels.as_mut_ptr(), pixels.len()), Chip8Status::Ok);
+            // the top of a 0 is 0xF0
+            assert_eq!(pixels[..8], [1, 1, 1, 1, 0, 0, 0, 0]);
+            chip8_free(chip);
+        }
+    }
+
+    #[test]
+    fn keys_past_f_are_errors() {
+        let chip = chip8_new();
+        unsafe {
+            assert_eq!(chip8_key(chip, 0xF, true), Chip8Status::Ok);
+            assert_eq!(chip8_key(chip, 0x10, true), Chip8Status::Error);
+            assert_eq!(chip8_key(std::ptr::null_mut(), 0x1, true), Chip8Status::NullPointer);
+            chip8_free(chip);
+        }
+    }
+}
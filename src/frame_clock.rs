@@ -0,0 +1,73 @@
+use std::time::{Duration, Instant};
+
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+/// Frames made up for after a stall, half a second. Anything longer is dropped rather than fast forwarded through.
+const MAX_CATCH_UP: u32 = 30;
+
+/// Counts the 60 Hz frames passing in real time, so timers keep their rate when the window loop stalls
+pub struct FrameClock {
+    last: Instant,
+    // time since the last whole frame
+    pending: Duration
+}
+
+impl Default for FrameClock {
+    fn default() -> Self {
+        FrameClock::new()
+    }
+}
+
+impl FrameClock {
+    pub fn new() -> Self {
+        FrameClock {
+            last: Instant::now(),
+            pending: Duration::ZERO
+        }
+    }
+
+    /// Whole frames elapsed since the last call, several after a stall
+    pub fn frames_elapsed(&mut self) -> u32 {
+        let now = Instant::now();
+        let elapsed = now.saturating_duration_since(self.last);
+        self.last = now;
+        self.advance(elapsed)
+    }
+
+    /// Whole frames in `elapsed` plus whatever was left over from earlier calls, at most `MAX_CATCH_UP`
+    pub fn advance(&mut self, elapsed: Duration) -> u32 {
+        self.pending += elapsed;
+        let frames = (self.pending.as_nanos() / FRAME.as_nanos()) as u32;
+        self.pending -= FRAME * frames;
+        frames.min(MAX_CATCH_UP)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_stall_is_caught_up_in_one_call() {
+        let mut clock = FrameClock::new();
+        assert_eq!(clock.advance(Duration::from_millis(200)), 12);
+    }
+
+    #[test]
+    fn partial_frames_carry_over() {
+        let mut clock = FrameClock::new();
+        assert_eq!(clock.advance(Duration::from_millis(10)), 0);
+        assert_eq!(clock.advance(Duration::from_millis(10)), 1);
+        assert_eq!(clock.advance(Duration::from_millis(14)), 1);
+        let frames: u32 = (0..600).map(|_| clock.advance(Duration::from_millis(1))).sum();
+        assert_eq!(frames, 36);
+    }
+
+    #[test]
+    fn long_stalls_are_clamped_and_dropped() {
+        let mut clock = FrameClock::new();
+        assert_eq!(clock.advance(Duration::from_secs(2)), MAX_CATCH_UP);
+        // the rest of the stall isn't made up later
+        assert_eq!(clock.advance(Duration::ZERO), 0);
+        assert_eq!(clock.advance(FRAME), 1);
+    }
+}
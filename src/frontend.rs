@@ -0,0 +1,128 @@
+use std::{mem, thread, time};
+use crate::chip8::Chip8;
+use crate::chip8_error::Chip8Error;
+use crate::emulation_thread::{Command, EmulationThread, InstructionsPerFrame};
+use crate::frame_clock::FrameClock;
+
+/// Input from the player, already mapped onto the 16 hex keys or the emulator's own controls
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyEvent {
+    Pressed(u8),
+    Released(u8),
+    /// One of the emulator's own keys, only sent by frontends that have them
+    Control(Control),
+    /// The window went into the background or came back. Releases are missed while it's away, so the keys still held
+    /// come as `Pressed` again after `Focus(true)`
+    Focus(bool),
+    /// The window was closed or the player asked to leave
+    Quit
+}
+
+/// The emulator's own keys in the default window, `run` ignores them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    Reset,
+    /// Reads the ROM from disk again
+    Reload,
+    /// Picks another ROM
+    Open,
+    SaveState,
+    LoadState,
+    Pause,
+    /// Runs one instruction while paused
+    Step,
+    Dump,
+    Screenshot,
+    /// Starts or stops a GIF recording
+    ToggleGif,
+    /// Rewinding lasts for as long as the key is held, `false` when it's let go
+    Rewind(bool)
+}
+
+/// A display to draw, the machine itself or a `Frame` it sent from another thread
+pub trait Screen {
+    /// Width and height in pixels
+    fn get_screen_size(&self) -> (usize, usize);
+    /// 0 for off, 1 for the first plane, 2 for the second XO-CHIP plane and 3 for both
+    fn pixel_color(&self, index: usize) -> usize;
+}
+
+impl Screen for Chip8 {
+    fn get_screen_size(&self) -> (usize, usize) {
+        Chip8::get_screen_size(self)
+    }
+
+    fn pixel_color(&self, index: usize) -> usize {
+        Chip8::pixel_color(self, index)
+    }
+}
+
+/// Shows the display, implemented by each windowing library
+pub trait Renderer {
+    /// `dirty_rows` has bit N set when row N changed since the last call, as returned by `Chip8::take_dirty_rows`
+    fn present(&mut self, screen: &dyn Screen, dirty_rows: u64);
+
+    /// Shows the ROM and whether it's paused, frontends without a title bar ignore it
+    fn set_title(&mut self, _title: &str) {}
+}
+
+pub trait InputSource {
+    /// Everything that happened since the last call, called once per frame
+    fn poll(&mut self) -> Vec<KeyEvent>;
+}
+
+/// Windowing libraries deliver input through the window they draw to, so the run loop takes both from one value
+pub trait Frontend: Renderer + InputSource {}
+
+impl<T: Renderer + InputSource> Frontend for T {}
+
+pub trait AudioSink {
+    /// Called once per frame with whether the buzzer should sound
+    fn set_beep(&mut self, on: bool);
+}
+
+/// For frontends without sound
+pub struct NoAudio;
+
+impl AudioSink for NoAudio {
+    fn set_beep(&mut self, _on: bool) {}
+}
+
+/// Plays `chip` at 60 frames a second with `instructions_per_frame` instructions each frame until the input source
+/// quits. Once the program exits or the machine halts its last screen stays up. Frontends only provide the window, keys and sound.
+///
+/// The machine runs on an `EmulationThread` meanwhile, so a slow `present` only delays drawing. Input is still read once a frame.
+pub fn run(chip: &mut Chip8, instructions_per_frame: u32, frontend: &mut dyn Frontend, audio: &mut dyn AudioSink) -> Result<(), Chip8Error> {
+    let worker = EmulationThread::spawn(mem::take(chip), InstructionsPerFrame(instructions_per_frame));
+    let mut frame_clock = FrameClock::new();
+    // an error ends the emulation thread, join hands it over
+    'running: while !worker.is_finished() {
+        if frame_clock.frames_elapsed() == 0 {
+            thread::sleep(time::Duration::from_millis(1));
+            continue;
+        }
+        for event in frontend.poll() {
+            match event {
+                KeyEvent::Pressed(key) => worker.send(Command::Key(key, true)),
+                KeyEvent::Released(key) => worker.send(Command::Key(key, false)),
+                KeyEvent::Control(_) | KeyEvent::Focus(_) => {}
+                KeyEvent::Quit => break 'running
+            }
+        }
+        // every frame's sound gets played but only the newest display is drawn, with the rows changed in all of them
+        let mut dirty_rows = 0;
+        let mut latest = None;
+        for update in worker.updates() {
+            audio.set_beep(update.sound_active);
+            dirty_rows |= update.dirty_rows;
+            latest = update.display.or(latest);
+        }
+        if let Some(frame) = latest {
+            frontend.present(&frame, dirty_rows);
+        }
+    }
+    audio.set_beep(false);
+    let (machine, _, result) = worker.join();
+    *chip = machine;
+    result
+}
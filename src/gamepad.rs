@@ -0,0 +1,83 @@
+use std::collections::{HashMap, HashSet};
+use gilrs::{Axis, Button, EventType, GamepadId, Gilrs};
+use chip8_rs_dmfg::frontend::{InputSource, KeyEvent};
+use crate::keymap::Keymap;
+
+/// How far the left stick has to be pushed to count as holding the d-pad in that direction
+const STICK_THRESHOLD: f32 = 0.5;
+
+/// Connected controllers, numbered in the order they were plugged in so the second one can drive other keys
+pub struct Gamepads {
+    gilrs: Gilrs,
+    keymap: Keymap,
+    connected: Vec<GamepadId>,
+    held: HashMap<GamepadId, HashSet<Button>>
+}
+
+impl Gamepads {
+    /// `None` when the platform's controller backend is unavailable, the keyboard keeps working without it
+    pub fn new(keymap: Keymap) -> Option<Self> {
+        let gilrs = Gilrs::new().ok()?;
+        let connected = gilrs.gamepads().map(|(id, _)| id).collect();
+        Some(Gamepads {
+            gilrs,
+            keymap,
+            connected,
+            held: HashMap::new()
+        })
+    }
+
+    /// Handles every controller event since the last poll, returning the hex keys that went down or up
+    fn changes(&mut self) -> Vec<(u8, bool)> {
+        let mut changes = Vec::new();
+        while let Some(event) = self.gilrs.next_event() {
+            let id = event.id;
+            match event.event {
+                EventType::Connected if !self.connected.contains(&id) => self.connected.push(id),
+                EventType::Disconnected => {
+                    // let go of whatever the controller was holding so no key stays stuck down
+                    let held = self.held.remove(&id).unwrap_or_default();
+                    for button in held {
+                        changes.extend(self.hex_key(id, button).map(|key| (key, false)));
+                    }
+                    self.connected.retain(|connected| *connected != id);
+                }
+                EventType::ButtonPressed(button, _) => self.set_button(id, button, true, &mut changes),
+                EventType::ButtonReleased(button, _) => self.set_button(id, button, false, &mut changes),
+                EventType::AxisChanged(axis, value, _) => {
+                    let (negative, positive) = match axis {
+                        Axis::LeftStickX => (Button::DPadLeft, Button::DPadRight),
+                        Axis::LeftStickY => (Button::DPadDown, Button::DPadUp),
+                        _ => continue
+                    };
+                    self.set_button(id, negative, value < -STICK_THRESHOLD, &mut changes);
+                    self.set_button(id, positive, value > STICK_THRESHOLD, &mut changes);
+                }
+                _ => {}
+            }
+        }
+        changes
+    }
+
+    fn set_button(&mut self, id: GamepadId, button: Button, pressed: bool, changes: &mut Vec<(u8, bool)>) {
+        let held = self.held.entry(id).or_default();
+        // sticks report every small movement, only changes in the held state become key events
+        let changed = if pressed {held.insert(button)} else {held.remove(&button)};
+        if changed {
+            changes.extend(self.hex_key(id, button).map(|key| (key, pressed)));
+        }
+    }
+
+    fn hex_key(&self, id: GamepadId, button: Button) -> Option<u8> {
+        let controller = self.connected.iter().position(|connected| *connected == id)?;
+        self.keymap.controller_hex_key(controller, &button)
+    }
+}
+
+impl InputSource for Gamepads {
+    fn poll(&mut self) -> Vec<KeyEvent> {
+        self.changes().into_iter()
+            .map(|(key, pressed)| if pressed {KeyEvent::Pressed(key)} else {KeyEvent::Released(key)})
+            .collect()
+    }
+}
@@ -0,0 +1,112 @@
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::Path;
+use raqote::Color;
+use chip8_rs_dmfg::chip8::{Chip8, TickStatus, DISPLAY_WIDTH};
+use chip8_rs_dmfg::chip8_error::Chip8Error;
+use chip8_rs_dmfg::recording::Replay;
+use crate::capture;
+
+/// Runs up to `max_ticks` instructions without a window, stopping early if the program exits or the machine halts.
+/// Headless runs have no real clock, so timers count down once every `ipf` ticks. With a replay the recorded input
+/// and frame timing is used instead. `on_frame` is called every `ipf` ticks either way.
+pub fn run(chip: &mut Chip8, max_ticks: u64, ipf: u64, mut replay: Option<&mut Replay>, on_frame: &mut dyn FnMut(&Chip8)) -> Result<(), Chip8Error> {
+    let ipf = ipf.max(1);
+    for tick in 0..max_ticks {
+        if let Some(replay) = replay.as_mut() {
+            replay.apply(chip);
+        }
+        match chip.tick()? {
+            TickStatus::Exited | TickStatus::Halted => break,
+            _ => {}
+        }
+        if (tick + 1) % ipf == 0 {
+            if replay.is_none() {
+                chip.decrement_time();
+            }
+            on_frame(chip);
+        }
+    }
+    Ok(())
+}
+
+/// One line per display row, `#` for a lit pixel and `.` for an unlit one.
+/// XO-CHIP pixels lit only on the second plane are `+` and those lit on both `@`.
+pub fn render_text(chip: &Chip8) -> String {
+    let (width, _) = chip.get_screen_size();
+    (0..chip.get_display().len())
+        .map(|i| ['.', '#', '+', '@'][chip.pixel_color(i)])
+        .collect::<Vec<char>>()
+        .chunks(width)
+        .map(|row| row.iter().collect::<String>())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Writes the display as a binary PPM image with one image pixel per CHIP-8 pixel, `palette` is indexed by `Chip8::pixel_color`
+pub fn write_ppm(chip: &Chip8, path: &str, palette: &[Color; 4]) -> io::Result<()> {
+    let (width, height) = chip.get_screen_size();
+    let mut image = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+    for i in 0..chip.get_display().len() {
+        let color = palette[chip.pixel_color(i)];
+        image.extend_from_slice(&[color.r(), color.g(), color.b()]);
+    }
+    fs::write(path, image)
+}
+
+/// Writes the display with one image pixel per CHIP-8 pixel, a PNG if `path` ends in `.png` and a PPM otherwise
+pub fn write_screenshot(chip: &Chip8, path: &str, palette: &[Color; 4]) -> Result<(), Box<dyn Error>> {
+    if Path::new(path).extension().is_some_and(|extension| extension.eq_ignore_ascii_case("png")) {
+        // high resolution is twice the width of low resolution
+        capture::write_png(chip, path, palette, chip.get_screen_size().0 / DISPLAY_WIDTH)
+    }else{
+        Ok(write_ppm(chip, path, palette)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_frame_runs_ipf_instructions() {
+        // ADD V0, 1; JP 0x200
+        let program = [0x70, 0x01, 0x12, 0x00];
+        for ipf in [1, 10, 15] {
+            let mut chip = Chip8::default();
+            chip.load(&program).unwrap();
+            let mut frames = 0;
+            run(&mut chip, 7 * ipf, ipf, None, &mut |_| frames += 1).unwrap();
+            assert_eq!(frames, 7, "ipf {}", ipf);
+            assert_eq!(chip.tick_count(), 7 * ipf, "ipf {}", ipf);
+        }
+    }
+
+    #[test]
+    fn timers_count_down_once_per_frame() {
+        // LD V0, 20; LD DT, V0; ADD V1, 1; JP 0x204
+        let program = [0x60, 0x14, 0xF0, 0x15, 0x71, 0x01, 0x12, 0x04];
+        let mut chip = Chip8::default();
+        chip.load(&program).unwrap();
+        let mut delays = Vec::new();
+        run(&mut chip, 4 * 12, 12, None, &mut |chip| delays.push(chip.delay_timer())).unwrap();
+        assert_eq!(delays, [19, 18, 17, 16]);
+    }
+
+    #[test]
+    fn screenshots_are_encoded_by_extension() {
+        let chip = Chip8::default();
+        let palette = [Color::new(255, 0, 0, 0), Color::new(255, 255, 255, 255), Color::new(255, 255, 0, 0), Color::new(255, 0, 0, 255)];
+        let dir = std::env::temp_dir();
+        let png = dir.join(format!("chip8-screenshot-{}.PNG", std::process::id()));
+        let ppm = dir.join(format!("chip8-screenshot-{}.ppm", std::process::id()));
+        write_screenshot(&chip, png.to_str().unwrap(), &palette).unwrap();
+        write_screenshot(&chip, ppm.to_str().unwrap(), &palette).unwrap();
+        let (png_bytes, ppm_bytes) = (fs::read(&png).unwrap(), fs::read(&ppm).unwrap());
+        fs::remove_file(png).unwrap();
+        fs::remove_file(ppm).unwrap();
+        assert_eq!(png_bytes[..8], [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n']);
+        assert!(ppm_bytes.starts_with(b"P6\n64 32\n255\n"));
+    }
+}
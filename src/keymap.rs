@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use gilrs::Button;
+use minifb::Key;
+
+const KEY_NAMES: [(&str, Key); 60] = [
+    ("0", Key::Key0), ("1", Key::Key1), ("2", Key::Key2), ("3", Key::Key3), ("4", Key::Key4),
+    ("5", Key::Key5), ("6", Key::Key6), ("7", Key::Key7), ("8", Key::Key8), ("9", Key::Key9),
+    ("a", Key::A), ("b", Key::B), ("c", Key::C), ("d", Key::D), ("e", Key::E), ("f", Key::F),
+    ("g", Key::G), ("h", Key::H), ("i", Key::I), ("j", Key::J), ("k", Key::K), ("l", Key::L),
+    ("m", Key::M), ("n", Key::N), ("o", Key::O), ("p", Key::P), ("q", Key::Q), ("r", Key::R),
+    ("s", Key::S), ("t", Key::T), ("u", Key::U), ("v", Key::V), ("w", Key::W), ("x", Key::X),
+    ("y", Key::Y), ("z", Key::Z),
+    ("numpad0", Key::NumPad0), ("numpad1", Key::NumPad1), ("numpad2", Key::NumPad2), ("numpad3", Key::NumPad3),
+    ("numpad4", Key::NumPad4), ("numpad5", Key::NumPad5), ("numpad6", Key::NumPad6), ("numpad7", Key::NumPad7),
+    ("numpad8", Key::NumPad8), ("numpad9", Key::NumPad9),
+    ("up", Key::Up), ("down", Key::Down), ("left", Key::Left), ("right", Key::Right),
+    ("space", Key::Space), ("enter", Key::Enter), ("tab", Key::Tab), ("comma", Key::Comma),
+    ("period", Key::Period), ("slash", Key::Slash), ("semicolon", Key::Semicolon), ("minus", Key::Minus),
+    ("equal", Key::Equal), ("apostrophe", Key::Apostrophe)
+];
+
+/// Controller buttons, written after a `controller1.` or `controller2.` prefix
+const BUTTON_NAMES: [(&str, Button); 21] = [
+    ("south", Button::South), ("east", Button::East), ("north", Button::North), ("west", Button::West),
+    ("a", Button::South), ("b", Button::East), ("x", Button::West), ("y", Button::North),
+    ("dpadup", Button::DPadUp), ("dpaddown", Button::DPadDown), ("dpadleft", Button::DPadLeft), ("dpadright", Button::DPadRight),
+    ("lefttrigger", Button::LeftTrigger), ("lefttrigger2", Button::LeftTrigger2),
+    ("righttrigger", Button::RightTrigger), ("righttrigger2", Button::RightTrigger2),
+    ("select", Button::Select), ("start", Button::Start), ("mode", Button::Mode),
+    ("leftthumb", Button::LeftThumb), ("rightthumb", Button::RightThumb)
+];
+
+/// Number of controllers that can be bound, more can be connected but are ignored
+pub const MAX_CONTROLLERS: usize = 2;
+
+/// Physical keyboard keys and controller buttons bound to the 16 CHIP-8 hex keys
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    keys: HashMap<Key, u8>,
+    controllers: [HashMap<Button, u8>; MAX_CONTROLLERS]
+}
+
+impl Keymap {
+    pub fn hex_key(&self, key: &Key) -> Option<u8> {
+        self.keys.get(key).copied()
+    }
+
+    /// `controller` counts from 0 in the order controllers were connected
+    pub fn controller_hex_key(&self, controller: usize, button: &Button) -> Option<u8> {
+        self.controllers.get(controller).and_then(|buttons| buttons.get(button)).copied()
+    }
+
+    /// D-pad on 2/4/6/8 for movement in most games, the face buttons on the keys around it
+    fn default_controller() -> HashMap<Button, u8> {
+        HashMap::from([
+            (Button::DPadUp, 0x2),
+            (Button::DPadLeft, 0x4),
+            (Button::DPadRight, 0x6),
+            (Button::DPadDown, 0x8),
+            (Button::South, 0x5),
+            (Button::East, 0x6),
+            (Button::West, 0x4),
+            (Button::North, 0xC),
+            (Button::Select, 0x0),
+            (Button::Start, 0xF)
+        ])
+    }
+}
+
+impl Default for Keymap {
+    /// 1234/QWER/ASDF/ZXCV laid out like the COSMAC VIP's 123C/456D/789E/A0BF keypad
+    fn default() -> Self {
+        Keymap {
+            keys: HashMap::from([
+                (Key::Key1, 0x1),
+                (Key::Key2, 0x2),
+                (Key::Key3, 0x3),
+                (Key::Key4, 0xC),
+                (Key::Q, 0x4),
+                (Key::W, 0x5),
+                (Key::E, 0x6),
+                (Key::R, 0xD),
+                (Key::A, 0x7),
+                (Key::S, 0x8),
+                (Key::D, 0x9),
+                (Key::F, 0xE),
+                (Key::Z, 0xA),
+                (Key::X, 0x0),
+                (Key::C, 0xB),
+                (Key::V, 0xF)
+            ]),
+            controllers: [Keymap::default_controller(), Keymap::default_controller()]
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum KeymapErr {
+    MalformedEntry(String),
+    UnknownKeyName(String),
+    UnknownButtonName(String),
+    InvalidHexKey(String),
+    KeyBoundTwice(String),
+    HexKeyBoundTwice(u8),
+    MissingHexKeys(Vec<u8>)
+}
+
+impl Display for KeymapErr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeymapErr::MalformedEntry(s) => f.write_str(&format!("Keymap entry '{}' should look like 'key=hex'", s)),
+            KeymapErr::UnknownKeyName(s) => {
+                let names = KEY_NAMES.iter().map(|(name, _)| *name).collect::<Vec<&str>>();
+                f.write_str(&format!("Unknown key '{}', accepted keys are: {}", s, names.join(", ")))
+            }
+            KeymapErr::UnknownButtonName(s) => {
+                let names = BUTTON_NAMES.iter().map(|(name, _)| *name).collect::<Vec<&str>>();
+                f.write_str(&format!("Unknown controller button '{}', accepted buttons are: {}", s, names.join(", ")))
+            }
+            KeymapErr::InvalidHexKey(s) => f.write_str(&format!("'{}' is not a CHIP-8 key, expected a hex digit 0-f", s)),
+            KeymapErr::KeyBoundTwice(s) => f.write_str(&format!("Key '{}' is bound more than once", s)),
+            KeymapErr::HexKeyBoundTwice(v) => f.write_str(&format!("CHIP-8 key {:X} is bound to more than one key", v)),
+            KeymapErr::MissingHexKeys(v) => {
+                let missing = v.iter().map(|k| format!("{:X}", k)).collect::<Vec<String>>();
+                f.write_str(&format!("CHIP-8 keys {} are not bound", missing.join(", ")))
+            }
+        }
+    }
+}
+
+impl Error for KeymapErr {
+
+}
+
+/// Parses `key=hex` bindings separated by commas or newlines, `#` starts a comment.
+/// Controller buttons are bound with `controller1.button=hex` or `controller2.button=hex`, and replace the default
+/// bindings of that controller. A map with only controller bindings keeps the default keyboard layout.
+impl FromStr for Keymap {
+    type Err = KeymapErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut keys = HashMap::new();
+        let mut controllers: [Option<HashMap<Button, u8>>; MAX_CONTROLLERS] = [None, None];
+        let mut bound = [false; 16];
+        let entries = s.lines()
+            .map(|line| line.split('#').next().unwrap_or(""))
+            .flat_map(|line| line.split(','))
+            .map(|entry| entry.trim())
+            .filter(|entry| !entry.is_empty());
+        for entry in entries {
+            let (name, hex) = entry.split_once('=').ok_or_else(|| KeymapErr::MalformedEntry(entry.to_string()))?;
+            let (name, hex) = (name.trim().to_lowercase(), hex.trim());
+            let value = u8::from_str_radix(hex, 16).ok()
+                .filter(|v| *v <= 0xF)
+                .ok_or_else(|| KeymapErr::InvalidHexKey(hex.to_string()))?;
+            let controller = name.strip_prefix("controller")
+                .and_then(|rest| rest.split_once('.'))
+                .and_then(|(number, button)| Some((number.parse::<usize>().ok()?, button)))
+                .filter(|(number, _)| (1..=MAX_CONTROLLERS).contains(number));
+            if let Some((number, button_name)) = controller {
+                let button = BUTTON_NAMES.iter()
+                    .find(|(known, _)| *known == button_name)
+                    .map(|(_, button)| *button)
+                    .ok_or_else(|| KeymapErr::UnknownButtonName(button_name.to_string()))?;
+                // a controller may drive one hex key from several buttons, so only repeated buttons are rejected
+                if controllers[number - 1].get_or_insert_with(HashMap::new).insert(button, value).is_some() {
+                    return Err(KeymapErr::KeyBoundTwice(name));
+                }
+                continue;
+            }
+            let key = KEY_NAMES.iter()
+                .find(|(key_name, _)| *key_name == name)
+                .map(|(_, key)| *key)
+                .ok_or_else(|| KeymapErr::UnknownKeyName(name.clone()))?;
+            if keys.insert(key, value).is_some() {
+                return Err(KeymapErr::KeyBoundTwice(name));
+            }
+            if bound[value as usize] {
+                return Err(KeymapErr::HexKeyBoundTwice(value));
+            }
+            bound[value as usize] = true;
+        }
+        let defaults = Keymap::default();
+        if keys.is_empty() {
+            keys = defaults.keys;
+        }else{
+            let missing = (0..16u8).filter(|v| !bound[*v as usize]).collect::<Vec<u8>>();
+            if !missing.is_empty() {
+                return Err(KeymapErr::MissingHexKeys(missing));
+            }
+        }
+        let [first, second] = controllers;
+        let [default_first, default_second] = defaults.controllers;
+        Ok(Keymap {
+            keys,
+            controllers: [first.unwrap_or(default_first), second.unwrap_or(default_second)]
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_default_layout_binds_every_hex_key_once() {
+        let keymap = Keymap::default();
+        let mut values = keymap.keys.values().copied().collect::<Vec<u8>>();
+        values.sort();
+        assert_eq!(values, (0..16).collect::<Vec<u8>>());
+        assert_eq!((keymap.hex_key(&Key::C), keymap.hex_key(&Key::V)), (Some(0xB), Some(0xF)));
+    }
+
+    #[test]
+    fn parsed_layouts_must_bind_every_hex_key_once() {
+        let full = "1=1,2=2,3=3,4=c,q=4,w=5,e=6,r=d,a=7,s=8,d=9,f=e,z=a,x=0,c=b,v=f";
+        assert_eq!(full.parse::<Keymap>().unwrap().hex_key(&Key::V), Some(0xF));
+        assert!(matches!(full.replace("v=f", "v=b").parse::<Keymap>(), Err(KeymapErr::HexKeyBoundTwice(0xB))));
+        assert!(matches!(full.replace(",v=f", ",c=f").parse::<Keymap>(), Err(KeymapErr::KeyBoundTwice(_))));
+        assert!(matches!(full.replace(",v=f", "").parse::<Keymap>(), Err(KeymapErr::MissingHexKeys(missing)) if missing == [0xF]));
+    }
+
+    #[test]
+    fn controller_bindings_keep_the_default_keyboard() {
+        let keymap = "controller2.dpadup=5 # up\ncontroller2.a=5".parse::<Keymap>().unwrap();
+        assert_eq!(keymap.hex_key(&Key::Q), Some(0x4));
+        assert_eq!(keymap.controller_hex_key(1, &Button::DPadUp), Some(0x5));
+        assert_eq!(keymap.controller_hex_key(1, &Button::South), Some(0x5));
+        assert_eq!(keymap.controller_hex_key(1, &Button::Start), None);
+        assert_eq!(keymap.controller_hex_key(0, &Button::Start), Some(0xF));
+    }
+}
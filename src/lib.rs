@@ -0,0 +1,33 @@
+//! Without the `std` feature the library is `no_std` and only needs `alloc`. The assemblers, the frame clock,
+//! the frontend loop and the emulation thread need the standard library and are left out.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod chip8;
+pub mod chip8_error;
+pub mod chip8_instruction_set;
+pub mod disassembler;
+pub mod analysis;
+#[cfg(feature = "std")]
+pub mod assembler;
+#[cfg(feature = "std")]
+pub mod octo;
+pub mod save_state;
+pub mod rng;
+pub mod quirks;
+pub mod recording;
+pub mod rewind;
+pub mod rom_database;
+#[cfg(feature = "std")]
+pub mod frame_clock;
+#[cfg(feature = "std")]
+pub mod frontend;
+#[cfg(feature = "std")]
+pub mod emulation_thread;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "python")]
+pub mod python;
@@ -1,77 +1,803 @@
-use std::collections::HashMap;
-use std::{fs, time};
-use std::fmt::{Display, Formatter};
-use std::ops::Shl;
-use indicatif::{ProgressBar, ProgressStyle};
-use log::LevelFilter;
-use raqote::Color;
-use minifb::{Key, KeyRepeat, Window, WindowOptions};
-use clap::Parser;
-use crate::chip8::Chip8;
-use crate::chip8_instruction_set::Instruction;
-use crate::cli::CliColor;
-
-mod chip8;
-mod chip8_instruction_set;
+use std::{fs, io};
+use std::fs::File;
+use std::path::Path;
+use std::io::{BufWriter, Write};
+use std::ops::Range;
+use std::ops::{BitAnd, Shr};
+use indicatif::ProgressBar;
+use raqote::{Color, DrawOptions, DrawTarget, SolidSource, Source};
+use clap::{Parser, Subcommand};
+use chip8_rs_dmfg::chip8::{Chip8, HaltReason, MemoryPolicy, OpcodePolicy, Variant, WriteProtection, DEFAULT_ORIGIN};
+use chip8_rs_dmfg::chip8_error::Chip8Error;
+use chip8_rs_dmfg::assembler::assemble;
+use chip8_rs_dmfg::octo::assemble_octo;
+use chip8_rs_dmfg::disassembler::{disassemble, disassemble_smart};
+use chip8_rs_dmfg::chip8_instruction_set::Address;
+use chip8_rs_dmfg::quirks::{IndexIncrement, Quirks, SpriteEdge};
+use chip8_rs_dmfg::recording::{Recording, Replay};
+use chip8_rs_dmfg::rom_database::{self, RomInfo};
+use chip8_rs_dmfg::frontend::{self, AudioSink, Frontend, NoAudio, Screen};
+use crate::audio::Beeper;
+use crate::capture::GifRecorder;
+use crate::cli::{CliColor, FrontendKind, Theme, Timing, parse_address, parse_address_range, parse_memory_size, parse_register, parse_stack_size};
+use crate::desktop::DesktopRunner;
+use crate::keymap::Keymap;
+use crate::minifb_frontend::MinifbFrontend;
+use crate::terminal_frontend::TerminalFrontend;
+#[cfg(feature = "sdl2")]
+use crate::sdl_frontend::SdlFrontend;
+#[cfg(feature = "debug-ui")]
+use crate::debug_ui::DebugApp;
+
+mod audio;
+mod capture;
 mod cli;
+mod compare;
+mod desktop;
+#[cfg(feature = "debug-ui")]
+mod debug_ui;
+mod dump;
+mod gamepad;
+mod headless;
+mod keymap;
+mod minifb_frontend;
+#[cfg(feature = "sdl2")]
+mod sdl_frontend;
+mod stats;
+mod terminal_frontend;
 
 #[derive(Parser, Debug)]
 struct Args {
+    /// ROM to run, a file picker opens when it isn't given. Required for --headless and disassembling
     #[clap(short, long)]
-    rom_path: String,
+    rom_path: Option<String>,
     #[clap(short, long, default_value_t = 16)]
     display_scale: u32,
 
-    #[clap(short, long, default_value_t = 4096)]
-    memory: usize,
+    /// Memory size in bytes, 4096 by default. XO-CHIP defaults to and allows up to 65536
+    #[clap(short, long, value_parser = parse_memory_size)]
+    memory: Option<usize>,
 
-    #[clap(short, long, default_value_t = 16)]
+    /// Address programs are loaded and start at, e.g. 0x600 for ETI-660 programs
+    #[clap(long, default_value = "0x200", value_parser = parse_address)]
+    load_address: Address,
+
+    /// Allow a --load-address below 0x200, where it can overwrite the built in fonts
+    #[clap(long)]
+    force: bool,
+
+    /// Maximum subroutine nesting depth
+    #[clap(short, long, default_value_t = 16, value_parser = parse_stack_size)]
     stack: usize,
 
     #[clap(short, long, default_value_t = CliColor::new(255, 255, 25, 25))]
-    color: CliColor
+    color: CliColor,
+
+    /// Color of unlit pixels, same syntax as --color
+    #[clap(long, default_value_t = CliColor::new(255, 0, 0, 0))]
+    background_color: CliColor,
+
+    /// Color of pixels lit only on the second XO-CHIP plane, pixels lit on both get a blend of the two colors
+    #[clap(long, default_value_t = CliColor::new(255, 255, 102, 0))]
+    plane2_color: CliColor,
+
+    /// Color preset setting both colors at once: gameboy, amber or paperwhite
+    #[clap(long, conflicts_with_all = &["color", "background-color"])]
+    theme: Option<Theme>,
+
+    /// Key bindings as comma separated key=hex pairs, e.g. "1=1,2=2,3=3,4=c,q=4,...,v=f"
+    #[clap(long, conflicts_with = "keymap-file")]
+    keymap: Option<Keymap>,
+
+    /// File with one key=hex binding per line, # starts a comment.
+    /// Controller buttons are bound with controller1.button=hex and controller2.button=hex, e.g. controller2.dpadup=c
+    #[clap(long)]
+    keymap_file: Option<String>,
+
+    /// Run without a window for --max-ticks instructions, then print the display
+    #[clap(long)]
+    headless: bool,
+
+    /// Number of instructions to run in headless mode, defaults to the length of the --replay recording
+    #[clap(long)]
+    max_ticks: Option<u64>,
+
+    /// Close the window once the program ends with a jump to itself or the SUPER-CHIP exit instruction.
+    /// Headless runs always stop there
+    #[clap(long)]
+    exit_on_halt: bool,
+
+    /// Save the final display of a headless run as an image instead of printing it, a PNG for .png paths and a PPM otherwise
+    #[clap(long, requires = "headless")]
+    screenshot: Option<String>,
+
+    /// Print a hash of the final display of a headless run
+    #[clap(long, requires = "headless")]
+    hash: bool,
+
+    /// Instruction set to run the ROM with, chip8, schip or xochip. Detected from the ROM when not given
+    #[clap(long)]
+    variant: Option<Variant>,
+
+    /// Limit sprite drawing to once per frame like the COSMAC VIP, defaults to on for chip8 and off for schip
+    #[clap(long, value_name = "BOOL")]
+    display_wait: Option<bool>,
+
+    /// What happens to sprites drawn past the edge of the display: clip, like the COSMAC VIP and SUPER-CHIP, or wrap,
+    /// like XO-CHIP. The default depends on the variant
+    #[clap(long)]
+    sprite_edges: Option<SpriteEdge>,
+
+    /// Shift VX in place with 8XY6 and 8XYE instead of shifting VY into it, defaults to on for schip and off otherwise
+    #[clap(long, value_name = "BOOL")]
+    shift_in_place: Option<bool>,
+
+    /// Treat BNNN as BXNN, jumping to XNN plus VX rather than NNN plus V0. Defaults to on for schip and off otherwise
+    #[clap(long, value_name = "BOOL")]
+    jump_with_vx: Option<bool>,
+
+    /// How far FX55 and FX65 move I: x+1, past the last register, x, onto it, or none.
+    /// Defaults to x+1 for chip8 and xochip and none for schip
+    #[clap(long, value_name = "MODE")]
+    load_store_increment: Option<IndexIncrement>,
+
+    /// Set VF when FX1E takes I past 0xFFF, some ROMs for the Amiga interpreter depend on it. Off by default
+    #[clap(long, value_name = "BOOL")]
+    i_overflow_vf: Option<bool>,
+
+    /// What to do when an instruction reads or writes past the end of memory: wrap around to 0 or error
+    #[clap(long, default_value_t = MemoryPolicy::Error)]
+    on_out_of_bounds: MemoryPolicy,
+
+    /// Protect the interpreter area below 0x200 holding the fonts from writes: off, ignore (and log a warning) or error
+    #[clap(long, default_value_t = WriteProtection::Off)]
+    protect_low_memory: WriteProtection,
+
+    /// What to do with opcodes that don't decode or belong to another variant: ignore, warn or halt.
+    /// Halting stops the machine on the opcode and writes a dump
+    #[clap(long, default_value_t = OpcodePolicy::Warn)]
+    on_unknown: OpcodePolicy,
+
+    /// What to do with 0NNN machine code calls, which can't run here: ignore, warn or halt
+    #[clap(long, default_value_t = OpcodePolicy::Warn)]
+    on_sys: OpcodePolicy,
+
+    /// Instructions per frame: simple runs --ipf instructions each frame, vip spends the COSMAC VIP's cycle budget each frame
+    /// with slow instructions like DXYN costing more, for ROMs that rely on the original pacing
+    #[clap(long, default_value_t = Timing::Simple)]
+    timing: Timing,
+
+    /// Instructions per 60 Hz frame with --timing simple, 0 runs as many as the host can
+    #[clap(long, default_value_t = 15)]
+    ipf: u32,
+
+    /// Don't look the ROM up in the built in database or warn about instructions its variant can't run
+    #[clap(long)]
+    no_autodetect: bool,
+
+    /// Pause before executing the instruction at this address, can be given multiple times
+    #[clap(long = "break", value_parser = parse_address)]
+    breakpoints: Vec<Address>,
+
+    /// Pause after an instruction changes this register, e.g. V3. Can be given multiple times
+    #[clap(long, value_name = "REGISTER", value_parser = parse_register)]
+    watch_reg: Vec<u8>,
+
+    /// Pause after an instruction writes to memory in START..END, e.g. 0x300..0x310. Can be given multiple times
+    #[clap(long, value_name = "RANGE", value_parser = parse_address_range)]
+    watch_mem: Vec<Range<Address>>,
+
+    /// Log every executed instruction to FILE, or to stderr if no file is given
+    #[clap(long, value_name = "FILE")]
+    trace: Option<Option<String>>,
+
+    /// Stop tracing after this many instructions
+    #[clap(long)]
+    trace_limit: Option<u64>,
+
+    /// Frames each beep lasts at least, however short the sound timer was set
+    #[clap(long, value_name = "FRAMES", default_value_t = 1)]
+    min_beep: u32,
+
+    /// Seconds of play kept for rewinding with Backspace, 0 disables rewinding
+    #[clap(long, default_value_t = 10)]
+    rewind_seconds: usize,
+
+    /// Frames between the states kept for rewinding, higher values reach further back in the same memory
+    #[clap(long, value_name = "FRAMES", default_value_t = 1)]
+    rewind_interval: usize,
+
+    /// Megabytes the rewind states may take up, the oldest are dropped past this
+    #[clap(long, value_name = "MB", default_value_t = 64)]
+    rewind_memory: usize,
+
+    /// Start paused so breakpoints can be set up or the first instruction stepped with N
+    #[clap(long)]
+    pause_on_start: bool,
+
+    /// Write a memory and machine dump when the emulator closes, M writes one at any time
+    #[clap(long)]
+    dump_on_exit: bool,
+
+    /// Where dumps are written, - for stdout. Defaults to a timestamped file next to the ROM
+    #[clap(long, value_name = "PATH")]
+    dump_to: Option<String>,
+
+    /// Seed for CXNN random numbers, so runs with the same ROM and input play out the same. Random by default
+    #[clap(long, conflicts_with = "replay")]
+    seed: Option<u64>,
+
+    /// Record key presses to FILE so the session can be replayed with --replay
+    #[clap(long, value_name = "FILE", conflicts_with = "replay")]
+    record: Option<String>,
+
+    /// Replay the key presses of a --record session instead of reading the keyboard
+    #[clap(long, value_name = "FILE")]
+    replay: Option<String>,
+
+    /// Run with just the display, keys and sound, at --ipf instructions per frame. None of the debugging, rewind or
+    /// recording keys are available, this is the loop alternative frontends share.
+    #[clap(long, conflicts_with_all = &["headless", "record", "replay", "compare"])]
+    plain: bool,
+
+    /// Open the game in a window with a debugger panel showing registers, the stack and disassembly.
+    /// Needs the debug-ui feature
+    #[clap(long, conflicts_with_all = &["headless", "record", "replay", "compare", "plain"])]
+    debug_ui: bool,
+
+    /// Window library for --plain: minifb, sdl when built with the sdl2 feature, or terminal. Anything but minifb implies --plain
+    #[clap(long, default_value_t = FrontendKind::Minifb, conflicts_with_all = &["headless", "record", "replay", "compare"])]
+    frontend: FrontendKind,
+
+    /// Run a second machine to the right of the first with the same input, to compare settings or ROMs
+    #[clap(long, conflicts_with_all = &["headless", "record", "replay"])]
+    compare: bool,
+
+    /// ROM for the second --compare machine, defaults to the same ROM
+    #[clap(long, value_name = "FILE", requires = "compare")]
+    compare_rom: Option<String>,
+
+    /// Quirks preset of the second --compare machine: chip8, schip or xochip
+    #[clap(long, value_name = "VARIANT", requires = "compare")]
+    compare_quirks: Option<Variant>,
+
+    /// Draw pixels that differ between the two --compare machines in this color
+    #[clap(long, requires = "compare")]
+    highlight_differences: Option<CliColor>,
+
+    /// Record the display to an animated GIF from the start, F9 starts and stops a recording at any time
+    #[clap(long, value_name = "FILE")]
+    record_gif: Option<String>,
+
+    /// Stop the --record-gif recording after this many seconds, also sets the length of a headless run
+    #[clap(long, requires = "record-gif")]
+    record_seconds: Option<u64>,
+
+    #[clap(subcommand)]
+    command: Option<Command>
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the ROM as a CHIP-8 assembly listing without running it
+    Disassemble {
+        /// Only list code reachable from the load address, with labels on branch targets and everything else as data
+        #[clap(long)]
+        smart: bool,
+        /// Write the listing to this file instead of printing it
+        #[clap(short, long)]
+        output: Option<String>
+    },
+    /// Assemble a listing written with the disassembler's mnemonics, or Octo source, into a ROM
+    Assemble {
+        /// Assembly source file, .8o files are read as Octo
+        input: String,
+        /// Where to write the assembled ROM
+        output: String,
+        /// Read the source as Octo whatever its extension
+        #[clap(long)]
+        octo: bool
+    }
 }
 
 fn main() {
     let args: Args = Args::parse();
+    if let Some(Command::Assemble { input, output, octo }) = &args.command {
+        let octo = *octo || Path::new(input).extension().is_some_and(|extension| extension.eq_ignore_ascii_case("8o"));
+        let assembled = fs::read_to_string(input).map_err(|e| e.to_string())
+            .and_then(|source| if octo {assemble_octo(&source)} else {assemble(&source)}.map_err(|e| e.to_string()))
+            .and_then(|program| fs::write(output, program).map_err(|e| e.to_string()));
+        if let Err(e) = assembled {
+            eprintln!("Failed to assemble {}: {}", input, e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    let rom_path = match (&args.rom_path, args.headless || args.command.is_some()) {
+        (Some(rom_path), _) => rom_path.clone(),
+        (None, true) => {
+            eprintln!("--rom-path is required");
+            std::process::exit(1);
+        }
+        (None, false) => match pick_rom() {
+            Some(rom_path) => rom_path,
+            None => {
+                eprintln!("No ROM selected");
+                return;
+            }
+        }
+    };
+    if let Err(e) = check_load_address(&args) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    let program = match fs::read(&rom_path) {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", rom_path, e);
+            std::process::exit(1);
+        }
+    };
+    let rom_name = file_name(&rom_path);
+
+    if let Some(Command::Disassemble { smart, output }) = &args.command {
+        let lines = if *smart {
+            disassemble_smart(&program, args.load_address)
+        }else{
+            disassemble(&program, args.load_address).iter().map(|word| word.to_string()).collect()
+        };
+        match output {
+            Some(output) => {
+                let listing = lines.iter().map(|line| format!("{}\n", line)).collect::<String>();
+                if let Err(e) = fs::write(output, listing) {
+                    eprintln!("Failed to write {}: {}", output, e);
+                    std::process::exit(1);
+                }
+            }
+            None => lines.iter().for_each(|line| println!("{}", line))
+        }
+        return;
+    }
 
-    let mut chip = Chip8::new(args.memory, args.stack, args.display_scale, args.color.into(),
-    HashMap::from([
-        (Key::Key1, 0x1),
-        (Key::Key2, 0x2),
-        (Key::Key3, 0x3),
-        (Key::Q, 0x4),
-        (Key::W, 0x5),
-        (Key::E, 0x6),
-        (Key::A, 0x7),
-        (Key::S, 0x8),
-        (Key::D, 0x9),
-        (Key::Z, 0xA),
-        (Key::X, 0x0),
-        (Key::C, 0xB),
-        (Key::Key4, 0xC),
-        (Key::R, 0xD),
-        (Key::F, 0xE),
-        (Key::C, 0xF)
-    ]));
-    let (screen_width, screen_height) = chip.get_screen_size();
-    let mut window = Window::new("Chip-8", screen_width, screen_height, WindowOptions::default()).unwrap();
-    let program = fs::read(args.rom_path).expect("File not found");
-    log::set_max_level(LevelFilter::Info);
-    chip.load(&program);
-    let mut last_tick = time::Instant::now();
-    let spinner = ProgressBar::new_spinner();
-    spinner.set_style(ProgressStyle::with_template("{spinner} Chip-8 | run time: {elapsed} clock speed: {per_sec}").unwrap());
-    while window.is_open() {
-        window.get_keys_pressed(KeyRepeat::No).iter().for_each(|k|chip.set_pressed(k, true));
-        window.get_keys_released().iter().for_each(|k|chip.set_pressed(k, false));
-        spinner.inc(1);
-        chip.tick();
-        if last_tick.elapsed().as_secs_f32() >= 1f32/60f32 {
-            last_tick = time::Instant::now();
-            chip.decrement_time();
-            window.update_with_buffer(chip.get_screen_buffer(), screen_width, screen_height).unwrap();
+    let rom_info = if args.no_autodetect {None} else {rom_database::lookup(&program)};
+    // the database is trusted to pick the variant, guessing from the instructions only warns
+    let variant = match (args.variant, rom_info) {
+        (Some(variant), _) => variant,
+        (None, Some(info)) => info.variant,
+        (None, None) => Variant::Chip8
+    };
+    if !args.no_autodetect {
+        if variant != Variant::XoChip && rom_database::uses_xochip_instructions(&program, args.load_address) {
+            eprintln!("Warning: ROM contains XO-CHIP instructions but is running as {}, pass --variant xochip to run them", variant);
+        } else if variant == Variant::Chip8 && rom_database::uses_schip_instructions(&program, args.load_address) {
+            eprintln!("Warning: ROM contains SUPER-CHIP instructions but is running as chip8, pass --variant schip to run them");
         }
     }
+    // explicit flags win over the database, which wins over the variant's preset
+    let mut quirks = match (args.variant, rom_info) {
+        (None, Some(info)) => info.quirks,
+        _ => Quirks::for_variant(variant)
+    };
+    if let Some(display_wait) = args.display_wait {
+        quirks.display_wait = display_wait;
+    }
+    if let Some(sprite_edges) = args.sprite_edges {
+        quirks.sprite_edges = sprite_edges;
+    }
+    if let Some(in_place) = args.shift_in_place {
+        quirks.shift_in_place = in_place;
+    }
+    if let Some(jump_with_vx) = args.jump_with_vx {
+        quirks.jump_with_vx = jump_with_vx;
+    }
+    if let Some(increment) = args.load_store_increment {
+        quirks.load_store_increment = increment;
+    }
+    if let Some(sets_vf) = args.i_overflow_vf {
+        quirks.i_overflow_sets_vf = sets_vf;
+    }
+    let title = window_title(&rom_name, rom_info);
+    let memory = args.memory.unwrap_or(if variant == Variant::XoChip {0x10000} else {0x1000});
+    if memory > 0x1000 && variant != Variant::XoChip {
+        eprintln!("Memory past 4096 bytes is only addressable with --variant xochip");
+        std::process::exit(1);
+    }
+    let builder = Chip8::builder().memory(memory).stack_depth(args.stack);
+    let chip = match builder.clone().build() {
+        Ok(chip) => chip,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let mut chip = chip
+        .with_variant(variant)
+        .with_quirks(quirks)
+        .with_unknown_policy(args.on_unknown)
+        .with_sys_policy(args.on_sys)
+        .with_memory_policy(args.on_out_of_bounds)
+        .with_write_protection(args.protect_low_memory)
+        .with_origin(args.load_address)
+        .with_font_overwrite(args.force);
+    let keymap = match &args.keymap_file {
+        Some(path) => match fs::read_to_string(path).map_err(|e| e.to_string()).and_then(|s| s.parse::<Keymap>().map_err(|e| e.to_string())) {
+            Ok(keymap) => keymap,
+            Err(e) => {
+                eprintln!("Failed to load keymap from {}: {}", path, e);
+                std::process::exit(1);
+            }
+        },
+        None => args.keymap.clone().unwrap_or_default()
+    };
+    if let Err(e) = chip.load(&program) {
+        eprintln!("Failed to load {}: {}", rom_path, e);
+        std::process::exit(1);
+    }
+    if let Some(seed) = args.seed {
+        chip.set_rng_seed(seed);
+    }
+    let mut replay = match &args.replay {
+        Some(path) => {
+            let replay = fs::read_to_string(path).map_err(|e| e.to_string())
+                .and_then(|s| s.parse::<Recording>().map_err(|e| e.to_string()))
+                .and_then(|recording| Replay::new(recording, &mut chip).map_err(|e| e.to_string()));
+            match replay {
+                Ok(replay) => Some(replay),
+                Err(e) => {
+                    eprintln!("Failed to load recording from {}: {}", path, e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => None
+    };
+    let recording = args.record.as_ref().map(|_| {
+        let seed = args.seed.unwrap_or_else(rand::random::<u64>);
+        chip.set_rng_seed(seed);
+        Recording::new(&chip, seed)
+    });
+    let (foreground, background) = match args.theme {
+        Some(theme) => theme.colors(),
+        None => (args.color.clone(), args.background_color.clone())
+    };
+    // indexed by Chip8::pixel_color
+    let palette: [Color; 4] = [
+        background.into(),
+        foreground.clone().into(),
+        args.plane2_color.clone().into(),
+        foreground.blend(&args.plane2_color).into()
+    ];
+    // before any of the run modes so headless runs are traced too
+    if let Some(trace_path) = &args.trace {
+        let mut output: Box<dyn Write + Send> = match trace_path {
+            Some(path) => match File::create(path) {
+                Ok(file) => Box::new(BufWriter::new(file)),
+                Err(e) => {
+                    eprintln!("Failed to create trace file {}: {}", path, e);
+                    std::process::exit(1);
+                }
+            },
+            None => Box::new(io::stderr())
+        };
+        let mut remaining = args.trace_limit;
+        chip.set_trace_fn(move |event| {
+            if remaining == Some(0) {
+                return;
+            }
+            remaining = remaining.map(|r| r - 1);
+            let _ = writeln!(output, "{}", event);
+        });
+    }
+    if args.headless {
+        let ipf = args.ipf.max(1) as u64;
+        let max_ticks = args.max_ticks
+            .or_else(|| replay.as_ref().map(|r| r.end_tick()))
+            .or_else(|| args.record_seconds.map(|seconds| seconds * 60 * ipf));
+        let max_ticks = match max_ticks {
+            Some(max_ticks) => max_ticks,
+            None => {
+                eprintln!("--headless needs --max-ticks, --replay or --record-seconds");
+                std::process::exit(1);
+            }
+        };
+        let mut gif = args.record_gif.as_ref().and_then(|path| match GifRecorder::create(path, &palette) {
+            Ok(gif) => Some(gif),
+            Err(e) => {
+                eprintln!("Failed to create GIF {}: {}", path, e);
+                None
+            }
+        });
+        let mut record_frame = |chip: &Chip8| {
+            if let Some(Err(e)) = gif.as_mut().map(|recorder| recorder.push(chip)) {
+                eprintln!("Failed to write GIF: {}", e);
+                gif = None;
+            }
+        };
+        if let Err(e) = headless::run(&mut chip, max_ticks, ipf, replay.as_mut(), &mut record_frame) {
+            chip.clear_trace_fn();
+            eprintln!("Emulation stopped: {}", e);
+            eprintln!("{}", dump::format_dump(&chip));
+            std::process::exit(1);
+        }
+        if let Some(gif) = gif {
+            let path = gif.path().to_string();
+            if let Err(e) = gif.finish() {
+                eprintln!("Failed to write GIF {}: {}", path, e);
+            }
+        }
+        // a program ending in a self jump finished normally, anything else is worth a look
+        if let Some(reason) = chip.halted().filter(|reason| !matches!(reason, HaltReason::SelfJump(_))) {
+            eprintln!("Emulation halted: {}", reason);
+            if !args.dump_on_exit {
+                write_dump(&chip, &rom_path, &args.dump_to);
+            }
+        }
+        match &args.screenshot {
+            Some(path) => {
+                if let Err(e) = headless::write_screenshot(&chip, path, &palette) {
+                    eprintln!("Failed to write screenshot to {}: {}", path, e);
+                    std::process::exit(1);
+                }
+            }
+            None => println!("{}", headless::render_text(&chip))
+        }
+        if args.hash {
+            println!("{:016x}", chip.display_hash());
+        }
+        if args.dump_on_exit {
+            write_dump(&chip, &rom_path, &args.dump_to);
+        }
+        return;
+    }
+    if args.compare {
+        let path_b = args.compare_rom.as_ref().unwrap_or(&rom_path);
+        let program_b = match fs::read(path_b) {
+            Ok(program) => program,
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", path_b, e);
+                std::process::exit(1);
+            }
+        };
+        // the same sizes already built once for A
+        let mut chip_b = builder.build().unwrap()
+            .with_variant(variant)
+            .with_quirks(args.compare_quirks.map_or(quirks, Quirks::for_variant))
+            .with_unknown_policy(args.on_unknown)
+            .with_sys_policy(args.on_sys)
+            .with_memory_policy(args.on_out_of_bounds)
+            .with_write_protection(args.protect_low_memory)
+            .with_origin(args.load_address)
+        .with_font_overwrite(args.force);
+        if let Err(e) = chip_b.load(&program_b) {
+            eprintln!("Failed to load {}: {}", path_b, e);
+            std::process::exit(1);
+        }
+        // both machines draw the same random numbers, otherwise random games drift apart right away
+        let seed = args.seed.unwrap_or_else(rand::random::<u64>);
+        chip.set_rng_seed(seed);
+        chip_b.set_rng_seed(seed);
+        let highlight: Option<Color> = args.highlight_differences.clone().map(Into::into);
+        compare::run([chip, chip_b], &keymap, &palette, highlight, args.display_scale as usize, &title);
+        return;
+    }
+    if args.plain || args.frontend != FrontendKind::Minifb {
+        let (mut frontend, mut audio): (Box<dyn Frontend>, Box<dyn AudioSink>) = match args.frontend {
+            FrontendKind::Minifb => {
+                let frontend = MinifbFrontend::new(&title, args.display_scale as usize, palette, keymap);
+                let audio: Box<dyn AudioSink> = match Beeper::new(args.min_beep) {
+                    Ok(beeper) => Box::new(beeper),
+                    Err(e) => {
+                        eprintln!("No audio output, running without sound: {}", e);
+                        Box::new(NoAudio)
+                    }
+                };
+                (Box::new(frontend), audio)
+            }
+            FrontendKind::Terminal => {
+                // printed before the alternate screen is entered, so it's there again after quitting
+                let audio: Box<dyn AudioSink> = match Beeper::new(args.min_beep) {
+                    Ok(beeper) => Box::new(beeper),
+                    Err(e) => {
+                        eprintln!("No audio output, running without sound: {}", e);
+                        Box::new(NoAudio)
+                    }
+                };
+                match TerminalFrontend::new(palette, keymap) {
+                    Ok(frontend) => (Box::new(frontend), audio),
+                    Err(e) => {
+                        eprintln!("Failed to set up the terminal: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            #[cfg(feature = "sdl2")]
+            FrontendKind::Sdl => match SdlFrontend::new(&title, args.display_scale as usize, palette, keymap) {
+                Ok(frontend) => {
+                    let audio: Box<dyn AudioSink> = match frontend.beeper(args.min_beep) {
+                        Ok(beeper) => Box::new(beeper),
+                        Err(e) => {
+                            eprintln!("No audio output, running without sound: {}", e);
+                            Box::new(NoAudio)
+                        }
+                    };
+                    (Box::new(frontend), audio)
+                }
+                Err(e) => {
+                    eprintln!("Failed to start SDL: {}", e);
+                    std::process::exit(1);
+                }
+            },
+            // FrontendKind doesn't parse sdl without the feature
+            #[cfg(not(feature = "sdl2"))]
+            FrontendKind::Sdl => unreachable!()
+        };
+        let result = frontend::run(&mut chip, args.ipf.max(1), frontend.as_mut(), audio.as_mut());
+        // closes the window or gives the terminal back before anything is printed
+        drop(frontend);
+        if let Err(e) = result {
+            chip.clear_trace_fn();
+            eprintln!("Emulation stopped: {}", e);
+            eprintln!("{}", dump::format_dump(&chip));
+            std::process::exit(1);
+        }
+        return;
+    }
+    if args.debug_ui {
+        run_debug_ui(chip, keymap, &palette, &args, &title);
+        return;
+    }
+    let runner = DesktopRunner::new(&args, &rom_path, &palette, replay, recording);
+    desktop::run(chip, runner, &args, title, palette, keymap);
+}
+
+/// Load addresses below 0x200 are in the interpreter area and only allowed with --force
+fn check_load_address(args: &Args) -> Result<(), String> {
+    if args.load_address < DEFAULT_ORIGIN && !args.force {
+        return Err(format!("Load address 0x{:03X} is in the interpreter area holding the fonts, pass --force to load there anyway", args.load_address));
+    }
+    Ok(())
+}
+
+/// Asks for a ROM with a file dialog, `None` if it was cancelled
+fn pick_rom() -> Option<String> {
+    rfd::FileDialog::new()
+        .set_title("Open CHIP-8 ROM")
+        .add_filter("CHIP-8 ROM", &["ch8", "c8", "sc8", "xo8"])
+        .add_filter("All files", &["*"])
+        .pick_file()
+        .map(|path| path.to_string_lossy().to_string())
+}
+
+fn file_name(path: &str) -> String {
+    Path::new(path).file_name().map_or(path.to_string(), |name| name.to_string_lossy().to_string())
+}
+
+fn window_title(rom_name: &str, info: Option<&RomInfo>) -> String {
+    match info {
+        Some(info) => format!("Chip-8 - {}", info.title),
+        None => format!("Chip-8 - {}", rom_name)
+    }
+}
+
+fn start_gif(path: &str, palette: &[Color; 4], spinner: &ProgressBar) -> Option<GifRecorder> {
+    match GifRecorder::create(path, palette) {
+        Ok(recorder) => {
+            spinner.println(format!("Recording GIF to {}", path));
+            Some(recorder)
+        }
+        Err(e) => {
+            spinner.println(format!("Failed to create GIF {}: {}", path, e));
+            None
+        }
+    }
+}
+
+fn finish_gif(recorder: GifRecorder, spinner: &ProgressBar) {
+    let path = recorder.path().to_string();
+    match recorder.finish() {
+        Ok(_) => spinner.println(format!("Saved GIF to {}", path)),
+        Err(e) => spinner.println(format!("Failed to write GIF {}: {}", path, e))
+    }
+}
+
+fn stop_with_error(chip: &mut Chip8, spinner: &ProgressBar, error: Chip8Error) -> ! {
+    // dropping the trace hook flushes a buffered trace file, process::exit won't run destructors
+    chip.clear_trace_fn();
     spinner.finish();
+    eprintln!("Emulation stopped: {}", error);
+    eprintln!("{}", dump::format_dump(chip));
+    std::process::exit(1);
+}
+
+fn write_dump(chip: &Chip8, rom_path: &str, dump_to: &Option<String>) {
+    let dump = dump::format_dump(chip);
+    match dump_to.as_deref() {
+        Some("-") => println!("{}", dump),
+        path => {
+            let path = path.map(|p| p.to_string()).unwrap_or_else(|| dump::default_dump_path(rom_path));
+            match fs::write(&path, dump) {
+                Ok(_) => eprintln!("Wrote dump to {}", path),
+                Err(e) => eprintln!("Failed to write dump to {}: {}", path, e)
+            }
+        }
+    }
+}
+
+fn format_machine_state(chip: &Chip8) -> String {
+    let next_instruction = chip.peek_next_instruction()
+        .map(|instruction| instruction.to_string())
+        .unwrap_or_else(|| "???".to_string());
+    let mut lines = vec![
+        format!("PC: 0x{:04x}  I: 0x{:04x}  DT: {}  ST: {}  stack depth: {}  next: {}",
+                chip.pc(), chip.i(), chip.delay_timer(), chip.sound_timer(), chip.stack().len(), next_instruction)
+    ];
+    let registers = chip.registers().iter().enumerate()
+        .map(|(i, v)| format!("V{:X}: 0x{:02x}", i, v))
+        .collect::<Vec<String>>();
+    registers.chunks(8).for_each(|row| lines.push(row.join("  ")));
+    lines.join("\n")
+}
+
+#[cfg(feature = "debug-ui")]
+fn run_debug_ui(mut chip: Chip8, keymap: Keymap, palette: &[Color; 4], args: &Args, title: &str) {
+    args.breakpoints.iter().for_each(|address| chip.add_breakpoint(*address));
+    let audio: Box<dyn AudioSink> = match Beeper::new(args.min_beep) {
+        Ok(beeper) => Box::new(beeper),
+        Err(e) => {
+            eprintln!("No audio output, running without sound: {}", e);
+            Box::new(NoAudio)
+        }
+    };
+    let app = DebugApp::new(chip, keymap, palette, audio, args.ipf.max(1));
+    if let Err(e) = debug_ui::run(app, args.pause_on_start, args.display_scale as usize, title) {
+        eprintln!("Failed to open the debugger window: {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(feature = "debug-ui"))]
+fn run_debug_ui(_chip: Chip8, _keymap: Keymap, _palette: &[Color; 4], _args: &Args, _title: &str) {
+    eprintln!("This build has no debugger panel, rebuild with --features debug-ui");
+    std::process::exit(1);
+}
+
+/// Draws the display into the `width` wide area of `screen` starting at `origin_x`.
+/// Only the rows set in `rows`, as returned by `Chip8::take_dirty_rows`, are redrawn and the rest is left as it is.
+fn draw_display(display: &dyn Screen, screen: &mut DrawTarget, palette: &[Color; 4], origin_x: f32, width: f32, rows: u64) {
+    let (display_width, display_height) = display.get_screen_size();
+    // the window keeps its size when a SUPER-CHIP program switches resolution, so pixels are scaled to fit
+    let pixel_size = width / display_width as f32;
+    for row in (0..display_height).filter(|row| rows.shr(row).bitand(1) == 1) {
+        let y = row as f32 * pixel_size;
+        screen.fill_rect(origin_x, y, width, pixel_size, &Source::Solid(SolidSource::from(palette[0])), &DrawOptions::default());
+        for column in 0..display_width {
+            let color = display.pixel_color(row * display_width + column);
+            if color != 0 {
+                fill_pixel(screen, origin_x + column as f32 * pixel_size, y, pixel_size, palette[color]);
+            }
+        }
+    }
+}
+
+fn fill_pixel(screen: &mut DrawTarget, x: f32, y: f32, size: f32, color: Color) {
+    screen.fill_rect(x, y, size, size, &Source::Solid(SolidSource::from(color)), &DrawOptions::default());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn arguments_refer_to_each_other_by_existing_ids() {
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn load_addresses_below_the_default_need_force() {
+        let parse = |arguments: &[&str]| Args::try_parse_from([&["chip8"], arguments].concat()).unwrap();
+        assert!(check_load_address(&parse(&["--load-address", "0x100"])).is_err());
+        assert!(check_load_address(&parse(&["--load-address", "0x100", "--force"])).is_ok());
+        assert!(check_load_address(&parse(&["--load-address", "0x600"])).is_ok());
+    }
 }
@@ -0,0 +1,95 @@
+use minifb::{Key, KeyRepeat, Window, WindowOptions};
+use raqote::{Color, DrawTarget};
+use chip8_rs_dmfg::chip8::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use chip8_rs_dmfg::frontend::{AudioSink, Control, InputSource, KeyEvent, Renderer, Screen};
+use crate::audio::Beeper;
+use crate::draw_display;
+use crate::keymap::Keymap;
+
+/// The emulator's own keys, Backspace for rewinding is held rather than pressed so it's handled apart from these
+const CONTROLS: [(Key, Control); 10] = [
+    (Key::F1, Control::Reset),
+    (Key::F2, Control::Reload),
+    (Key::F3, Control::Open),
+    (Key::F5, Control::SaveState),
+    (Key::F7, Control::LoadState),
+    (Key::P, Control::Pause),
+    (Key::N, Control::Step),
+    (Key::M, Control::Dump),
+    (Key::F9, Control::ToggleGif),
+    (Key::F12, Control::Screenshot)
+];
+
+/// A minifb window drawn with raqote, the frontend the emulator has always used
+pub struct MinifbFrontend {
+    window: Window,
+    screen: DrawTarget,
+    palette: [Color; 4],
+    keymap: Keymap,
+    // input is collected whenever the window is updated, so polling only updates it when present didn't
+    presented: bool,
+    focused: bool,
+    rewinding: bool
+}
+
+impl MinifbFrontend {
+    pub fn new(title: &str, scale: usize, palette: [Color; 4], keymap: Keymap) -> Self {
+        let (width, height) = (DISPLAY_WIDTH * scale, DISPLAY_HEIGHT * scale);
+        MinifbFrontend {
+            window: Window::new(title, width, height, WindowOptions::default()).unwrap(),
+            screen: DrawTarget::new(width as i32, height as i32),
+            palette,
+            keymap,
+            presented: false,
+            focused: true,
+            rewinding: false
+        }
+    }
+}
+
+impl Renderer for MinifbFrontend {
+    fn present(&mut self, display: &dyn Screen, dirty_rows: u64) {
+        let (width, height) = (self.screen.width() as usize, self.screen.height() as usize);
+        draw_display(display, &mut self.screen, &self.palette, 0.0, width as f32, dirty_rows);
+        self.window.update_with_buffer(self.screen.get_data(), width, height).unwrap();
+        self.presented = true;
+    }
+
+    fn set_title(&mut self, title: &str) {
+        self.window.set_title(title);
+    }
+}
+
+impl InputSource for MinifbFrontend {
+    fn poll(&mut self) -> Vec<KeyEvent> {
+        if !std::mem::take(&mut self.presented) {
+            self.window.update();
+        }
+        if !self.window.is_open() {
+            return vec![KeyEvent::Quit];
+        }
+        let mut events = Vec::new();
+        if self.window.is_active() != self.focused {
+            self.focused = !self.focused;
+            events.push(KeyEvent::Focus(self.focused));
+            if self.focused {
+                events.extend(self.window.get_keys().iter().filter_map(|key| self.keymap.hex_key(key)).map(KeyEvent::Pressed));
+            }
+        }
+        if self.window.is_key_down(Key::Backspace) != self.rewinding {
+            self.rewinding = !self.rewinding;
+            events.push(KeyEvent::Control(Control::Rewind(self.rewinding)));
+        }
+        let pressed_keys = self.window.get_keys_pressed(KeyRepeat::No);
+        events.extend(pressed_keys.iter().filter_map(|key| self.keymap.hex_key(key)).map(KeyEvent::Pressed));
+        events.extend(self.window.get_keys_released().iter().filter_map(|key| self.keymap.hex_key(key)).map(KeyEvent::Released));
+        events.extend(CONTROLS.iter().filter(|(key, _)| pressed_keys.contains(key)).map(|(_, control)| KeyEvent::Control(*control)));
+        events
+    }
+}
+
+impl AudioSink for Beeper {
+    fn set_beep(&mut self, on: bool) {
+        self.update(on as u8, false);
+    }
+}
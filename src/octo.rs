@@ -0,0 +1,622 @@
+//! Assembles Octo (.8o) source, the syntax most CHIP-8 homebrew is written in. Covers labels, `:const`, `:alias`,
+//! `:org`, `:byte`, `:call`, register arithmetic, `i :=`, timers, keys, sprites, bcd/save/load, the SUPER-CHIP and
+//! XO-CHIP statements, `if ... then`, `if ... begin ... else ... end` and `loop ... while ... again`.
+//! Macros, `:calc`, `:next`, `:unpack`, `:stringmode`, `:assert` and `pitch` are reported as unsupported.
+use std::collections::HashMap;
+use crate::assembler::{assemble_error, AssembleError, ORIGIN};
+use crate::chip8_instruction_set::{Address, Instruction, Operand, ParseInstructionError, Register};
+
+const UNSUPPORTED: [&str; 7] = [":macro", ":calc", ":next", ":unpack", ":stringmode", ":assert", "pitch"];
+
+/// A forward reference to a label, filled in once the whole program is assembled
+struct Fixup {
+    at: usize,
+    line: usize,
+    label: String,
+    /// The 16 bit address after `i := long`, otherwise the low 12 bits of an instruction
+    long: bool
+}
+
+enum Block {
+    /// `jump` skips the block when the condition is false
+    If { line: usize, jump: usize },
+    /// `jump` skips the else block when the if block ran
+    Else { line: usize, jump: usize },
+    /// Every `while` jumps to after `again`
+    Loop { line: usize, start: Address, exits: Vec<usize> }
+}
+
+/// The condition between `if` or `while` and `then`/`begin`, e.g. `v0 == 5`, `v1 < v2` or `v3 -key`
+struct Condition<'a> {
+    register: &'a str,
+    op: &'a str,
+    operand: Option<&'a str>
+}
+
+struct Octo<'a> {
+    tokens: Vec<(usize, &'a str)>,
+    next: usize,
+    /// Index of the first token of the statement being assembled
+    statement: usize,
+    program: Vec<u8>,
+    labels: HashMap<&'a str, Address>,
+    constants: HashMap<&'a str, Address>,
+    aliases: HashMap<&'a str, Register>,
+    fixups: Vec<Fixup>,
+    blocks: Vec<Block>,
+    /// Whether the program starts with a `jump main`, decided when the first code is assembled
+    main_jump: Option<bool>
+}
+
+/// Assembles Octo source into a ROM loaded at `ORIGIN`. Tokens are separated by whitespace and `#` starts a comment.
+///
+/// Like Octo, the program starts with a jump to `main` unless `: main` comes before any code, a name on its own calls
+/// that label and `vf` is overwritten by the `<`, `>`, `<=` and `>=` comparisons.
+pub fn assemble_octo(source: &str) -> Result<Vec<u8>, AssembleError> {
+    let mut octo = Octo {
+        tokens: source.lines()
+            .enumerate()
+            .flat_map(|(i, line)| line.split('#').next().unwrap_or("").split_whitespace().map(move |token| (i + 1, token)))
+            .collect(),
+        next: 0,
+        statement: 0,
+        program: Vec::new(),
+        labels: HashMap::new(),
+        constants: HashMap::new(),
+        aliases: HashMap::new(),
+        fixups: Vec::new(),
+        blocks: Vec::new(),
+        main_jump: None
+    };
+    while octo.next < octo.tokens.len() {
+        octo.statement()?;
+    }
+    octo.finish()
+}
+
+impl<'a> Octo<'a> {
+    fn address(&self) -> usize {
+        ORIGIN as usize + self.program.len()
+    }
+
+    fn line(&self) -> usize {
+        self.tokens[self.statement].0
+    }
+
+    fn statement_text(&self) -> String {
+        self.tokens[self.statement..self.next].iter().map(|(_, token)| *token).collect::<Vec<&str>>().join(" ")
+    }
+
+    fn invalid(&self) -> AssembleError {
+        AssembleError::InvalidOperands(self.line(), self.statement_text())
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.next).map(|(_, token)| *token)
+    }
+
+    fn expect(&mut self) -> Result<&'a str, AssembleError> {
+        match self.peek() {
+            Some(token) => {
+                self.next += 1;
+                Ok(token)
+            }
+            None => Err(self.invalid())
+        }
+    }
+
+    fn expect_token(&mut self, expected: &str) -> Result<(), AssembleError> {
+        match self.expect()? {
+            token if token == expected => Ok(()),
+            _ => Err(self.invalid())
+        }
+    }
+
+    fn register(&self, token: &str) -> Option<Register> {
+        if let Some(register) = self.aliases.get(token) {
+            return Some(*register);
+        }
+        match token.strip_prefix(['v', 'V']) {
+            Some(digit) if digit.len() == 1 => u8::from_str_radix(digit, 16).ok(),
+            _ => None
+        }
+    }
+
+    /// A number literal, constant or label defined above, `None` for anything else. Negative numbers are bytes.
+    fn value(&self, token: &str) -> Result<Option<Address>, ParseInstructionError> {
+        if let Some(value) = self.constants.get(token).or_else(|| self.labels.get(token)) {
+            return Ok(Some(*value));
+        }
+        let (negative, digits) = match token.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, token)
+        };
+        if !digits.starts_with(|c: char| c.is_ascii_digit()) {
+            return Ok(None);
+        }
+        let parsed = if let Some(hex) = digits.strip_prefix("0x") {
+            Address::from_str_radix(hex, 16)
+        }else if let Some(binary) = digits.strip_prefix("0b") {
+            Address::from_str_radix(binary, 2)
+        }else{
+            digits.parse::<Address>()
+        };
+        match parsed {
+            Ok(value) if !negative => Ok(Some(value)),
+            Ok(value) if value <= 0x80 => Ok(Some((value as u8).wrapping_neg() as Address)),
+            _ => Err(ParseInstructionError::InvalidNumber(token.to_string()))
+        }
+    }
+
+    /// Anything that isn't a register or a value is taken to be a label defined further down
+    fn operand(&self, token: &str, forward: &mut Option<String>) -> Result<Operand, ParseInstructionError> {
+        if let Some(register) = self.register(token) {
+            return Ok(Operand::V(register));
+        }
+        if let Some(value) = self.value(token)? {
+            return Ok(Operand::Number(value));
+        }
+        match token {
+            // only ever passed in by the statements below, Octo spells these differently
+            "I" | "[I]" | "DT" | "ST" | "K" | "F" | "HF" | "B" | "R" | "LONG" => token.parse(),
+            _ => {
+                *forward = Some(token.to_string());
+                // any address a label can have, 0 isn't a valid SYS target
+                Ok(Operand::Number(ORIGIN))
+            }
+        }
+    }
+
+    /// The first code decides whether the program needs a jump to `main` in front of it
+    fn begin_code(&mut self) {
+        if self.main_jump.is_none() {
+            let jump = !self.labels.contains_key("main");
+            if jump {
+                // labels so far point at the code, which now starts after the jump
+                self.labels.values_mut().for_each(|label| *label += 2);
+                self.program.extend([0x10, 0x00]);
+            }
+            self.main_jump = Some(jump);
+        }
+    }
+
+    /// Assembles one instruction written with the disassembler's mnemonics
+    fn instruction(&mut self, mnemonic: &str, tokens: &[&str]) -> Result<(), AssembleError> {
+        self.begin_code();
+        let line = self.line();
+        let mut forward = None;
+        let instruction = Instruction::from_tokens(mnemonic, tokens, |token| self.operand(token, &mut forward))
+            .map_err(|e| assemble_error(e, line, &self.statement_text()))?;
+        if let Some(label) = forward {
+            match instruction {
+                Instruction::JumpToAddress(_) | Instruction::JumpWithOffset(_) | Instruction::ExecSubroutine(_)
+                | Instruction::ExecSubroutineML(_) | Instruction::StoreAddressToI(_) => {
+                    self.fixups.push(Fixup { at: self.program.len(), line, label, long: false });
+                }
+                Instruction::LoadILong(_) => self.fixups.push(Fixup { at: self.program.len() + 2, line, label, long: true }),
+                _ => return Err(AssembleError::UnknownLabel(line, label))
+            }
+        }
+        self.program.extend(instruction.to_bytes());
+        Ok(())
+    }
+
+    /// A `jump` to be pointed somewhere with `patch` once the address is known
+    fn jump_placeholder(&mut self) -> usize {
+        self.begin_code();
+        self.program.extend([0x10, 0x00]);
+        self.program.len() - 2
+    }
+
+    fn patch(&mut self, at: usize, address: usize, line: usize) -> Result<(), AssembleError> {
+        if address > 0xFFF {
+            return Err(AssembleError::ValueOutOfRange(line, format!("0x{:X}", address)));
+        }
+        // a native call to a label
+        if self.program[at] & 0xF0 == 0 && !Instruction::is_sys_target(address as Address) {
+            return Err(AssembleError::SysAddressTaken(line, format!("0x{:03X}", address)));
+        }
+        self.program[at] = (self.program[at] & 0xF0) | (address >> 8) as u8;
+        self.program[at + 1] = address as u8;
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<(), AssembleError> {
+        self.statement = self.next;
+        let line = self.line();
+        let token = self.expect()?;
+        match token {
+            ":" => {
+                let name = self.expect()?;
+                if self.labels.insert(name, self.address() as Address).is_some() {
+                    return Err(AssembleError::DuplicateLabel(line, name.to_string()));
+                }
+            }
+            ":const" => {
+                let name = self.expect()?;
+                let value = self.expect()?;
+                match self.value(value) {
+                    Ok(Some(value)) => {
+                        self.constants.insert(name, value);
+                    }
+                    Ok(None) => return Err(AssembleError::UnknownLabel(line, value.to_string())),
+                    Err(_) => return Err(AssembleError::ValueOutOfRange(line, value.to_string()))
+                }
+            }
+            ":alias" => {
+                let name = self.expect()?;
+                let register = self.expect()?;
+                let register = self.register(register).ok_or_else(|| self.invalid())?;
+                self.aliases.insert(name, register);
+            }
+            ":org" => {
+                let origin = self.expect()?;
+                let origin = match self.value(origin) {
+                    Ok(Some(origin)) => origin as usize,
+                    _ => return Err(AssembleError::ValueOutOfRange(line, origin.to_string()))
+                };
+                self.begin_code();
+                if origin < self.address() {
+                    return Err(AssembleError::OriginBackwards(line, format!("0x{:X}", origin)));
+                }
+                self.program.resize(origin - ORIGIN as usize, 0);
+            }
+            ":byte" => {
+                let value = self.expect()?;
+                self.byte(value)?;
+            }
+            ":call" => {
+                let address = self.expect()?;
+                self.instruction("CALL", &[address])?;
+            }
+            ":breakpoint" => {
+                self.expect()?;
+            }
+            ":monitor" => {
+                self.expect()?;
+                self.expect()?;
+            }
+            _ if UNSUPPORTED.contains(&token) => return Err(AssembleError::Unsupported(line, token.to_string())),
+            "clear" => self.instruction("CLS", &[])?,
+            "return" | ";" => self.instruction("RET", &[])?,
+            "hires" => self.instruction("HIGH", &[])?,
+            "lores" => self.instruction("LOW", &[])?,
+            "scroll-right" => self.instruction("SCR", &[])?,
+            "scroll-left" => self.instruction("SCL", &[])?,
+            "exit" => self.instruction("EXIT", &[])?,
+            "audio" => self.instruction("AUDIO", &[])?,
+            "scroll-down" | "scroll-up" | "plane" => {
+                let mnemonic = match token {
+                    "scroll-down" => "SCD",
+                    "scroll-up" => "SCU",
+                    _ => "PLANE"
+                };
+                let n = self.expect()?;
+                self.instruction(mnemonic, &[n])?;
+            }
+            "bcd" => {
+                let x = self.expect()?;
+                self.instruction("LD", &["B", x])?;
+            }
+            "saveflags" => {
+                let x = self.expect()?;
+                self.instruction("LD", &["R", x])?;
+            }
+            "loadflags" => {
+                let x = self.expect()?;
+                self.instruction("LD", &[x, "R"])?;
+            }
+            "save" | "load" => {
+                let x = self.expect()?;
+                if self.peek() == Some("-") {
+                    self.next += 1;
+                    let y = self.expect()?;
+                    self.instruction(if token == "save" {"SAVE"} else {"LOAD"}, &[x, y])?;
+                }else if token == "save" {
+                    self.instruction("LD", &["[I]", x])?;
+                }else{
+                    self.instruction("LD", &[x, "[I]"])?;
+                }
+            }
+            "sprite" => {
+                let x = self.expect()?;
+                let y = self.expect()?;
+                let n = self.expect()?;
+                self.instruction("DRW", &[x, y, n])?;
+            }
+            "jump" => {
+                let address = self.expect()?;
+                self.instruction("JP", &[address])?;
+            }
+            "jump0" => {
+                let address = self.expect()?;
+                self.instruction("JP", &["V0", address])?;
+            }
+            "native" => {
+                let address = self.expect()?;
+                self.instruction("SYS", &[address])?;
+            }
+            "i" => match self.expect()? {
+                ":=" => match self.expect()? {
+                    "hex" => {
+                        let x = self.expect()?;
+                        self.instruction("LD", &["F", x])?;
+                    }
+                    "bighex" => {
+                        let x = self.expect()?;
+                        self.instruction("LD", &["HF", x])?;
+                    }
+                    "long" => {
+                        let address = self.expect()?;
+                        self.instruction("LD", &["I", "LONG", address])?;
+                    }
+                    address => self.instruction("LD", &["I", address])?
+                },
+                "+=" => {
+                    let x = self.expect()?;
+                    self.instruction("ADD", &["I", x])?;
+                }
+                _ => return Err(self.invalid())
+            },
+            "delay" | "buzzer" => {
+                self.expect_token(":=")?;
+                let x = self.expect()?;
+                self.instruction("LD", &[if token == "delay" {"DT"} else {"ST"}, x])?;
+            }
+            "if" => {
+                let condition = self.read_condition()?;
+                match self.expect()? {
+                    "then" => self.condition(&condition, false)?,
+                    "begin" => {
+                        self.condition(&condition, true)?;
+                        let jump = self.jump_placeholder();
+                        self.blocks.push(Block::If { line, jump });
+                    }
+                    _ => return Err(self.invalid())
+                }
+            }
+            "else" => match self.blocks.pop() {
+                Some(Block::If { jump, .. }) => {
+                    let else_jump = self.jump_placeholder();
+                    self.patch(jump, self.address(), line)?;
+                    self.blocks.push(Block::Else { line, jump: else_jump });
+                }
+                _ => return Err(AssembleError::UnbalancedBlock(line, token.to_string()))
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::If { jump, .. }) | Some(Block::Else { jump, .. }) => self.patch(jump, self.address(), line)?,
+                _ => return Err(AssembleError::UnbalancedBlock(line, token.to_string()))
+            },
+            "loop" => {
+                self.begin_code();
+                self.blocks.push(Block::Loop { line, start: self.address() as Address, exits: Vec::new() });
+            }
+            "while" => {
+                if !matches!(self.blocks.last(), Some(Block::Loop { .. })) {
+                    return Err(AssembleError::UnbalancedBlock(line, token.to_string()));
+                }
+                let condition = self.read_condition()?;
+                self.condition(&condition, true)?;
+                let exit = self.jump_placeholder();
+                if let Some(Block::Loop { exits, .. }) = self.blocks.last_mut() {
+                    exits.push(exit);
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop { start, exits, .. }) => {
+                    let jump = self.jump_placeholder();
+                    self.patch(jump, start as usize, line)?;
+                    for exit in exits {
+                        self.patch(exit, self.address(), line)?;
+                    }
+                }
+                _ => return Err(AssembleError::UnbalancedBlock(line, token.to_string()))
+            },
+            "then" | "begin" => return Err(AssembleError::UnbalancedBlock(line, token.to_string())),
+            _ if self.register(token).is_some() => self.assignment(token)?,
+            // numbers and constants on their own are data, any other name is a call
+            _ if self.constants.contains_key(token) || token.starts_with(|c: char| c.is_ascii_digit() || c == '-') => self.byte(token)?,
+            _ => self.instruction("CALL", &[token])?
+        }
+        Ok(())
+    }
+
+    fn byte(&mut self, token: &str) -> Result<(), AssembleError> {
+        match self.value(token) {
+            Ok(Some(value)) if value <= 0xFF => {
+                self.begin_code();
+                self.program.push(value as u8);
+                Ok(())
+            }
+            Ok(None) => Err(AssembleError::UnknownLabel(self.line(), token.to_string())),
+            _ => Err(AssembleError::ValueOutOfRange(self.line(), token.to_string()))
+        }
+    }
+
+    /// `vx := ...`, `vx += ...` and the other operators with a register on the left
+    fn assignment(&mut self, x: &'a str) -> Result<(), AssembleError> {
+        let op = self.expect()?;
+        let y = self.expect()?;
+        match op {
+            ":=" => match y {
+                "delay" => self.instruction("LD", &[x, "DT"]),
+                "key" => self.instruction("LD", &[x, "K"]),
+                "random" => {
+                    let mask = self.expect()?;
+                    self.instruction("RND", &[x, mask])
+                }
+                _ => self.instruction("LD", &[x, y])
+            },
+            "+=" => self.instruction("ADD", &[x, y]),
+            "-=" if self.register(y).is_some() => self.instruction("SUB", &[x, y]),
+            // subtracting a number is adding its negation
+            "-=" => match self.value(y) {
+                Ok(Some(value)) if value <= 0xFF => {
+                    let negated = (value as u8).wrapping_neg().to_string();
+                    self.instruction("ADD", &[x, &negated])
+                }
+                Ok(None) => Err(AssembleError::UnknownLabel(self.line(), y.to_string())),
+                _ => Err(AssembleError::ValueOutOfRange(self.line(), y.to_string()))
+            },
+            "=-" => self.instruction("SUBN", &[x, y]),
+            "|=" => self.instruction("OR", &[x, y]),
+            "&=" => self.instruction("AND", &[x, y]),
+            "^=" => self.instruction("XOR", &[x, y]),
+            ">>=" => self.instruction("SHR", &[x, y]),
+            "<<=" => self.instruction("SHL", &[x, y]),
+            _ => Err(self.invalid())
+        }
+    }
+
+    fn read_condition(&mut self) -> Result<Condition<'a>, AssembleError> {
+        let register = self.expect()?;
+        let op = self.expect()?;
+        let operand = match op {
+            "key" | "-key" => None,
+            "==" | "!=" | "<" | ">" | "<=" | ">=" => Some(self.expect()?),
+            _ => return Err(self.invalid())
+        };
+        Ok(Condition { register, op, operand })
+    }
+
+    /// Skips the next instruction when the condition is false, or when it's true if `skip_when_true`
+    fn condition(&mut self, condition: &Condition<'a>, skip_when_true: bool) -> Result<(), AssembleError> {
+        let x = condition.register;
+        match (condition.op, condition.operand) {
+            ("key", _) => self.instruction(if skip_when_true {"SKP"} else {"SKNP"}, &[x]),
+            ("-key", _) => self.instruction(if skip_when_true {"SKNP"} else {"SKP"}, &[x]),
+            (op @ ("==" | "!="), Some(y)) => {
+                let skip_when_equal = (op == "!=") != skip_when_true;
+                self.instruction(if skip_when_equal {"SE"} else {"SNE"}, &[x, y])
+            }
+            (op, Some(y)) => {
+                // compared in vf, a number goes in vf first and the comparison is turned around
+                let (a, b, op) = if self.register(y).is_some() {
+                    (x, y, op)
+                }else{
+                    self.instruction("LD", &["VF", y])?;
+                    let flipped = match op {
+                        "<" => ">",
+                        ">" => "<",
+                        "<=" => ">=",
+                        _ => "<="
+                    };
+                    ("VF", x, flipped)
+                };
+                if self.register(a) != Some(0xF) {
+                    self.instruction("LD", &["VF", a])?;
+                }
+                // vf ends up as the no borrow flag of a - b for < and >=, b - a for > and <=
+                self.instruction(if matches!(op, "<" | ">=") {"SUB"} else {"SUBN"}, &["VF", b])?;
+                // the flag is set when the condition is false for < and >, true for <= and >=
+                let flag_when_false = if matches!(op, "<" | ">") {"1"} else {"0"};
+                self.instruction(if skip_when_true {"SNE"} else {"SE"}, &["VF", flag_when_false])
+            }
+            _ => Err(self.invalid())
+        }
+    }
+
+    fn finish(mut self) -> Result<Vec<u8>, AssembleError> {
+        if let Some(block) = self.blocks.last() {
+            return Err(match block {
+                Block::If { line, .. } => AssembleError::UnbalancedBlock(*line, "begin".to_string()),
+                Block::Else { line, .. } => AssembleError::UnbalancedBlock(*line, "else".to_string()),
+                Block::Loop { line, .. } => AssembleError::UnbalancedBlock(*line, "loop".to_string())
+            });
+        }
+        let main = *self.labels.get("main").ok_or(AssembleError::MissingMain)?;
+        if self.main_jump == Some(true) {
+            self.patch(0, main as usize, 1)?;
+        }
+        for fixup in std::mem::take(&mut self.fixups) {
+            let address = *self.labels.get(fixup.label.as_str())
+                .ok_or(AssembleError::UnknownLabel(fixup.line, fixup.label))?;
+            if fixup.long {
+                self.program[fixup.at..fixup.at + 2].copy_from_slice(&address.to_be_bytes());
+            }else{
+                self.patch(fixup.at, address as usize, fixup.line)?;
+            }
+        }
+        // XO-CHIP can address all 64 KiB
+        if self.address() > 0x10000 {
+            return Err(AssembleError::ProgramTooLarge(self.program.len()));
+        }
+        Ok(self.program)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::{Chip8, Variant};
+    use crate::quirks::Quirks;
+
+    const BOUNCE: &str = include_str!("../examples/octo/bounce.8o");
+
+    fn lit(chip: &Chip8, x: usize, y: usize) -> bool {
+        chip.get_display()[y * chip.get_screen_size().0 + x]
+    }
+
+    #[test]
+    fn the_bounce_example_runs() {
+        let program = assemble_octo(BOUNCE).unwrap();
+        let mut chip = Chip8::default().with_quirks(Quirks { display_wait: false, ..Quirks::for_variant(Variant::Chip8) });
+        chip.load(&program).unwrap();
+        // enough instructions that every frame ends in the delay loop with the ball and paddle drawn
+        for _ in 0..20 {
+            chip.run_frame(1000).unwrap();
+        }
+        let [ball_x, ball_y, dx, dy, paddle_x] = [0, 1, 2, 3, 4].map(|v| chip.registers()[v] as usize);
+        assert_eq!((dx, dy), (1, 1));
+        assert!(ball_x > 10);
+        assert_eq!(ball_x - 10, ball_y - 5);
+        assert_eq!(paddle_x, 28);
+        let ball = [(0, 0), (1, 0), (0, 1), (1, 1)].iter().all(|(x, y)| lit(&chip, ball_x + x, ball_y + y));
+        assert!(ball);
+        assert!((28..36).all(|x| lit(&chip, x, 30)));
+        assert_eq!(chip.get_display().iter().filter(|pixel| **pixel).count(), 12);
+
+        chip.set_key_state(9, true);
+        for _ in 0..4 {
+            chip.run_frame(1000).unwrap();
+        }
+        assert!(chip.registers()[4] > 28);
+    }
+
+    #[test]
+    fn native_calls_reach_labels_further_down() {
+        let program = assemble_octo(": main native code\n: code return").unwrap();
+        assert_eq!(program, [0x02, 0x02, 0x00, 0xEE]);
+    }
+
+    #[test]
+    fn unsupported_statements_are_reported_with_their_line() {
+        let cases = [
+            (": main\n\t:macro twice X { X X }", AssembleError::Unsupported(2, ":macro".to_string())),
+            (": main\n  v0 := 1\n\n  :calc x { 2 * 3 }", AssembleError::Unsupported(4, ":calc".to_string())),
+            (": main\n  pitch := v0", AssembleError::Unsupported(2, "pitch".to_string())),
+            (":stringmode text \"abc\" { }\n: main", AssembleError::Unsupported(1, ":stringmode".to_string()))
+        ];
+        for (source, error) in cases {
+            assert_eq!(assemble_octo(source), Err(error), "{}", source);
+        }
+    }
+
+    #[test]
+    fn unbalanced_blocks_are_reported_with_their_line() {
+        let cases = [
+            (": main\n  end", AssembleError::UnbalancedBlock(2, "end".to_string())),
+            (": main\n  v0 := 1\n  else", AssembleError::UnbalancedBlock(3, "else".to_string())),
+            (": main\n  again", AssembleError::UnbalancedBlock(2, "again".to_string())),
+            (": main\n  while v0 != 0", AssembleError::UnbalancedBlock(2, "while".to_string())),
+            (": main\n  then", AssembleError::UnbalancedBlock(2, "then".to_string())),
+            // blocks left open point at where they started
+            (": main\n  if v0 == 1 begin\n    v1 := 2", AssembleError::UnbalancedBlock(2, "begin".to_string())),
+            (": main\n  if v0 == 1 begin\n  else\n    v1 := 2", AssembleError::UnbalancedBlock(3, "else".to_string())),
+            (": main\n  loop\n    v0 += 1", AssembleError::UnbalancedBlock(2, "loop".to_string()))
+        ];
+        for (source, error) in cases {
+            assert_eq!(assemble_octo(source), Err(error), "{}", source);
+        }
+    }
+}
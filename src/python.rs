@@ -0,0 +1,209 @@
+//! Python bindings, `pip install .` builds them with maturin (see pyproject.toml) and `examples/python/` uses them
+// pyo3 0.20's #[pymethods] expands to impls inside generated functions, which newer compilers warn about. The
+// expansion isn't covered by an allow on the impl itself, so it covers this module. Drop it when pyo3 is bumped.
+#![allow(non_local_definitions)]
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use crate::chip8::Chip8;
+use crate::save_state::SaveState;
+
+/// The emulator as seen from Python, named `Chip8` there
+#[pyclass(name = "Chip8")]
+pub struct PyChip8 {
+    chip: Chip8
+}
+
+#[pymethods]
+impl PyChip8 {
+    #[new]
+    fn new() -> Self {
+        PyChip8 { chip: Chip8::default() }
+    }
+
+    fn load_rom(&mut self, rom: &[u8]) -> PyResult<()> {
+        self.chip.load(rom).map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// Runs one instruction, the timers are left alone
+    fn tick(&mut self) -> PyResult<()> {
+        self.chip.step()
+            .map(|_| ())
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// Runs up to `cycles` instructions and counts the timers down once. Other Python threads run in the meantime.
+    fn run_frame(&mut self, py: Python<'_>, cycles: u32) -> PyResult<()> {
+        let chip = &mut self.chip;
+        py.allow_threads(move || chip.run_frame(cycles).map(|_| ()).map_err(|e| e.to_string()))
+            .map_err(PyRuntimeError::new_err)
+    }
+
+    /// `key` is the hex key, 0 to 15
+    fn key(&mut self, key: u8, pressed: bool) -> PyResult<()> {
+        if key > 0xf {
+            return Err(PyValueError::new_err(format!("Key must be between 0 and 15, not {}", key)));
+        }
+        self.chip.set_key_state(key, pressed);
+        Ok(())
+    }
+
+    /// Rows of pixels, true where any plane is lit
+    fn framebuffer(&self) -> Vec<Vec<bool>> {
+        framebuffer_rows(&self.chip)
+    }
+
+    /// One byte per pixel row by row, 0 off, 1 the first plane, 2 the second XO-CHIP plane and 3 both.
+    /// `numpy.frombuffer(chip.framebuffer_bytes(), numpy.uint8).reshape(chip.height, chip.width)` makes an array of it.
+    fn framebuffer_bytes<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, &framebuffer_bytes(&self.chip))
+    }
+
+    #[getter]
+    fn width(&self) -> usize {
+        self.chip.get_screen_size().0
+    }
+
+    #[getter]
+    fn height(&self) -> usize {
+        self.chip.get_screen_size().1
+    }
+
+    #[getter]
+    fn registers(&self) -> [u8; 16] {
+        *self.chip.registers()
+    }
+
+    #[getter]
+    fn pc(&self) -> u16 {
+        self.chip.pc()
+    }
+
+    #[getter]
+    fn i(&self) -> u16 {
+        self.chip.i()
+    }
+
+    #[getter]
+    fn delay_timer(&self) -> u8 {
+        self.chip.delay_timer()
+    }
+
+    #[getter]
+    fn sound_timer(&self) -> u8 {
+        self.chip.sound_timer()
+    }
+
+    /// The whole machine in the same format as the desktop build's save state files
+    fn save_state<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, &self.chip.save_state().to_bytes())
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> PyResult<()> {
+        SaveState::from_bytes(state)
+            .and_then(|state| self.chip.load_state(&state))
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+}
+
+fn framebuffer_rows(chip: &Chip8) -> Vec<Vec<bool>> {
+    let (width, _) = chip.get_screen_size();
+    (0..chip.get_display().len())
+        .map(|i| chip.pixel_color(i) != 0)
+        .collect::<Vec<bool>>()
+        .chunks(width)
+        .map(|row| row.to_vec())
+        .collect()
+}
+
+fn framebuffer_bytes(chip: &Chip8) -> Vec<u8> {
+    (0..chip.get_display().len())
+        .map(|i| chip.pixel_color(i) as u8)
+        .collect()
+}
+
+/// The `chip8_rs_dmfg` Python module
+#[pymodule]
+fn chip8_rs_dmfg(_py: Python<'_>, module: &PyModule) -> PyResult<()> {
+    module.add_class::<PyChip8>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Variant;
+
+    /// Switches to hires and draws the font's 0 in the top left corner
+    const HIRES_DIGIT: [u8; 8] = [
+        0x00, 0xFF, // HIGH
+        0xF0, 0x29, // LD F, V0
+        0xD0, 0x05, // DRW V0, V0, 5
+        0x12, 0x06  // JP 0x206
+    ];
+
+    /// `PyChip8::new` is plain CHIP-8, which ignores HIGH
+    fn superchip() -> PyChip8 {
+        let mut chip = PyChip8 { chip: Chip8::default().with_variant(Variant::SuperChip) };
+        chip.load_rom(&HIRES_DIGIT).unwrap();
+        chip
+    }
+
+    fn hires_digit() -> PyChip8 {
+        let mut chip = superchip();
+        chip.chip.run_frame(10).unwrap();
+        chip
+    }
+
+    #[test]
+    fn framebuffer_rows_and_bytes_agree() {
+        let chip = hires_digit();
+        let rows = chip.framebuffer();
+        assert_eq!(rows.len(), chip.height());
+        assert!(rows.iter().all(|row| row.len() == chip.width()));
+        // the top of a 0 is 0xF0
+        assert_eq!(rows[0][..8], [true, true, true, true, false, false, false, false]);
+        assert_eq!(rows.iter().flatten().filter(|lit| **lit).count(), 14);
+
+        let bytes = framebuffer_bytes(&chip.chip);
+        assert_eq!(bytes.len(), chip.width() * chip.height());
+        assert_eq!(bytes.iter().map(|pixel| *pixel != 0).collect::<Vec<bool>>(), rows.concat());
+        assert!(bytes.iter().all(|pixel| *pixel <= 1));
+    }
+
+    #[test]
+    fn framebuffer_follows_the_resolution() {
+        let mut chip = superchip();
+        assert_eq!((chip.width(), chip.height()), (64, 32));
+        assert_eq!(chip.framebuffer().concat(), vec![false; 64 * 32]);
+
+        chip.tick().unwrap();
+        assert_eq!((chip.width(), chip.height()), (128, 64));
+        let rows = chip.framebuffer();
+        assert_eq!(rows.len(), 64);
+        assert!(rows.iter().all(|row| row.len() == 128));
+        assert_eq!(framebuffer_bytes(&chip.chip).len(), 128 * 64);
+    }
+
+    #[test]
+    fn save_state_bytes_round_trip() {
+        let mut chip = hires_digit();
+        let state = chip.chip.save_state().to_bytes();
+        let before = chip.framebuffer();
+        // reloading the ROM goes back to a blank lores screen
+        chip.load_rom(&HIRES_DIGIT).unwrap();
+        assert_ne!(chip.framebuffer(), before);
+
+        chip.load_state(&state).unwrap();
+        assert_eq!(chip.framebuffer(), before);
+        assert_eq!((chip.width(), chip.height()), (128, 64));
+        assert_eq!(chip.chip.save_state().to_bytes(), state);
+    }
+
+    #[test]
+    fn bad_keys_and_states_are_rejected() {
+        let mut chip = PyChip8::new();
+        assert!(chip.key(0xf, true).is_ok());
+        assert!(chip.key(16, true).is_err());
+        assert!(chip.load_state(b"not a save state").is_err());
+    }
+}
@@ -0,0 +1,137 @@
+use core::fmt::{Display, Formatter};
+use core::str::FromStr;
+use alloc::format;
+use alloc::string::String;
+use crate::chip8::Variant;
+
+/// What DXYN does with the part of a sprite that runs past the edge of the display
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpriteEdge {
+    /// Leave it out, the start position still wraps around
+    Clip,
+    /// Draw it on the opposite side
+    Wrap
+}
+
+impl FromStr for SpriteEdge {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "clip" => Ok(SpriteEdge::Clip),
+            "wrap" => Ok(SpriteEdge::Wrap),
+            _ => Err(format!("Unknown sprite edge behaviour '{}', expected clip or wrap", s))
+        }
+    }
+}
+
+impl Display for SpriteEdge {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            SpriteEdge::Clip => f.write_str("clip"),
+            SpriteEdge::Wrap => f.write_str("wrap")
+        }
+    }
+}
+
+/// How far FX55 and FX65 move I after storing or loading V0 to VX
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexIncrement {
+    /// Past the last register, like the COSMAC VIP
+    XPlusOne,
+    /// Onto the last register, like CHIP-48
+    X,
+    /// Not at all, like SUPER-CHIP 1.1
+    None
+}
+
+impl FromStr for IndexIncrement {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "x+1" => Ok(IndexIncrement::XPlusOne),
+            "x" => Ok(IndexIncrement::X),
+            "none" => Ok(IndexIncrement::None),
+            _ => Err(format!("Unknown index increment '{}', expected x+1, x or none", s))
+        }
+    }
+}
+
+impl Display for IndexIncrement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            IndexIncrement::XPlusOne => f.write_str("x+1"),
+            IndexIncrement::X => f.write_str("x"),
+            IndexIncrement::None => f.write_str("none")
+        }
+    }
+}
+
+/// Behaviours that differ between CHIP-8 interpreters, ROMs written for one often misbehave on another
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// DXYN waits for the next 60 Hz frame once a sprite was drawn in the current one, like the COSMAC VIP
+    pub display_wait: bool,
+    pub load_store_increment: IndexIncrement,
+    /// FX1E sets VF to 1 when I goes past 0xFFF and to 0 otherwise, an undocumented behaviour of the Amiga interpreter
+    /// that Spacefight 2091! relies on
+    pub i_overflow_sets_vf: bool,
+    pub sprite_edges: SpriteEdge,
+    /// 8XY6 and 8XYE shift VX itself and ignore VY, like CHIP-48 and SUPER-CHIP, instead of shifting VY into VX
+    pub shift_in_place: bool,
+    /// BXNN jumps to XNN plus VX, like CHIP-48 and SUPER-CHIP, instead of BNNN jumping to NNN plus V0
+    pub jump_with_vx: bool
+}
+
+impl Quirks {
+    /// The original COSMAC VIP interpreter
+    pub const fn vip() -> Self {
+        Quirks {
+            display_wait: true,
+            load_store_increment: IndexIncrement::XPlusOne,
+            i_overflow_sets_vf: false,
+            sprite_edges: SpriteEdge::Clip,
+            shift_in_place: false,
+            jump_with_vx: false
+        }
+    }
+
+    /// SUPER-CHIP 1.1 on the HP48
+    pub const fn schip() -> Self {
+        Quirks {
+            display_wait: false,
+            load_store_increment: IndexIncrement::None,
+            i_overflow_sets_vf: false,
+            sprite_edges: SpriteEdge::Clip,
+            shift_in_place: true,
+            jump_with_vx: true
+        }
+    }
+
+    /// XO-CHIP as implemented by Octo
+    pub const fn xochip() -> Self {
+        Quirks {
+            display_wait: false,
+            load_store_increment: IndexIncrement::XPlusOne,
+            i_overflow_sets_vf: false,
+            sprite_edges: SpriteEdge::Wrap,
+            shift_in_place: false,
+            jump_with_vx: false
+        }
+    }
+
+    pub fn for_variant(variant: Variant) -> Self {
+        match variant {
+            Variant::Chip8 => Quirks::vip(),
+            Variant::SuperChip => Quirks::schip(),
+            Variant::XoChip => Quirks::xochip()
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::vip()
+    }
+}
@@ -0,0 +1,264 @@
+//! Input recordings for deterministic replay.
+//!
+//! A recording is a text file, one entry per line:
+//!
+//! ```text
+//! c8rec 1
+//! seed 0123456789abcdef
+//! rom fedcba9876543210
+//! 120 down 5
+//! 134 frame
+//! 160 up 5
+//! 181 up all
+//! 2400 end
+//! ```
+//!
+//! The header holds the format version, the RNG seed the session ran with and the `hash_program` of the ROM.
+//! Every other line starts with the `tick_count` the event happened at: a key going `down` or `up`, every key let go of
+//! at once with `up all`,
+//! a `frame` where the timers counted down, and a final `end` marking where the session stopped.
+//! Events apply before the instruction with that tick index executes and must be in tick order.
+
+#[cfg(feature = "std")]
+use std::error::Error;
+use core::fmt::{Display, Formatter};
+use core::str::FromStr;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use crate::chip8::Chip8;
+
+const HEADER: &str = "c8rec 1";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputEvent {
+    Press(u8),
+    Release(u8),
+    /// `Chip8::release_all_keys`, for when the window lost focus
+    ReleaseAll,
+    Frame
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recording {
+    pub seed: u64,
+    pub program_hash: u64,
+    pub events: Vec<(u64, InputEvent)>,
+    pub end_tick: u64
+}
+
+#[derive(Debug)]
+pub enum RecordingError {
+    BadHeader,
+    MalformedLine(usize, String),
+    OutOfOrder(usize),
+    RomMismatch
+}
+
+impl Display for RecordingError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            RecordingError::BadHeader => f.write_str(&format!("Not a recording, expected the first line to be '{}'", HEADER)),
+            RecordingError::MalformedLine(line, s) => f.write_str(&format!("Malformed recording entry '{}' on line {}", s, line)),
+            RecordingError::OutOfOrder(line) => f.write_str(&format!("Recording entry on line {} happens before the previous one", line)),
+            RecordingError::RomMismatch => f.write_str("Recording was made with a different ROM")
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for RecordingError {
+
+}
+
+impl Recording {
+    /// Starts an empty recording of the program currently loaded into `chip`, which must be running with `seed`
+    pub fn new(chip: &Chip8, seed: u64) -> Self {
+        Recording {
+            seed,
+            program_hash: chip.program_hash(),
+            events: Vec::new(),
+            end_tick: 0
+        }
+    }
+
+    pub fn record(&mut self, chip: &Chip8, event: InputEvent) {
+        self.events.push((chip.tick_count(), event));
+        self.end_tick = chip.tick_count();
+    }
+
+    pub fn finish(&mut self, chip: &Chip8) {
+        self.end_tick = chip.tick_count();
+    }
+}
+
+impl Display for Recording {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "seed {:016x}", self.seed)?;
+        writeln!(f, "rom {:016x}", self.program_hash)?;
+        for (tick, event) in &self.events {
+            match event {
+                InputEvent::Press(key) => writeln!(f, "{} down {:x}", tick, key)?,
+                InputEvent::Release(key) => writeln!(f, "{} up {:x}", tick, key)?,
+                InputEvent::ReleaseAll => writeln!(f, "{} up all", tick)?,
+                InputEvent::Frame => writeln!(f, "{} frame", tick)?
+            }
+        }
+        writeln!(f, "{} end", self.end_tick)
+    }
+}
+
+impl FromStr for Recording {
+    type Err = RecordingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines().enumerate().map(|(i, line)| (i + 1, line.trim())).filter(|(_, line)| !line.is_empty());
+        if lines.next().map(|(_, line)| line) != Some(HEADER) {
+            return Err(RecordingError::BadHeader);
+        }
+        let mut read_header = |name: &str| {
+            let (number, line) = lines.next().ok_or(RecordingError::BadHeader)?;
+            line.strip_prefix(name)
+                .and_then(|value| u64::from_str_radix(value.trim(), 16).ok())
+                .ok_or_else(|| RecordingError::MalformedLine(number, line.to_string()))
+        };
+        let seed = read_header("seed")?;
+        let program_hash = read_header("rom")?;
+        let mut events = Vec::new();
+        let mut end_tick = 0;
+        for (number, line) in lines {
+            let malformed = || RecordingError::MalformedLine(number, line.to_string());
+            let parts = line.split_whitespace().collect::<Vec<&str>>();
+            let tick = parts.first().and_then(|t| t.parse::<u64>().ok()).ok_or_else(malformed)?;
+            if tick < end_tick {
+                return Err(RecordingError::OutOfOrder(number));
+            }
+            end_tick = tick;
+            let key = || parts.get(2)
+                .and_then(|k| u8::from_str_radix(k, 16).ok())
+                .filter(|k| *k <= 0xF)
+                .ok_or_else(malformed);
+            match parts[1..] {
+                ["down", _] => events.push((tick, InputEvent::Press(key()?))),
+                ["up", "all"] => events.push((tick, InputEvent::ReleaseAll)),
+                ["up", _] => events.push((tick, InputEvent::Release(key()?))),
+                ["frame"] => events.push((tick, InputEvent::Frame)),
+                ["end"] => break,
+                _ => return Err(malformed())
+            }
+        }
+        Ok(Recording { seed, program_hash, events, end_tick })
+    }
+}
+
+/// Feeds the events of a recording back into a `Chip8` as it runs
+pub struct Replay {
+    recording: Recording,
+    position: usize
+}
+
+impl Replay {
+    /// Seeds `chip` with the recorded seed, the recorded ROM must already be loaded
+    pub fn new(recording: Recording, chip: &mut Chip8) -> Result<Self, RecordingError> {
+        if recording.program_hash != chip.program_hash() {
+            return Err(RecordingError::RomMismatch);
+        }
+        chip.set_rng_seed(recording.seed);
+        Ok(Replay { recording, position: 0 })
+    }
+
+    /// Applies every event due before the next tick of `chip`
+    pub fn apply(&mut self, chip: &mut Chip8) {
+        while let Some((tick, event)) = self.recording.events.get(self.position) {
+            if *tick > chip.tick_count() {
+                break;
+            }
+            match event {
+                InputEvent::Press(key) => chip.set_key_state(*key, true),
+                InputEvent::Release(key) => chip.set_key_state(*key, false),
+                InputEvent::ReleaseAll => chip.release_all_keys(),
+                InputEvent::Frame => chip.decrement_time()
+            }
+            self.position += 1;
+        }
+    }
+
+    pub fn is_finished(&self, chip: &Chip8) -> bool {
+        self.position >= self.recording.events.len() && chip.tick_count() >= self.recording.end_tick
+    }
+
+    pub fn end_tick(&self) -> u64 {
+        self.recording.end_tick
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Waits for a key and draws its digit at a random position, forever
+    const DRAW_PRESSED_KEY: [u8; 12] = [0xF0, 0x0A, 0xF0, 0x29, 0xC1, 0x3F, 0xC2, 0x1F, 0xD1, 0x25, 0x12, 0x00];
+
+    fn apply(chip: &mut Chip8, event: InputEvent) {
+        match event {
+            InputEvent::Press(key) => chip.set_key_state(key, true),
+            InputEvent::Release(key) => chip.set_key_state(key, false),
+            InputEvent::ReleaseAll => chip.release_all_keys(),
+            InputEvent::Frame => chip.decrement_time()
+        }
+    }
+
+    #[test]
+    fn replay_reaches_the_recorded_display() {
+        let script = [
+            (5, InputEvent::Press(3)),
+            (9, InputEvent::Release(3)),
+            (40, InputEvent::Press(0xA)),
+            (41, InputEvent::Press(7)),
+            (60, InputEvent::ReleaseAll),
+            (150, InputEvent::Press(0))
+        ];
+        let mut chip = Chip8::default().with_seed(99);
+        chip.load(&DRAW_PRESSED_KEY).unwrap();
+        let mut recording = Recording::new(&chip, 99);
+        for step in 0..200 {
+            let due = script.iter().filter(|(at, _)| *at == step).map(|(_, event)| *event);
+            for event in due.chain((step % 10 == 0).then_some(InputEvent::Frame)) {
+                recording.record(&chip, event);
+                apply(&mut chip, event);
+            }
+            chip.tick().unwrap();
+        }
+        recording.finish(&chip);
+
+        let mut replayed = Chip8::default();
+        replayed.load(&DRAW_PRESSED_KEY).unwrap();
+        let mut replay = Replay::new(recording.to_string().parse().unwrap(), &mut replayed).unwrap();
+        while !replay.is_finished(&replayed) {
+            replay.apply(&mut replayed);
+            replayed.tick().unwrap();
+        }
+        assert_eq!(replayed.tick_count(), chip.tick_count());
+        assert!(replayed.get_display().contains(&true));
+        assert_eq!(replayed.get_display(), chip.get_display());
+        assert_eq!(replayed.registers(), chip.registers());
+    }
+
+    #[test]
+    fn replay_refuses_another_rom() {
+        let mut chip = Chip8::default();
+        chip.load(&DRAW_PRESSED_KEY).unwrap();
+        let recording = Recording::new(&chip, 1);
+        chip.load(&[0x12, 0x00]).unwrap();
+        assert!(matches!(Replay::new(recording, &mut chip), Err(RecordingError::RomMismatch)));
+    }
+
+    #[test]
+    fn malformed_and_out_of_order_lines_are_rejected() {
+        let header = "c8rec 1\nseed 1\nrom 2\n";
+        assert!(matches!("c8rec 2\n".parse::<Recording>(), Err(RecordingError::BadHeader)));
+        assert!(matches!(format!("{}10 down 10\n", header).parse::<Recording>(), Err(RecordingError::MalformedLine(4, _))));
+        assert!(matches!(format!("{}10 down 1\n5 up 1\n", header).parse::<Recording>(), Err(RecordingError::OutOfOrder(5))));
+    }
+}
@@ -0,0 +1,171 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use crate::chip8::Chip8;
+use crate::save_state::SaveState;
+
+/// Serialized save states taken every `interval` frames, newest at the back
+pub struct RewindBuffer {
+    states: VecDeque<Vec<u8>>,
+    max_states: usize,
+    interval: usize,
+    // oldest states are dropped first once this is exceeded
+    max_bytes: usize,
+    bytes: usize,
+    // frames since a state was last pushed or popped
+    frames: usize
+}
+
+impl RewindBuffer {
+    /// Keeps up to `max_states` states within `max_bytes`, an `interval` of 0 counts as 1
+    pub fn new(max_states: usize, interval: usize, max_bytes: usize) -> Self {
+        RewindBuffer {
+            states: VecDeque::new(),
+            max_states,
+            interval: interval.max(1),
+            max_bytes,
+            bytes: 0,
+            frames: 0
+        }
+    }
+
+    /// Called once per frame, only every `interval`th frame is kept
+    pub fn push(&mut self, chip: &Chip8) {
+        if self.max_states == 0 {
+            return;
+        }
+        self.frames += 1;
+        if self.frames < self.interval {
+            return;
+        }
+        self.frames = 0;
+        let state = chip.save_state().to_bytes();
+        self.bytes += state.len();
+        self.states.push_back(state);
+        while self.states.len() > self.max_states || (self.bytes > self.max_bytes && self.states.len() > 1) {
+            if let Some(dropped) = self.states.pop_front() {
+                self.bytes -= dropped.len();
+            }
+        }
+    }
+
+    /// Called once per frame while rewinding, restores the most recent state every `interval` frames so rewinding
+    /// runs at the speed the game played. Returns whether the machine changed.
+    pub fn pop(&mut self, chip: &mut Chip8) -> bool {
+        self.frames += 1;
+        if self.frames < self.interval {
+            return false;
+        }
+        self.frames = 0;
+        match self.states.pop_back() {
+            Some(bytes) => {
+                self.bytes -= bytes.len();
+                // states were taken from this machine so they always deserialize and match it
+                SaveState::from_bytes(&bytes)
+                    .and_then(|state| chip.load_state(&state))
+                    .is_ok()
+            }
+            None => false
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.states.clear();
+        self.bytes = 0;
+        self.frames = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ADD V0, 1; JP 0x200
+    const COUNTER: [u8; 4] = [0x70, 0x01, 0x12, 0x00];
+
+    fn counter() -> Chip8 {
+        let mut chip = Chip8::default();
+        chip.load(&COUNTER).unwrap();
+        chip
+    }
+
+    /// Pushes `frames` frames, adding one to V0 before each
+    fn play(rewind: &mut RewindBuffer, chip: &mut Chip8, frames: usize) {
+        for _ in 0..frames {
+            chip.tick().unwrap();
+            chip.tick().unwrap();
+            rewind.push(chip);
+        }
+    }
+
+    /// V0 after each frame of rewinding until the buffer runs out
+    fn rewind_all(rewind: &mut RewindBuffer, chip: &mut Chip8) -> Vec<u8> {
+        let mut counts = Vec::new();
+        while rewind.pop(chip) {
+            counts.push(chip.registers()[0]);
+        }
+        counts
+    }
+
+    #[test]
+    fn rewinding_restores_the_newest_states_first() {
+        let mut chip = counter();
+        let mut rewind = RewindBuffer::new(10, 1, usize::MAX);
+        play(&mut rewind, &mut chip, 4);
+        assert_eq!(rewind_all(&mut rewind, &mut chip), [4, 3, 2, 1]);
+        assert!(!rewind.pop(&mut chip));
+        assert_eq!(rewind.bytes, 0);
+    }
+
+    #[test]
+    fn the_oldest_states_are_evicted_past_the_capacity() {
+        let mut chip = counter();
+        let mut rewind = RewindBuffer::new(3, 1, usize::MAX);
+        play(&mut rewind, &mut chip, 5);
+        assert_eq!(rewind.states.len(), 3);
+        assert_eq!(rewind_all(&mut rewind, &mut chip), [5, 4, 3]);
+    }
+
+    #[test]
+    fn the_oldest_states_are_evicted_past_the_memory_budget() {
+        let mut chip = counter();
+        let state_size = chip.save_state().to_bytes().len();
+        let mut rewind = RewindBuffer::new(10, 1, state_size * 2 + 1);
+        play(&mut rewind, &mut chip, 5);
+        assert_eq!((rewind.states.len(), rewind.bytes), (2, state_size * 2));
+        assert_eq!(rewind_all(&mut rewind, &mut chip), [5, 4]);
+        // the newest state is kept however small the budget
+        let mut rewind = RewindBuffer::new(10, 1, 0);
+        play(&mut rewind, &mut chip, 3);
+        assert_eq!(rewind_all(&mut rewind, &mut chip), [7]);
+    }
+
+    #[test]
+    fn states_are_kept_and_restored_every_interval_frames() {
+        let mut chip = counter();
+        let mut rewind = RewindBuffer::new(10, 3, usize::MAX);
+        play(&mut rewind, &mut chip, 6);
+        assert_eq!(rewind.states.len(), 2);
+        let restored: Vec<bool> = (0..7).map(|_| rewind.pop(&mut chip)).collect();
+        assert_eq!(restored, [false, false, true, false, false, true, false]);
+        assert_eq!(chip.registers()[0], 3);
+    }
+
+    #[test]
+    fn a_zero_capacity_keeps_nothing() {
+        let mut chip = counter();
+        let mut rewind = RewindBuffer::new(0, 1, usize::MAX);
+        play(&mut rewind, &mut chip, 3);
+        assert!(rewind.states.is_empty());
+        assert!(!rewind.pop(&mut chip));
+    }
+
+    #[test]
+    fn clearing_drops_every_state() {
+        let mut chip = counter();
+        let mut rewind = RewindBuffer::new(10, 1, usize::MAX);
+        play(&mut rewind, &mut chip, 3);
+        rewind.clear();
+        assert_eq!((rewind.states.len(), rewind.bytes), (0, 0));
+        assert!(!rewind.pop(&mut chip));
+    }
+}
@@ -0,0 +1,51 @@
+#[cfg(feature = "std")]
+use rand::{Rng, thread_rng};
+
+/// Small xorshift64* generator. Its whole state is a single `u64`, so it can be seeded for
+/// reproducible runs and stored in save states.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XorShiftRng {
+    state: u64
+}
+
+impl XorShiftRng {
+    pub fn new(seed: u64) -> Self {
+        // splitmix64 so that similar seeds give unrelated sequences, xorshift can't start from 0
+        let mut z = seed.wrapping_add(0x9e3779b97f4a7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^= z >> 31;
+        XorShiftRng::from_state(z)
+    }
+
+    #[cfg(feature = "std")]
+    pub fn from_entropy() -> Self {
+        XorShiftRng::new(thread_rng().gen())
+    }
+
+    pub(crate) fn from_state(state: u64) -> Self {
+        XorShiftRng { state: if state == 0 { 0x9e3779b97f4a7c15 } else { state } }
+    }
+
+    pub(crate) fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn next_u8(&mut self) -> u8 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545f4914f6cdd1d) >> 56) as u8
+    }
+}
+
+/// Seeded from the system's entropy with `std`. Without it there is no entropy source to ask and every machine starts
+/// from the same fixed seed, so embedded builds should seed it themselves with `Chip8::with_seed` or `set_random_source`.
+impl Default for XorShiftRng {
+    fn default() -> Self {
+        #[cfg(feature = "std")]
+        return XorShiftRng::from_entropy();
+        #[cfg(not(feature = "std"))]
+        return XorShiftRng::new(0);
+    }
+}
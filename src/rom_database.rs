@@ -0,0 +1,104 @@
+use crate::analysis::analyze;
+use crate::chip8::Variant;
+use crate::chip8_instruction_set::Address;
+use crate::quirks::Quirks;
+use crate::save_state::hash_program;
+
+/// What is known about a ROM, used to pick settings without command line flags
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RomInfo {
+    pub title: &'static str,
+    pub variant: Variant,
+    pub quirks: Quirks
+}
+
+/// Known ROMs keyed by their `hash_program`, which needs the ROM itself to compute
+const KNOWN_ROMS: [(u64, RomInfo); 6] = [
+    (0x64e45391ba0238a1, RomInfo { title: "IBM Logo", variant: Variant::Chip8, quirks: Quirks::vip() }),
+    (0x25e96e1086ce43cb, RomInfo { title: "Maze (David Winter)", variant: Variant::Chip8, quirks: Quirks::vip() }),
+    (0xb45b7f671fd4e77b, RomInfo { title: "Opcode test (corax89)", variant: Variant::Chip8, quirks: Quirks::vip() }),
+    (0x3bc2d971028b324c, RomInfo { title: "Floppy Bird", variant: Variant::Chip8, quirks: Quirks::vip() }),
+    (0xdb964ec407cc549c, RomInfo { title: "Hello World", variant: Variant::Chip8, quirks: Quirks::vip() }),
+    (0xaed6bcd1bf3b7ee1, RomInfo { title: "Second Counter", variant: Variant::Chip8, quirks: Quirks::vip() })
+];
+
+pub fn lookup(program: &[u8]) -> Option<&'static RomInfo> {
+    let hash = hash_program(program);
+    KNOWN_ROMS.iter().find(|(known, _)| *known == hash).map(|(_, info)| info)
+}
+
+/// Guesses whether a program loaded at `origin` was written for SUPER-CHIP by looking for its instructions.
+/// Only the code `analyze` can reach is looked at, sprites and other data often decode as SUPER-CHIP scrolls.
+pub fn uses_schip_instructions(program: &[u8], origin: Address) -> bool {
+    analyze(program, origin).instructions().any(|(_, instruction)| instruction.is_schip())
+}
+
+/// Like `uses_schip_instructions` but for XO-CHIP, a program too large for 4 KiB of memory also counts
+pub fn uses_xochip_instructions(program: &[u8], origin: Address) -> bool {
+    program.len() > 0x1000usize.saturating_sub(origin as usize) ||
+        analyze(program, origin).instructions().any(|(_, instruction)| instruction.is_xochip())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::disassemble;
+
+    const BUNDLED_CHIP8_ROMS: [&[u8]; 6] = [
+        include_bytes!("../roms/IBM Logo.ch8"),
+        include_bytes!("../roms/Maze [David Winter, 199x].ch8"),
+        include_bytes!("../roms/test_opcode.ch8"),
+        include_bytes!("../roms/floppybird.rom"),
+        include_bytes!("../roms/helloworld.rom"),
+        include_bytes!("../roms/secondCounter.rom")
+    ];
+
+    #[test]
+    fn bundled_roms_are_found_by_their_hash() {
+        let titles: Vec<&str> = BUNDLED_CHIP8_ROMS.iter().map(|rom| lookup(rom).map_or("", |info| info.title)).collect();
+        assert_eq!(titles, ["IBM Logo", "Maze (David Winter)", "Opcode test (corax89)", "Floppy Bird", "Hello World", "Second Counter"]);
+        assert!(BUNDLED_CHIP8_ROMS.iter().all(|rom| lookup(rom).unwrap().variant == Variant::Chip8));
+    }
+
+    #[test]
+    fn a_changed_byte_is_another_rom() {
+        let mut ibm_logo = include_bytes!("../roms/IBM Logo.ch8").to_vec();
+        ibm_logo[0x20] ^= 1;
+        assert_eq!(lookup(&ibm_logo), None);
+        assert_eq!(lookup(&[]), None);
+    }
+
+    #[test]
+    fn bundled_chip8_roms_arent_taken_for_another_variant() {
+        for (i, rom) in BUNDLED_CHIP8_ROMS.iter().enumerate() {
+            assert!(!uses_xochip_instructions(rom, 0x200), "ROM {} looks like XO-CHIP", i);
+            assert!(!uses_schip_instructions(rom, 0x200), "ROM {} looks like SUPER-CHIP", i);
+        }
+        // floppybird.rom has 5XY2 words among its sprites, which used to pass for XO-CHIP
+        assert!(disassemble(BUNDLED_CHIP8_ROMS[3], 0x200).iter().any(|word| word.instruction.is_some_and(|instruction| instruction.is_xochip())));
+    }
+
+    #[test]
+    fn data_in_chip8_roms_isnt_taken_for_schip_instructions() {
+        let ibm_logo = include_bytes!("../roms/IBM Logo.ch8");
+        // the logo sprites hold 00FF and 00FE words, the whole file scanned would look like SUPER-CHIP
+        assert!(disassemble(ibm_logo, 0x200).iter().any(|word| word.instruction.is_some_and(|instruction| instruction.is_schip())));
+        assert!(!uses_schip_instructions(ibm_logo, 0x200));
+    }
+
+    #[test]
+    fn reachable_schip_instructions_are_found() {
+        // HIGH; CLS; JP 0x204
+        assert!(uses_schip_instructions(&[0x00, 0xFF, 0x00, 0xE0, 0x12, 0x04], 0x200));
+        // JP 0x204; HIGH; CLS; JP 0x206
+        assert!(!uses_schip_instructions(&[0x12, 0x04, 0x00, 0xFF, 0x00, 0xE0, 0x12, 0x06], 0x200));
+    }
+
+    #[test]
+    fn reachable_xochip_instructions_and_large_programs_are_found() {
+        // PLANE 3; JP 0x202
+        assert!(uses_xochip_instructions(&[0xF3, 0x01, 0x12, 0x02], 0x200));
+        assert!(uses_xochip_instructions(&[0x12, 0x00].repeat(0x701), 0x200));
+        assert!(!uses_xochip_instructions(&[0x12, 0x00].repeat(0x700), 0x200));
+    }
+}
@@ -0,0 +1,241 @@
+#[cfg(feature = "std")]
+use std::error::Error;
+use core::fmt::{Display, Formatter};
+use alloc::format;
+use alloc::vec::Vec;
+use crate::chip8_instruction_set::Address;
+use crate::quirks::{IndexIncrement, Quirks, SpriteEdge};
+
+const MAGIC: &[u8; 4] = b"C8ST";
+const VERSION: u8 = 5;
+
+/// Snapshot of everything needed to resume a `Chip8` exactly where it was.
+///
+/// Serialized layout (all integers little endian):
+/// magic `C8ST`, version byte, program hash (u64), memory (u32 length + bytes),
+/// stack (u32 length + u16 entries), PC (u16), I (u16), V0-VF, 16 key bytes,
+/// delay timer, sound timer, display (u32 length + one byte per pixel), second XO-CHIP plane (same as display),
+/// selected planes, 16 audio pattern bytes, high resolution flag, exited flag, 16 RPL flag bytes, RNG state (u64),
+/// tick count (u64), quirks (display wait, FX55/FX65 increment as 0 x+1 / 1 x / 2 none, I overflow sets VF,
+/// sprite edges as 0 clip / 1 wrap, shift in place, jump with VX)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SaveState {
+    pub(crate) program_hash: u64,
+    pub(crate) memory: Vec<u8>,
+    pub(crate) stack_memory: Vec<Address>,
+    pub(crate) instruction_pointer: Address,
+    pub(crate) address_register: Address,
+    pub(crate) registers: [u8; 16],
+    pub(crate) keys: [bool; 16],
+    pub(crate) delay_timer: u8,
+    pub(crate) sound_timer: u8,
+    pub(crate) display: Vec<bool>,
+    pub(crate) second_plane: Vec<bool>,
+    pub(crate) planes: u8,
+    pub(crate) audio_pattern: [u8; 16],
+    pub(crate) hires: bool,
+    pub(crate) exited: bool,
+    pub(crate) flags: [u8; 16],
+    pub(crate) rng_state: u64,
+    pub(crate) tick_count: u64,
+    pub(crate) quirks: Quirks
+}
+
+#[derive(Debug)]
+pub enum SaveStateError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    Truncated,
+    MemorySizeMismatch { expected: usize, found: usize },
+    DisplaySizeMismatch { expected: usize, found: usize },
+    StackTooDeep { max: usize, found: usize },
+    InvalidQuirks,
+    RomMismatch
+}
+
+impl Display for SaveStateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            SaveStateError::BadMagic => f.write_str("Not a save state file"),
+            SaveStateError::UnsupportedVersion(v) => f.write_str(&format!("Unsupported save state version {}, expected {}", v, VERSION)),
+            SaveStateError::Truncated => f.write_str("Save state file is truncated"),
+            SaveStateError::MemorySizeMismatch { expected, found } => f.write_str(&format!("Save state has {} bytes of memory but the emulator has {}", found, expected)),
+            SaveStateError::DisplaySizeMismatch { expected, found } => f.write_str(&format!("Save state has {} display pixels but the emulator has {}", found, expected)),
+            SaveStateError::StackTooDeep { max, found } => f.write_str(&format!("Save state has {} return addresses on the stack but the emulator's stack holds {}", found, max)),
+            SaveStateError::InvalidQuirks => f.write_str("Save state has quirk settings this emulator doesn't know"),
+            SaveStateError::RomMismatch => f.write_str("Save state was created with a different ROM")
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for SaveStateError {
+
+}
+
+impl SaveState {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.memory.len() + self.display.len() * 2 + 128);
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&self.program_hash.to_le_bytes());
+        bytes.extend_from_slice(&(self.memory.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.memory);
+        bytes.extend_from_slice(&(self.stack_memory.len() as u32).to_le_bytes());
+        self.stack_memory.iter().for_each(|address| bytes.extend_from_slice(&address.to_le_bytes()));
+        bytes.extend_from_slice(&self.instruction_pointer.to_le_bytes());
+        bytes.extend_from_slice(&self.address_register.to_le_bytes());
+        bytes.extend_from_slice(&self.registers);
+        bytes.extend(self.keys.iter().map(|pressed| *pressed as u8));
+        bytes.push(self.delay_timer);
+        bytes.push(self.sound_timer);
+        bytes.extend_from_slice(&(self.display.len() as u32).to_le_bytes());
+        bytes.extend(self.display.iter().map(|lit| *lit as u8));
+        bytes.extend_from_slice(&(self.second_plane.len() as u32).to_le_bytes());
+        bytes.extend(self.second_plane.iter().map(|lit| *lit as u8));
+        bytes.push(self.planes);
+        bytes.extend_from_slice(&self.audio_pattern);
+        bytes.push(self.hires as u8);
+        bytes.push(self.exited as u8);
+        bytes.extend_from_slice(&self.flags);
+        bytes.extend_from_slice(&self.rng_state.to_le_bytes());
+        bytes.extend_from_slice(&self.tick_count.to_le_bytes());
+        let increment = match self.quirks.load_store_increment {
+            IndexIncrement::XPlusOne => 0,
+            IndexIncrement::X => 1,
+            IndexIncrement::None => 2
+        };
+        let edges = match self.quirks.sprite_edges {
+            SpriteEdge::Clip => 0,
+            SpriteEdge::Wrap => 1
+        };
+        bytes.extend_from_slice(&[
+            self.quirks.display_wait as u8,
+            increment,
+            self.quirks.i_overflow_sets_vf as u8,
+            edges,
+            self.quirks.shift_in_place as u8,
+            self.quirks.jump_with_vx as u8
+        ]);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<SaveState, SaveStateError> {
+        let mut reader = StateReader { bytes, position: 0 };
+        if reader.read_bytes(MAGIC.len())? != MAGIC {
+            return Err(SaveStateError::BadMagic);
+        }
+        let version = reader.read_u8()?;
+        if version != VERSION {
+            return Err(SaveStateError::UnsupportedVersion(version));
+        }
+        let program_hash = reader.read_u64()?;
+        let memory_len = reader.read_u32()? as usize;
+        let memory = reader.read_bytes(memory_len)?.to_vec();
+        let stack_len = reader.read_u32()? as usize;
+        let stack_memory = (0..stack_len).map(|_| reader.read_u16()).collect::<Result<Vec<Address>, SaveStateError>>()?;
+        let instruction_pointer = reader.read_u16()?;
+        let address_register = reader.read_u16()?;
+        let mut registers = [0; 16];
+        registers.copy_from_slice(reader.read_bytes(16)?);
+        let mut keys = [false; 16];
+        reader.read_bytes(16)?.iter().enumerate().for_each(|(i, pressed)| keys[i] = *pressed != 0);
+        let delay_timer = reader.read_u8()?;
+        let sound_timer = reader.read_u8()?;
+        let display_len = reader.read_u32()? as usize;
+        let display = reader.read_bytes(display_len)?.iter().map(|lit| *lit != 0).collect();
+        let second_plane_len = reader.read_u32()? as usize;
+        let second_plane = reader.read_bytes(second_plane_len)?.iter().map(|lit| *lit != 0).collect();
+        let planes = reader.read_u8()?;
+        let mut audio_pattern = [0; 16];
+        audio_pattern.copy_from_slice(reader.read_bytes(16)?);
+        let hires = reader.read_u8()? != 0;
+        let exited = reader.read_u8()? != 0;
+        let mut flags = [0; 16];
+        flags.copy_from_slice(reader.read_bytes(16)?);
+        let rng_state = reader.read_u64()?;
+        let tick_count = reader.read_u64()?;
+        let quirks = reader.read_bytes(6)?;
+        let load_store_increment = match quirks[1] {
+            0 => IndexIncrement::XPlusOne,
+            1 => IndexIncrement::X,
+            2 => IndexIncrement::None,
+            _ => return Err(SaveStateError::InvalidQuirks)
+        };
+        let sprite_edges = match quirks[3] {
+            0 => SpriteEdge::Clip,
+            1 => SpriteEdge::Wrap,
+            _ => return Err(SaveStateError::InvalidQuirks)
+        };
+        let quirks = Quirks {
+            display_wait: quirks[0] != 0,
+            load_store_increment,
+            i_overflow_sets_vf: quirks[2] != 0,
+            sprite_edges,
+            shift_in_place: quirks[4] != 0,
+            jump_with_vx: quirks[5] != 0
+        };
+        Ok(SaveState {
+            program_hash,
+            memory,
+            stack_memory,
+            instruction_pointer,
+            address_register,
+            registers,
+            keys,
+            delay_timer,
+            sound_timer,
+            display,
+            second_plane,
+            planes,
+            audio_pattern,
+            hires,
+            exited,
+            flags,
+            rng_state,
+            tick_count,
+            quirks
+        })
+    }
+}
+
+struct StateReader<'a> {
+    bytes: &'a [u8],
+    position: usize
+}
+
+impl<'a> StateReader<'a> {
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], SaveStateError> {
+        let end = self.position.checked_add(len).ok_or(SaveStateError::Truncated)?;
+        let slice = self.bytes.get(self.position..end).ok_or(SaveStateError::Truncated)?;
+        self.position = end;
+        Ok(slice)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u16(&mut self) -> Result<u16, SaveStateError> {
+        let mut buf = [0; 2];
+        buf.copy_from_slice(self.read_bytes(2)?);
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, SaveStateError> {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(self.read_bytes(4)?);
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, SaveStateError> {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(self.read_bytes(8)?);
+        Ok(u64::from_le_bytes(buf))
+    }
+}
+
+/// FNV-1a hash of a program, stable across builds so it can be stored in files
+pub fn hash_program(program: &[u8]) -> u64 {
+    program.iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
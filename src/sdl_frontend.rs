@@ -0,0 +1,175 @@
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use minifb::Key;
+use raqote::Color;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::event::Event;
+use sdl2::keyboard::Scancode;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::WindowCanvas;
+use sdl2::{EventPump, Sdl};
+use chip8_rs_dmfg::chip8::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use chip8_rs_dmfg::frontend::{AudioSink, InputSource, KeyEvent, Renderer, Screen};
+use crate::audio::{BeepGate, SquareWave, SAMPLE_RATE};
+use crate::keymap::Keymap;
+
+/// SDL scancodes for every key a keymap can name, keymaps are written in terms of minifb keys
+const SCANCODES: [(Scancode, Key); 60] = [
+    (Scancode::Num0, Key::Key0), (Scancode::Num1, Key::Key1), (Scancode::Num2, Key::Key2), (Scancode::Num3, Key::Key3),
+    (Scancode::Num4, Key::Key4), (Scancode::Num5, Key::Key5), (Scancode::Num6, Key::Key6), (Scancode::Num7, Key::Key7),
+    (Scancode::Num8, Key::Key8), (Scancode::Num9, Key::Key9),
+    (Scancode::A, Key::A), (Scancode::B, Key::B), (Scancode::C, Key::C), (Scancode::D, Key::D), (Scancode::E, Key::E),
+    (Scancode::F, Key::F), (Scancode::G, Key::G), (Scancode::H, Key::H), (Scancode::I, Key::I), (Scancode::J, Key::J),
+    (Scancode::K, Key::K), (Scancode::L, Key::L), (Scancode::M, Key::M), (Scancode::N, Key::N), (Scancode::O, Key::O),
+    (Scancode::P, Key::P), (Scancode::Q, Key::Q), (Scancode::R, Key::R), (Scancode::S, Key::S), (Scancode::T, Key::T),
+    (Scancode::U, Key::U), (Scancode::V, Key::V), (Scancode::W, Key::W), (Scancode::X, Key::X), (Scancode::Y, Key::Y),
+    (Scancode::Z, Key::Z),
+    (Scancode::Kp0, Key::NumPad0), (Scancode::Kp1, Key::NumPad1), (Scancode::Kp2, Key::NumPad2), (Scancode::Kp3, Key::NumPad3),
+    (Scancode::Kp4, Key::NumPad4), (Scancode::Kp5, Key::NumPad5), (Scancode::Kp6, Key::NumPad6), (Scancode::Kp7, Key::NumPad7),
+    (Scancode::Kp8, Key::NumPad8), (Scancode::Kp9, Key::NumPad9),
+    (Scancode::Up, Key::Up), (Scancode::Down, Key::Down), (Scancode::Left, Key::Left), (Scancode::Right, Key::Right),
+    (Scancode::Space, Key::Space), (Scancode::Return, Key::Enter), (Scancode::Tab, Key::Tab), (Scancode::Comma, Key::Comma),
+    (Scancode::Period, Key::Period), (Scancode::Slash, Key::Slash), (Scancode::Semicolon, Key::Semicolon),
+    (Scancode::Minus, Key::Minus), (Scancode::Equals, Key::Equal), (Scancode::Apostrophe, Key::Apostrophe)
+];
+
+/// An SDL window, the display is drawn at its real resolution and SDL scales it up to the window
+pub struct SdlFrontend {
+    sdl: Sdl,
+    canvas: WindowCanvas,
+    events: EventPump,
+    palette: [Color; 4],
+    keymap: Keymap
+}
+
+impl SdlFrontend {
+    pub fn new(title: &str, scale: usize, palette: [Color; 4], keymap: Keymap) -> Result<Self, Box<dyn Error>> {
+        let sdl = sdl2::init()?;
+        let window = sdl.video()?
+            .window(title, (DISPLAY_WIDTH * scale) as u32, (DISPLAY_HEIGHT * scale) as u32)
+            .position_centered()
+            .build()?;
+        Ok(SdlFrontend {
+            canvas: window.into_canvas().build()?,
+            events: sdl.event_pump()?,
+            sdl,
+            palette,
+            keymap
+        })
+    }
+
+    /// Sound through the window's SDL context, SDL only allows one. Fails without an audio device, the window still works then.
+    pub fn beeper(&self, min_beep_frames: u32) -> Result<SdlBeeper, String> {
+        let active = Arc::new(AtomicBool::new(false));
+        let spec = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE as i32),
+            channels: Some(1),
+            samples: None
+        };
+        let device = self.sdl.audio()?.open_playback(None, &spec, |_| SquareWaveCallback(SquareWave::new(active.clone())))?;
+        device.resume();
+        Ok(SdlBeeper {
+            _device: device,
+            active,
+            gate: BeepGate::new(min_beep_frames)
+        })
+    }
+
+    fn hex_key(&self, scancode: Option<Scancode>) -> Option<u8> {
+        let scancode = scancode?;
+        SCANCODES.iter()
+            .find(|(code, _)| *code == scancode)
+            .and_then(|(_, key)| self.keymap.hex_key(key))
+    }
+}
+
+impl Renderer for SdlFrontend {
+    /// The whole display goes to the texture each time, it's only a few kilobytes
+    fn present(&mut self, display: &dyn Screen, _dirty_rows: u64) {
+        let (width, height) = display.get_screen_size();
+        let pixels = (0..width * height)
+            .flat_map(|i| {
+                let color = self.palette[display.pixel_color(i)];
+                [color.r(), color.g(), color.b()]
+            })
+            .collect::<Vec<u8>>();
+        // created each time since SUPER-CHIP programs can change the resolution
+        let creator = self.canvas.texture_creator();
+        let drawn = creator.create_texture_streaming(PixelFormatEnum::RGB24, width as u32, height as u32)
+            .map_err(|e| e.to_string())
+            .and_then(|mut texture| {
+                texture.update(None, &pixels, width * 3).map_err(|e| e.to_string())?;
+                self.canvas.copy(&texture, None, None)
+            });
+        match drawn {
+            Ok(_) => self.canvas.present(),
+            Err(e) => eprintln!("Failed to draw the display: {}", e)
+        }
+    }
+}
+
+impl InputSource for SdlFrontend {
+    fn poll(&mut self) -> Vec<KeyEvent> {
+        let events = self.events.poll_iter().collect::<Vec<Event>>();
+        events.into_iter()
+            .filter_map(|event| match event {
+                Event::Quit { .. } => Some(KeyEvent::Quit),
+                // held keys repeat, the keypad only cares about the first press
+                Event::KeyDown { scancode, repeat: false, .. } => self.hex_key(scancode).map(KeyEvent::Pressed),
+                Event::KeyUp { scancode, .. } => self.hex_key(scancode).map(KeyEvent::Released),
+                _ => None
+            })
+            .collect()
+    }
+}
+
+/// Plays the same square wave as `Beeper` through SDL audio
+pub struct SdlBeeper {
+    // closing the device stops the callback
+    _device: AudioDevice<SquareWaveCallback>,
+    active: Arc<AtomicBool>,
+    gate: BeepGate
+}
+
+impl AudioSink for SdlBeeper {
+    fn set_beep(&mut self, on: bool) {
+        let open = self.gate.frame(on as u8);
+        self.active.store(open, Ordering::Relaxed);
+    }
+}
+
+struct SquareWaveCallback(SquareWave);
+
+impl AudioCallback for SquareWaveCallback {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        out.iter_mut().for_each(|sample| *sample = self.0.next().unwrap_or(0.0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8_rs_dmfg::chip8::Chip8;
+
+    #[test]
+    fn runs_on_the_dummy_drivers() {
+        // headless machines have neither a display nor a sound card
+        std::env::set_var("SDL_VIDEODRIVER", "dummy");
+        std::env::set_var("SDL_AUDIODRIVER", "dummy");
+        let palette = [Color::new(255, 0, 0, 0), Color::new(255, 255, 255, 255), Color::new(255, 255, 0, 0), Color::new(255, 0, 0, 255)];
+        let mut frontend = SdlFrontend::new("test", 1, palette, Keymap::default()).unwrap();
+        let mut chip = Chip8::default();
+        // LD F, V0; DRW V0, V0, 5
+        chip.load(&[0xF0, 0x29, 0xD0, 0x05]).unwrap();
+        chip.run_frame(2).unwrap();
+        frontend.present(&chip, u64::MAX);
+        assert!(frontend.poll().is_empty());
+        if let Ok(mut beeper) = frontend.beeper(0) {
+            beeper.set_beep(true);
+            beeper.set_beep(false);
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, Instant};
+
+/// How far back instructions and frames are counted
+const WINDOW: Duration = Duration::from_secs(1);
+
+/// What the emulator is doing, shown in the status line and window title
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunState {
+    Running,
+    WaitingForKey,
+    Paused,
+    Exited,
+    /// Stopped with the PC on the instruction at this address
+    Halted(u16),
+    Unfocused,
+    Rewinding
+}
+
+impl Display for RunState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RunState::Running => f.write_str("running"),
+            RunState::WaitingForKey => f.write_str("waiting for key"),
+            RunState::Paused => f.write_str("paused"),
+            RunState::Exited => f.write_str("exited"),
+            RunState::Halted(address) => f.write_str(&format!("halted at 0x{:03X}", address)),
+            RunState::Unfocused => f.write_str("paused, window unfocused"),
+            RunState::Rewinding => f.write_str("rewinding")
+        }
+    }
+}
+
+/// Instructions and display updates per second, measured over a sliding window
+pub struct Stats {
+    // (time, Chip8::tick_count) samples, oldest at the front
+    ticks: VecDeque<(Instant, u64)>,
+    frames: VecDeque<Instant>
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Stats {
+            ticks: VecDeque::new(),
+            frames: VecDeque::new()
+        }
+    }
+
+    pub fn sample_ticks(&mut self, tick_count: u64) {
+        let now = Instant::now();
+        self.ticks.push_back((now, tick_count));
+        // the oldest sample in the window is kept as the starting point of the measurement
+        while self.ticks.len() > 2 && now.duration_since(self.ticks[1].0) >= WINDOW {
+            self.ticks.pop_front();
+        }
+    }
+
+    pub fn frame_drawn(&mut self) {
+        let now = Instant::now();
+        self.frames.push_back(now);
+        while self.frames.front().is_some_and(|drawn| now.duration_since(*drawn) > WINDOW) {
+            self.frames.pop_front();
+        }
+    }
+
+    /// Forgets all samples, for when the tick count starts over after a reset or reload
+    pub fn clear(&mut self) {
+        self.ticks.clear();
+        self.frames.clear();
+    }
+
+    pub fn instructions_per_second(&self) -> u64 {
+        match (self.ticks.front(), self.ticks.back()) {
+            (Some((start, first)), Some((end, last))) if end > start => {
+                (last.saturating_sub(*first) as f64 / end.duration_since(*start).as_secs_f64()) as u64
+            }
+            _ => 0
+        }
+    }
+
+    pub fn frames_per_second(&self) -> usize {
+        let now = Instant::now();
+        self.frames.iter().filter(|drawn| now.duration_since(**drawn) <= WINDOW).count()
+    }
+}
+
+/// Status line for the terminal, the spinner truncates it to the terminal width
+pub fn format_status(rom_name: &str, stats: &Stats, state: RunState) -> String {
+    format!("{} | {} | {} IPS | {} FPS", rom_name, state, stats.instructions_per_second(), stats.frames_per_second())
+}
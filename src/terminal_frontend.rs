@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::io::{self, Stdout, Write};
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
+use std::time::Duration;
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags};
+use crossterm::style::{self, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use minifb::Key;
+use raqote::Color;
+use chip8_rs_dmfg::frontend::{InputSource, KeyEvent, Renderer, Screen};
+use crate::keymap::Keymap;
+
+/// Most terminals only report presses, so without release events a key counts as held until it stops repeating.
+/// Half a second covers the usual delay before a held key starts repeating.
+const HELD_FRAMES: u32 = 30;
+
+// whether the terminal was asked to report key releases, so restoring it can undo that
+static ENHANCED_KEYS: AtomicBool = AtomicBool::new(false);
+static PANIC_HOOK: Once = Once::new();
+
+/// Draws two pixels per character cell, the top one in the foreground of a half block and the bottom one in its background
+pub struct TerminalFrontend {
+    out: Stdout,
+    palette: [style::Color; 4],
+    keymap: Keymap,
+    unicode: bool,
+    // terminal size in cells
+    columns: u16,
+    rows: u16,
+    // palette indices of the top and bottom pixel of every cell as last drawn, row by row
+    cells: Vec<(usize, usize)>,
+    cells_width: usize,
+    // frames since each held key was last seen, only used when the terminal can't report releases
+    held: HashMap<u8, u32>,
+    // printing it on the alternate screen would garble the display and be gone after quitting, so it stops the run
+    // and is printed once the terminal is restored
+    draw_error: Option<io::Error>
+}
+
+impl TerminalFrontend {
+    pub fn new(palette: [Color; 4], keymap: Keymap) -> Result<Self, Box<dyn Error>> {
+        let (columns, rows) = terminal::size()?;
+        let mut out = io::stdout();
+        terminal::enable_raw_mode()?;
+        // the panic message would otherwise be printed to the alternate screen and lost. Restoring is safe to repeat,
+        // so the hook stays for later frontends too.
+        PANIC_HOOK.call_once(|| {
+            let previous_hook = panic::take_hook();
+            panic::set_hook(Box::new(move |info| {
+                restore_terminal();
+                previous_hook(info);
+            }));
+        });
+        execute!(out, EnterAlternateScreen, Hide, Clear(ClearType::All))?;
+        if terminal::supports_keyboard_enhancement().unwrap_or(false) {
+            execute!(out, PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES))?;
+            ENHANCED_KEYS.store(true, Ordering::Relaxed);
+        }
+        Ok(TerminalFrontend {
+            out,
+            palette: palette.map(|color| style::Color::Rgb { r: color.r(), g: color.g(), b: color.b() }),
+            keymap,
+            unicode: supports_unicode(),
+            columns,
+            rows,
+            cells: Vec::new(),
+            cells_width: 0,
+            held: HashMap::new(),
+            draw_error: None
+        })
+    }
+
+    fn draw_cell(&mut self, index: usize) -> io::Result<()> {
+        let (x, y) = (index % self.cells_width, index / self.cells_width);
+        // writing past the edge would wrap onto the next line
+        if x >= self.columns as usize || y >= self.rows as usize {
+            return Ok(());
+        }
+        let (top, bottom) = self.cells[index];
+        let (glyph, foreground, background) = if self.unicode {
+            ('▀', top, bottom)
+        }else{
+            // without half blocks a cell can only show which of its pixels are lit, in the color of the top one
+            let glyph = [' ', '\'', '.', ':'][(top != 0) as usize + 2 * (bottom != 0) as usize];
+            (glyph, if top != 0 {top} else {bottom}, 0)
+        };
+        queue!(
+            self.out,
+            MoveTo(x as u16, y as u16),
+            SetForegroundColor(self.palette[foreground]),
+            SetBackgroundColor(self.palette[background]),
+            Print(glyph)
+        )
+    }
+
+    fn redraw_all(&mut self) -> io::Result<()> {
+        queue!(self.out, ResetColor, Clear(ClearType::All))?;
+        for index in 0..self.cells.len() {
+            self.draw_cell(index)?;
+        }
+        self.out.flush()
+    }
+
+    fn hex_key(&self, code: KeyCode) -> Option<u8> {
+        let key = match code {
+            KeyCode::Char(c) => char_key(c.to_ascii_lowercase())?,
+            KeyCode::Up => Key::Up,
+            KeyCode::Down => Key::Down,
+            KeyCode::Left => Key::Left,
+            KeyCode::Right => Key::Right,
+            KeyCode::Enter => Key::Enter,
+            KeyCode::Tab => Key::Tab,
+            _ => return None
+        };
+        self.keymap.hex_key(&key)
+    }
+}
+
+impl Renderer for TerminalFrontend {
+    /// Only cells that changed since the last call are written, rewriting the whole screen every frame flickers
+    fn present(&mut self, display: &dyn Screen, _dirty_rows: u64) {
+        let (width, height) = display.get_screen_size();
+        let cells = (0..height / 2)
+            .flat_map(|row| (0..width).map(move |column| (row * 2 * width + column, (row * 2 + 1) * width + column)))
+            .map(|(top, bottom)| (display.pixel_color(top), display.pixel_color(bottom)))
+            .collect::<Vec<(usize, usize)>>();
+        let result = if width != self.cells_width || cells.len() != self.cells.len() {
+            // the resolution changed, nothing on screen lines up anymore
+            self.cells = cells;
+            self.cells_width = width;
+            self.redraw_all()
+        }else{
+            let changed = (0..cells.len()).filter(|i| cells[*i] != self.cells[*i]).collect::<Vec<usize>>();
+            self.cells = cells;
+            changed.into_iter()
+                .try_for_each(|index| self.draw_cell(index))
+                .and_then(|_| self.out.flush())
+        };
+        if let Err(e) = result {
+            self.draw_error.get_or_insert(e);
+        }
+    }
+}
+
+impl InputSource for TerminalFrontend {
+    fn poll(&mut self) -> Vec<KeyEvent> {
+        let mut events = Vec::new();
+        let enhanced = ENHANCED_KEYS.load(Ordering::Relaxed);
+        if !enhanced {
+            self.held.values_mut().for_each(|frames| *frames += 1);
+        }
+        while event::poll(Duration::ZERO).unwrap_or(false) {
+            match event::read() {
+                Ok(Event::Key(key)) => {
+                    // raw mode swallows Ctrl+C, so it and Escape are the ways out
+                    if key.code == KeyCode::Esc || (key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL)) {
+                        events.push(KeyEvent::Quit);
+                        continue;
+                    }
+                    let hex_key = match self.hex_key(key.code) {
+                        Some(hex_key) => hex_key,
+                        None => continue
+                    };
+                    match key.kind {
+                        KeyEventKind::Release => events.push(KeyEvent::Released(hex_key)),
+                        KeyEventKind::Press if enhanced => events.push(KeyEvent::Pressed(hex_key)),
+                        _ if enhanced => {}
+                        // without releases a repeat is the only sign the key is still down
+                        _ => if self.held.insert(hex_key, 0).is_none() {
+                            events.push(KeyEvent::Pressed(hex_key));
+                        }
+                    }
+                }
+                Ok(Event::Resize(columns, rows)) => {
+                    self.columns = columns;
+                    self.rows = rows;
+                    if let Err(e) = self.redraw_all() {
+                        self.draw_error.get_or_insert(e);
+                    }
+                }
+                Ok(_) => {}
+                Err(_) => break
+            }
+        }
+        let released = self.held.iter()
+            .filter(|(_, frames)| **frames > HELD_FRAMES)
+            .map(|(hex_key, _)| *hex_key)
+            .collect::<Vec<u8>>();
+        for hex_key in released {
+            self.held.remove(&hex_key);
+            events.push(KeyEvent::Released(hex_key));
+        }
+        if self.draw_error.is_some() {
+            events.push(KeyEvent::Quit);
+        }
+        events
+    }
+}
+
+impl Drop for TerminalFrontend {
+    fn drop(&mut self) {
+        restore_terminal();
+        if let Some(e) = &self.draw_error {
+            eprintln!("Failed to draw to the terminal: {}", e);
+        }
+    }
+}
+
+/// Undoes everything `TerminalFrontend::new` changed, safe to call more than once
+fn restore_terminal() {
+    let mut out = io::stdout();
+    if ENHANCED_KEYS.swap(false, Ordering::Relaxed) {
+        let _ = execute!(out, PopKeyboardEnhancementFlags);
+    }
+    let _ = execute!(out, ResetColor, Show, LeaveAlternateScreen);
+    let _ = terminal::disable_raw_mode();
+}
+
+/// Half blocks need a UTF-8 locale, going by the usual environment variables
+fn supports_unicode() -> bool {
+    if cfg!(windows) {
+        return true;
+    }
+    ["LC_ALL", "LC_CTYPE", "LANG"].iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .is_some_and(|locale| {
+            let locale = locale.to_lowercase();
+            locale.contains("utf-8") || locale.contains("utf8")
+        })
+}
+
+/// The keymap's name for a typed character
+fn char_key(c: char) -> Option<Key> {
+    let key = match c {
+        '0' => Key::Key0, '1' => Key::Key1, '2' => Key::Key2, '3' => Key::Key3, '4' => Key::Key4,
+        '5' => Key::Key5, '6' => Key::Key6, '7' => Key::Key7, '8' => Key::Key8, '9' => Key::Key9,
+        'a' => Key::A, 'b' => Key::B, 'c' => Key::C, 'd' => Key::D, 'e' => Key::E, 'f' => Key::F,
+        'g' => Key::G, 'h' => Key::H, 'i' => Key::I, 'j' => Key::J, 'k' => Key::K, 'l' => Key::L,
+        'm' => Key::M, 'n' => Key::N, 'o' => Key::O, 'p' => Key::P, 'q' => Key::Q, 'r' => Key::R,
+        's' => Key::S, 't' => Key::T, 'u' => Key::U, 'v' => Key::V, 'w' => Key::W, 'x' => Key::X,
+        'y' => Key::Y, 'z' => Key::Z,
+        ' ' => Key::Space, ',' => Key::Comma, '.' => Key::Period, '/' => Key::Slash, ';' => Key::Semicolon,
+        '-' => Key::Minus, '=' => Key::Equal, '\'' => Key::Apostrophe,
+        _ => return None
+    };
+    Some(key)
+}
@@ -0,0 +1,91 @@
+use std::ops::Shr;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::Clamped;
+use crate::chip8::{Chip8, Variant};
+
+/// Instructions per frame when the page doesn't pick a speed, the same as the desktop build's --ipf default
+const DEFAULT_INSTRUCTIONS_PER_FRAME: u32 = 15;
+
+/// The emulator as seen from JavaScript. The page calls `tick_frame` from `requestAnimationFrame`
+/// and copies `framebuffer` into a canvas with `putImageData`.
+#[wasm_bindgen]
+pub struct Emulator {
+    chip: Chip8,
+    instructions_per_frame: u32,
+    // RGBA, indexed by Chip8::pixel_color
+    palette: [[u8; 4]; 4]
+}
+
+#[wasm_bindgen]
+impl Emulator {
+    /// `variant` is chip8, schip or xochip
+    #[wasm_bindgen(constructor)]
+    pub fn new(variant: &str) -> Result<Emulator, JsError> {
+        let variant = variant.parse::<Variant>().map_err(|e| JsError::new(&e))?;
+        let memory = if variant == Variant::XoChip {0x10000} else {0x1000};
+        let chip = Chip8::builder()
+            .memory(memory)
+            .build()
+            .map_err(|e| JsError::new(&e.to_string()))?
+            .with_variant(variant);
+        Ok(Emulator {
+            chip,
+            instructions_per_frame: DEFAULT_INSTRUCTIONS_PER_FRAME,
+            palette: [[0, 0, 0, 255], [255, 255, 255, 255], [255, 0, 0, 255], [255, 255, 0, 255]]
+        })
+    }
+
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), JsError> {
+        self.chip.load(rom).map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    pub fn set_instructions_per_frame(&mut self, instructions_per_frame: u32) {
+        self.instructions_per_frame = instructions_per_frame;
+    }
+
+    /// `index` 0 is the background, 1 the first plane, 2 the second XO-CHIP plane and 3 both. `rgb` is 0xRRGGBB.
+    pub fn set_color(&mut self, index: usize, rgb: u32) {
+        if let Some(color) = self.palette.get_mut(index) {
+            *color = [rgb.shr(16u32) as u8, rgb.shr(8u32) as u8, rgb as u8, 255];
+        }
+    }
+
+    /// Runs one 60 Hz frame of instructions and counts the timers down, the page calls this once per animation frame
+    pub fn tick_frame(&mut self) -> Result<(), JsError> {
+        self.chip.run_frame(self.instructions_per_frame)
+            .map(|_| ())
+            .map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    /// `key` is the hex key, 0 to F
+    pub fn key_down(&mut self, key: u8) {
+        self.chip.set_key_state(key, true);
+    }
+
+    pub fn key_up(&mut self, key: u8) {
+        self.chip.set_key_state(key, false);
+    }
+
+    /// Width of the display in pixels, 128 while a SUPER-CHIP program is in high resolution
+    pub fn width(&self) -> usize {
+        self.chip.get_screen_size().0
+    }
+
+    pub fn height(&self) -> usize {
+        self.chip.get_screen_size().1
+    }
+
+    /// RGBA pixels, `width() * height() * 4` bytes, ready for `new ImageData(pixels, width, height)`
+    pub fn framebuffer(&self) -> Clamped<Vec<u8>> {
+        Clamped(
+            (0..self.chip.get_display().len())
+                .flat_map(|i| self.palette[self.chip.pixel_color(i)])
+                .collect()
+        )
+    }
+
+    /// Whether the page should be beeping
+    pub fn sound_active(&self) -> bool {
+        self.chip.is_sound_active()
+    }
+}
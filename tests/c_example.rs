@@ -0,0 +1,46 @@
+//! Builds the library as a cdylib with the ffi feature and links examples/c/main.c against it, the same steps as
+//! examples/c/Makefile. Skipped without a C compiler.
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn c_example_links_and_runs() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    if Command::new("cc").arg("--version").output().is_err() {
+        eprintln!("No C compiler, skipping the C example");
+        return;
+    }
+    // its own target directory, the one of this crate is locked by the running cargo test
+    let target = root.join("target").join("c_example");
+    let built = Command::new(env!("CARGO"))
+        .args(["rustc", "--lib", "--no-default-features", "--features", "ffi", "--crate-type", "cdylib"])
+        .arg("--manifest-path").arg(root.join("Cargo.toml"))
+        .env("CARGO_TARGET_DIR", &target)
+        .output()
+        .expect("cargo should run");
+    assert!(built.status.success(), "{}", String::from_utf8_lossy(&built.stderr));
+
+    let libs = target.join("debug");
+    let binary = target.join("chip8");
+    let compiled = Command::new("cc")
+        .args(["-Wall", "-Wextra", "-Werror", "-o"]).arg(&binary)
+        .arg(root.join("examples/c/main.c"))
+        .arg("-I").arg(root.join("include"))
+        .arg("-L").arg(&libs)
+        .arg("-lchip8_rs_dmfg")
+        .arg(format!("-Wl,-rpath,{}", libs.display()))
+        .output()
+        .expect("cc should run");
+    assert!(compiled.status.success(), "{}", String::from_utf8_lossy(&compiled.stderr));
+
+    // cargo test points LD_LIBRARY_PATH at its own deps directory, which beats the rpath
+    let run = Command::new(&binary)
+        .arg(root.join("roms/IBM Logo.ch8"))
+        .env("LD_LIBRARY_PATH", &libs)
+        .output()
+        .expect("the example should run");
+    assert!(run.status.success(), "{}", String::from_utf8_lossy(&run.stderr));
+    let screen = String::from_utf8_lossy(&run.stdout);
+    assert_eq!(screen.lines().count(), 32);
+    assert!(screen.contains('#'), "{}", screen);
+}
@@ -0,0 +1,16 @@
+//! Builds no_std_check/, which uses the library without its `std` feature, so a `std` only item in the core fails the tests
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn core_builds_without_std() {
+    let check = Path::new(env!("CARGO_MANIFEST_DIR")).join("no_std_check");
+    let output = Command::new(env!("CARGO"))
+        .arg("build")
+        .arg("--manifest-path").arg(check.join("Cargo.toml"))
+        // its own target directory, the one of this crate is locked by the running cargo test
+        .env("CARGO_TARGET_DIR", check.join("target"))
+        .output()
+        .expect("cargo should run");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}